@@ -0,0 +1,121 @@
+//! Compares state access of borsh decoded entities with zero copy ones in compute units.
+//! Accounts of human and royalty states are owned by this program, see `tests/compute_units.rs`
+#![forbid(unsafe_code)]
+#![deny(clippy::all)]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use human_common::entity::{
+    next_entity, next_readonly_entity, next_zero_copy_entity_mut, zero_copy_from_acc,
+};
+use human_program::state::{ContractState, ContractStateHeader};
+use human_royalty::{error::Error as RoyaltyError, state::State as RoyaltyState};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy)]
+pub enum BenchInstruction {
+    /// Read an address of human contract state, as repost does
+    ReadContractState { zero_copy: bool },
+    /// Bump `drop_idx` of royalty state either in place or with a borsh round trip
+    UpdateRoyaltyState { zero_copy: bool },
+}
+
+pub fn read_contract_state(program_id: &Pubkey, state: &Pubkey, zero_copy: bool) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new_readonly(*state, false)],
+        data: BenchInstruction::ReadContractState { zero_copy }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+pub fn update_royalty_state(program_id: &Pubkey, state: &Pubkey, zero_copy: bool) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![AccountMeta::new(*state, false)],
+        data: BenchInstruction::UpdateRoyaltyState { zero_copy }
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = BenchInstruction::try_from_slice(instruction_data).map_err(|e| {
+        msg!("error parsing instruction: {}", e);
+        ProgramError::InvalidInstructionData
+    })?;
+
+    match instruction {
+        BenchInstruction::ReadContractState { zero_copy } => {
+            process_read_contract_state(program_id, accounts, zero_copy)
+        }
+        BenchInstruction::UpdateRoyaltyState { zero_copy } => {
+            process_update_royalty_state(program_id, accounts, zero_copy)
+        }
+    }
+}
+
+// [] human contract state
+fn process_read_contract_state(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    zero_copy: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let swap_state = if zero_copy {
+        let state_acc = next_account_info(account_info_iter)?;
+        let state = zero_copy_from_acc::<ContractStateHeader>(state_acc, program_id)?;
+        state.swap_state
+    } else {
+        let (state, _) = next_readonly_entity::<_, ContractState>(account_info_iter, program_id)?;
+        state.swap_state
+    };
+
+    if swap_state == Pubkey::default() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(())
+}
+
+// [write] royalty state
+fn process_update_royalty_state(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    zero_copy: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    if zero_copy {
+        let (mut state, _) =
+            next_zero_copy_entity_mut::<_, RoyaltyState>(account_info_iter, program_id)?;
+        state.drop_idx = state
+            .drop_idx
+            .checked_add(1)
+            .ok_or(RoyaltyError::Overflow)?;
+
+        return Ok(());
+    }
+
+    let (mut state, _) = next_entity::<_, RoyaltyState>(account_info_iter, program_id)?;
+    state.drop_idx = state
+        .drop_idx
+        .checked_add(1)
+        .ok_or(RoyaltyError::Overflow)?;
+
+    state.commit()
+}
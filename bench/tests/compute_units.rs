@@ -0,0 +1,140 @@
+// Compute unit benchmark for state access of current accounts.
+// Needs compiled program: cargo test-bpf --features test-bpf -- --nocapture
+#![cfg(feature = "test-bpf")]
+
+use std::str::FromStr;
+
+use human_bench::{read_contract_state, update_royalty_state};
+use human_common::entity::Entity;
+use human_program::{
+    state::{init_state, STATE_ACC_SIZE},
+    InitInstruction,
+};
+use human_royalty::state::{Settings, State, StateKeys};
+use solana_program::{instruction::Instruction, pubkey::Pubkey, rent::Rent};
+use solana_sdk::{account::Account, signature::Signer, transaction::Transaction};
+
+use solana_program_test::*;
+
+struct Bench {
+    program_id: Pubkey,
+    contract_state: Pubkey,
+    royalty_state: Pubkey,
+}
+
+fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+impl Bench {
+    fn new() -> Self {
+        Self {
+            program_id: Pubkey::from_str("33333333333333333333333333333333333333333333").unwrap(),
+            contract_state: Pubkey::new_unique(),
+            royalty_state: Pubkey::new_unique(),
+        }
+    }
+
+    /// program test with current human and royalty states owned by the bench program
+    fn program_test(&self) -> ProgramTest {
+        let mut pt = ProgramTest::default();
+        pt.prefer_bpf(true);
+        pt.add_program("human_bench", self.program_id, None);
+
+        let mut data = vec![0; STATE_ACC_SIZE];
+        let args = InitInstruction {
+            owner: Pubkey::new_unique(),
+            admin: Pubkey::new_unique(),
+            commission: Pubkey::new_unique(),
+            treasury: Pubkey::new_unique(),
+            swap_state: Pubkey::new_unique(),
+            vesting: None,
+        };
+        init_state(&mut data, Pubkey::new_unique(), args, 0).unwrap();
+        pt.add_account(self.contract_state, account(self.program_id, data));
+
+        let settings = Settings {
+            min_token_to_enroll: 0,
+            owner_fee: 0,
+            host_fee: 250,
+            host_flat_fee: 0,
+        };
+        let keys = StateKeys {
+            token_mint: Pubkey::new_unique(),
+            realm_addr: Pubkey::new_unique(),
+            vault_addr: Pubkey::new_unique(),
+        };
+        let state = State::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            settings,
+            keys,
+        );
+        let mut data = vec![0; State::SIZE];
+        state.serialize_to(&mut data).unwrap();
+        pt.add_account(self.royalty_state, account(self.program_id, data));
+
+        pt
+    }
+
+    /// runs instruction on a fresh bank and returns consumed units
+    async fn measure(&self, instruction: Instruction) -> u64 {
+        let (mut banks_client, payer, recent_blockhash) = self.program_test().start().await;
+
+        let mut tx = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+
+        let result = banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .unwrap();
+
+        result.result.unwrap();
+        result.metadata.unwrap().compute_units_consumed
+    }
+}
+
+#[tokio::test]
+async fn bench_read_contract_state() {
+    let bench = Bench::new();
+    let id = &bench.program_id;
+
+    let borsh = bench
+        .measure(read_contract_state(id, &bench.contract_state, false))
+        .await;
+    let zero_copy = bench
+        .measure(read_contract_state(id, &bench.contract_state, true))
+        .await;
+
+    println!("contract state read");
+    println!("  borsh decode: {borsh} CU");
+    println!("  zero copy: {zero_copy} CU");
+
+    assert!(zero_copy < borsh);
+}
+
+#[tokio::test]
+async fn bench_update_royalty_state() {
+    let bench = Bench::new();
+    let id = &bench.program_id;
+
+    let borsh = bench
+        .measure(update_royalty_state(id, &bench.royalty_state, false))
+        .await;
+    let zero_copy = bench
+        .measure(update_royalty_state(id, &bench.royalty_state, true))
+        .await;
+
+    println!("royalty state update");
+    println!("  borsh round trip: {borsh} CU");
+    println!("  zero copy: {zero_copy} CU");
+
+    assert!(zero_copy < borsh);
+}
@@ -0,0 +1,263 @@
+#![forbid(unsafe_code)]
+#![deny(clippy::all)]
+
+//! Overflow checked math for lamport and token amounts.
+//! Every function returns `None` instead of panicking or wrapping, and split helpers
+//! always return parts that sum up to the input, so no lamport is created or lost.
+
+use std::cmp::Reverse;
+
+/// Direction to round the result of a division in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// value * numerator / denominator, computed in u128
+pub fn mul_div(value: u64, numerator: u64, denominator: u64, rounding: Rounding) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+
+    let product = (value as u128) * (numerator as u128);
+    let denominator = denominator as u128;
+
+    let mut result = product / denominator;
+    let remainder = product - result * denominator;
+
+    if rounding == Rounding::Up && remainder > 0 {
+        result += 1;
+    }
+
+    result.try_into().ok()
+}
+
+pub fn mul_div_floor(value: u64, numerator: u64, denominator: u64) -> Option<u64> {
+    mul_div(value, numerator, denominator, Rounding::Down)
+}
+
+pub fn mul_div_ceil(value: u64, numerator: u64, denominator: u64) -> Option<u64> {
+    mul_div(value, numerator, denominator, Rounding::Up)
+}
+
+/// Basis points, 1/100 of a percent. Never exceeds 100%
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bps(u16);
+
+impl Bps {
+    pub const DENOMINATOR: u16 = 10_000;
+
+    pub const ZERO: Bps = Bps(0);
+    pub const MAX: Bps = Bps(Self::DENOMINATOR);
+
+    /// for constants, fails to compile if value is above 100%
+    pub const fn new(bps: u16) -> Self {
+        assert!(bps <= Self::DENOMINATOR, "basis points above 100%");
+        Bps(bps)
+    }
+
+    pub const fn try_new(bps: u16) -> Option<Self> {
+        if bps > Self::DENOMINATOR {
+            return None;
+        }
+
+        Some(Bps(bps))
+    }
+
+    pub const fn get(self) -> u16 {
+        self.0
+    }
+
+    /// part of amount, e.g. 15% of 1000 is 150
+    pub fn apply(self, amount: u64, rounding: Rounding) -> Option<u64> {
+        mul_div(amount, self.0 as u64, Self::DENOMINATOR as u64, rounding)
+    }
+
+    /// part of amount rounded down, so fee never exceeds the amount
+    pub fn of(self, amount: u64) -> Option<u64> {
+        self.apply(amount, Rounding::Down)
+    }
+
+    pub fn checked_add(self, other: Bps) -> Option<Bps> {
+        Self::try_new(self.0.checked_add(other.0)?)
+    }
+
+    /// what is left of 100%
+    pub fn complement(self) -> Bps {
+        Bps(Self::DENOMINATOR - self.0)
+    }
+}
+
+/// Takes each fee off the top of amount (rounded down) and returns them
+/// together with the remainder. fees + remainder == amount.
+/// Fails if fees add up to more than 100%
+pub fn split_off<const N: usize>(amount: u64, fees: [Bps; N]) -> Option<([u64; N], u64)> {
+    fees.iter()
+        .try_fold(Bps::ZERO, |acc, fee| acc.checked_add(*fee))?;
+
+    let mut parts = [0; N];
+    let mut remainder = amount;
+
+    for (part, fee) in parts.iter_mut().zip(fees) {
+        *part = fee.of(amount)?;
+        remainder = remainder.checked_sub(*part)?;
+    }
+
+    Some((parts, remainder))
+}
+
+/// Splits amount proportionally to weights. Parts always sum up to amount:
+/// lamports lost to rounding go one by one to parts with the largest fractional remainder
+pub fn split_proportional(amount: u64, weights: &[u64]) -> Option<Vec<u64>> {
+    let total = weights
+        .iter()
+        .try_fold(0u128, |acc, w| acc.checked_add(*w as u128))?;
+
+    if total == 0 {
+        return None;
+    }
+
+    let mut parts = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+
+    for (i, w) in weights.iter().enumerate() {
+        let product = (amount as u128) * (*w as u128);
+
+        // weight <= total, so part <= amount
+        parts.push((product / total) as u64);
+        remainders.push((Reverse(product % total), i));
+    }
+
+    let distributed = parts.iter().try_fold(0u64, |acc, p| acc.checked_add(*p))?;
+
+    // less than number of parts
+    let leftover = amount.checked_sub(distributed)? as usize;
+
+    remainders.sort_unstable();
+
+    for (_, i) in remainders.into_iter().take(leftover) {
+        parts[i] += 1;
+    }
+
+    Some(parts)
+}
+
+/// amount + other, e.g. a deposit added to a running total
+pub fn add(amount: u64, other: u64) -> Option<u64> {
+    amount.checked_add(other)
+}
+
+/// amount - part, e.g. a refund taken out of a running total. Fails if part exceeds amount
+pub fn sub(amount: u64, part: u64) -> Option<u64> {
+    amount.checked_sub(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div_floor(10, 1, 3), Some(3));
+        assert_eq!(mul_div_ceil(10, 1, 3), Some(4));
+        assert_eq!(mul_div_ceil(9, 1, 3), Some(3));
+        assert_eq!(mul_div_floor(1, 1, 0), None);
+
+        // intermediate product does not overflow
+        assert_eq!(mul_div_floor(u64::MAX, u64::MAX, u64::MAX), Some(u64::MAX));
+        assert_eq!(mul_div_floor(u64::MAX, 2, 1), None);
+    }
+
+    #[test]
+    fn test_bps() {
+        assert_eq!(Bps::new(1500).of(1000), Some(150));
+        assert_eq!(Bps::new(1).apply(5000, Rounding::Down), Some(0));
+        assert_eq!(Bps::new(1).apply(5000, Rounding::Up), Some(1));
+        assert_eq!(Bps::MAX.of(u64::MAX), Some(u64::MAX));
+
+        assert_eq!(Bps::try_new(10_001), None);
+        assert_eq!(Bps::new(6000).checked_add(Bps::new(5000)), None);
+        assert_eq!(Bps::new(2500).complement(), Bps::new(7500));
+    }
+
+    #[test]
+    fn test_split_off() {
+        let (fees, rest) = split_off(3_333_333, [Bps::new(1000), Bps::new(500)]).unwrap();
+        assert_eq!(fees, [333_333, 166_666]);
+        assert_eq!(rest, 2_833_334);
+
+        assert_eq!(split_off(100, [Bps::new(6000), Bps::new(5000)]), None);
+    }
+
+    #[test]
+    fn test_split_proportional() {
+        assert_eq!(split_proportional(10, &[1, 1, 1]), Some(vec![4, 3, 3]));
+        assert_eq!(split_proportional(11, &[1, 1, 1]), Some(vec![4, 4, 3]));
+        assert_eq!(split_proportional(5, &[1, 3]), Some(vec![1, 4]));
+        assert_eq!(split_proportional(5, &[0, 0]), None);
+        assert_eq!(split_proportional(5, &[]), None);
+    }
+
+    #[test]
+    fn test_add_sub() {
+        assert_eq!(add(2, 3), Some(5));
+        assert_eq!(add(u64::MAX, 1), None);
+        assert_eq!(sub(5, 3), Some(2));
+        assert_eq!(sub(3, 5), None);
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_mul_div(value: u64, numerator: u64, denominator in 1u64..) {
+            let exact = (value as u128) * (numerator as u128);
+
+            if let Some(floor) = mul_div_floor(value, numerator, denominator) {
+                assert!(floor as u128 * denominator as u128 <= exact);
+            }
+
+            if let (Some(floor), Some(ceil)) = (
+                mul_div_floor(value, numerator, denominator),
+                mul_div_ceil(value, numerator, denominator),
+            ) {
+                assert!(ceil - floor <= 1);
+                assert!(ceil as u128 * denominator as u128 >= exact);
+            }
+        }
+
+        #[test]
+        fn proptest_split_off(amount: u64, a in 0u16..=10_000, b in 0u16..=10_000) {
+            let fees = [Bps::new(a), Bps::new(b)];
+
+            match split_off(amount, fees) {
+                Some(([x, y], rest)) => {
+                    assert!(a as u32 + b as u32 <= 10_000);
+                    assert_eq!(x as u128 + y as u128 + rest as u128, amount as u128);
+                }
+                None => assert!(a as u32 + b as u32 > 10_000),
+            }
+        }
+
+        #[test]
+        fn proptest_split_proportional(
+            amount: u64,
+            weights in prop::collection::vec(0u64..u64::MAX, 1..20),
+        ) {
+            prop_assume!(weights.iter().any(|w| *w > 0));
+
+            let total: u128 = weights.iter().map(|w| *w as u128).sum();
+            let parts = split_proportional(amount, &weights).unwrap();
+
+            assert_eq!(parts.iter().map(|p| *p as u128).sum::<u128>(), amount as u128);
+
+            // each part is off by less than a lamport from its exact share
+            for (part, w) in parts.iter().zip(&weights) {
+                let exact = (amount as u128) * (*w as u128);
+                let part = *part as u128 * total;
+
+                assert!(part.abs_diff(exact) < total);
+            }
+        }
+    }
+}
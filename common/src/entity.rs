@@ -1,6 +1,9 @@
+use std::cell::{Ref, RefMut};
+use std::mem::size_of;
 use std::ops::{Deref, DerefMut};
 
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
@@ -14,6 +17,8 @@ pub trait Entity
 where
     Self: Sized + BorshDeserialize + BorshSerialize,
 {
+    /// Smallest account the entity is stored in. Latest version can keep `SIZE` of
+    /// older ones, as long as whatever it's encoded to fits in the account it's written to
     const SIZE: usize;
     const MAGIC: u8;
 
@@ -25,10 +30,10 @@ where
         }
 
         let serialized = self.try_to_vec()?;
-        assert!(
-            serialized.len() < Self::SIZE,
-            "serialized state to small for this account SIZE"
-        );
+        if serialized.len() >= data.len() {
+            msg!("{} bytes don't fit in {}", serialized.len() + 1, data.len());
+            return Err(ProgramError::AccountDataTooSmall);
+        }
 
         data[0] = Self::MAGIC;
 
@@ -50,53 +55,210 @@ where
             return Err(ProgramError::InvalidAccountData);
         }
 
+        Self::decode(data)
+    }
+
+    /// Decodes data tagged with `MAGIC`. Override to repair known broken layouts
+    fn decode(data: &[u8]) -> Result<Self, ProgramError> {
         let state = Self::deserialize(&mut &data[1..])?;
 
         Ok(state)
     }
 
+    /// Upgrades data written by an older version of this entity, usually with `migrate_from`.
+    /// Entities without previous versions reject any other `MAGIC`
+    fn migrate(_data: &[u8]) -> Result<Self, ProgramError> {
+        Err(ProgramError::InvalidAccountData)
+    }
+
+    /// Deserializes the entity from data of this or any older version.
+    /// Returns `true` if data was upgraded and has to be written back
+    fn load(data: &[u8]) -> Result<(Self, bool), ProgramError> {
+        match data.first() {
+            Some(&magic) if magic != 0 && magic != Self::MAGIC => Ok((Self::migrate(data)?, true)),
+            _ => Ok((Self::deserialize_from(data)?, false)),
+        }
+    }
+
     fn is_initialized(data: &[u8]) -> bool {
         data.len() >= Self::SIZE && data[0] != 0
     }
 }
 
+/// Entity with a `#[repr(C, packed)]` Pod layout, read and written in place instead of
+/// a borsh round trip. Layout must match its borsh encoding (see `impl_borsh_for_pod`),
+/// so older versions still migrate through `Entity`
+pub trait ZeroCopyEntity: Entity + Pod {
+    /// `MAGIC` of older versions that start with the same layout, they are read in place as is
+    const COMPATIBLE_MAGIC: &'static [u8] = &[];
+
+    fn from_bytes(data: &[u8]) -> Result<&Self, ProgramError> {
+        check_zero_copy::<Self>(data)?;
+
+        Ok(bytemuck::from_bytes(&data[1..1 + size_of::<Self>()]))
+    }
+
+    fn from_bytes_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        check_zero_copy::<Self>(data)?;
+
+        Ok(bytemuck::from_bytes_mut(
+            &mut data[1..1 + size_of::<Self>()],
+        ))
+    }
+}
+
+fn check_zero_copy<T: ZeroCopyEntity>(data: &[u8]) -> ProgramResult {
+    if data.len() < T::SIZE || data.len() < 1 + size_of::<T>() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    match data[0] {
+        0 => Err(ProgramError::UninitializedAccount),
+        magic if is_zero_copy_magic::<T>(magic) => Ok(()),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+fn is_zero_copy_magic<T: ZeroCopyEntity>(magic: u8) -> bool {
+    magic == T::MAGIC || T::COMPATIBLE_MAGIC.contains(&magic)
+}
+
+/// Previous version of an entity, linked to the next one by `upgrade`.
+/// Chain is walked by `MAGIC` until a known version is found
+pub trait Migrate: Entity {
+    type Next: Entity;
+
+    fn upgrade(self) -> Self::Next;
+}
+
+/// Loads `P` or any version before it and upgrades it to `P::Next`.
+/// Intended as `Entity::migrate` implementation of `P::Next`
+pub fn migrate_from<P: Migrate>(data: &[u8]) -> Result<P::Next, ProgramError> {
+    let (prev, _) = P::load(data)?;
+
+    Ok(prev.upgrade())
+}
+
+/// Upgrades entity in place if account was written by an older version.
+/// Account is never grown here, since that needs a rent top-up: keep `SIZE` at the size
+/// of older versions and leave growing to a dedicated instruction.
+/// Returns whether entity has to be written back
+fn load_entity<T: Entity>(acc: &AccountInfo) -> Result<(T, bool), ProgramError> {
+    let (state, migrated) = T::load(&acc.try_borrow_data()?)?;
+
+    if migrated {
+        msg!("migrating {} to {:#x}", acc.key, T::MAGIC);
+
+        if acc.data_len() < T::SIZE {
+            msg!("account has to be resized to {} bytes first", T::SIZE);
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+    }
+
+    Ok((state, migrated))
+}
+
+fn check_owner(acc: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if acc.owner != program_id {
+        msg!("{:?} owner: {:?} != {:?}", acc.key, &acc.owner, program_id);
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    Ok(())
+}
+
 pub fn next_entity<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>, T: Entity>(
     i: &mut I,
     program_id: &Pubkey,
 ) -> Result<(EntityGuard<'a, 'b, T>, &'a AccountInfo<'b>), ProgramError> {
     let state_acc = next_account_info(i)?;
 
-    if state_acc.owner != program_id {
-        msg!(
-            "{:?} owner: {:?} != {:?}",
-            state_acc.key,
-            &state_acc.owner,
-            program_id
-        );
-        return Err(ProgramError::IllegalOwner);
-    }
+    let state = entity_from_acc(state_acc, program_id)?;
 
-    let data = state_acc.try_borrow_data()?;
-
-    let state = Entity::deserialize_from(&data)?;
-
-    Ok((EntityGuard::new(state, state_acc), state_acc))
+    Ok((state, state_acc))
 }
 
 pub fn entity_from_acc<'a, 'b: 'a, T: Entity>(
     acc: &'a AccountInfo<'b>,
     program_id: &Pubkey,
 ) -> Result<EntityGuard<'a, 'b, T>, ProgramError> {
-    if acc.owner != program_id {
-        msg!("{:?} owner: {:?} != {:?}", acc.key, &acc.owner, program_id);
-        return Err(ProgramError::IllegalOwner);
-    }
+    check_owner(acc, program_id)?;
+
+    let (state, migrated) = load_entity(acc)?;
+
+    Ok(EntityGuard::new(state, acc, migrated))
+}
+
+/// Same as `next_entity`, but entity is never written back.
+/// Older versions are upgraded in memory only
+pub fn next_readonly_entity<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>, T: Entity>(
+    i: &mut I,
+    program_id: &Pubkey,
+) -> Result<(ReadonlyEntityGuard<T>, &'a AccountInfo<'b>), ProgramError> {
+    let state_acc = next_account_info(i)?;
+
+    let state = readonly_entity_from_acc(state_acc, program_id)?;
+
+    Ok((state, state_acc))
+}
+
+pub fn readonly_entity_from_acc<T: Entity>(
+    acc: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<ReadonlyEntityGuard<T>, ProgramError> {
+    check_owner(acc, program_id)?;
+
+    let (state, _) = T::load(&acc.try_borrow_data()?)?;
+
+    Ok(ReadonlyEntityGuard { inner: state })
+}
+
+/// Borrows entity in place. Older versions are not upgraded, use `readonly_entity_from_acc` for them
+pub fn zero_copy_from_acc<'a, T: ZeroCopyEntity>(
+    acc: &'a AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Ref<'a, T>, ProgramError> {
+    check_owner(acc, program_id)?;
 
     let data = acc.try_borrow_data()?;
+    T::from_bytes(&data)?;
+
+    Ok(Ref::map(data, |data| T::from_bytes(data).unwrap()))
+}
+
+/// Mutably borrows entity in place, upgrading older versions first.
+/// Borrow has to be released before passing the account to CPI
+pub fn zero_copy_from_acc_mut<'a, T: ZeroCopyEntity>(
+    acc: &'a AccountInfo,
+    program_id: &Pubkey,
+) -> Result<RefMut<'a, T>, ProgramError> {
+    check_owner(acc, program_id)?;
+
+    let outdated = matches!(acc.try_borrow_data()?.first(),
+        Some(&magic) if magic != 0 && !is_zero_copy_magic::<T>(magic));
+    if outdated {
+        let (state, _) = load_entity::<T>(acc)?;
+        state.serialize_to(&mut acc.try_borrow_mut_data()?)?;
+    }
+
+    let data = acc.try_borrow_mut_data()?;
+
+    Ok(RefMut::map(data, |data| T::from_bytes_mut(data).unwrap()))
+}
+
+pub fn next_zero_copy_entity_mut<'a, 'b: 'a, I, T>(
+    i: &mut I,
+    program_id: &Pubkey,
+) -> Result<(RefMut<'a, T>, &'a AccountInfo<'b>), ProgramError>
+where
+    I: Iterator<Item = &'a AccountInfo<'b>>,
+    T: ZeroCopyEntity,
+{
+    let acc = next_account_info(i)?;
 
-    let state = Entity::deserialize_from(&data)?;
+    let state = zero_copy_from_acc_mut(acc, program_id)?;
 
-    Ok(EntityGuard::new(state, acc))
+    Ok((state, acc))
 }
 
 // Save entity to account. It is assumed that account address and owner is checked
@@ -110,15 +272,42 @@ pub fn initialize_entity<T: Entity>(ent: T, state_acc: &AccountInfo) -> ProgramR
     ent.serialize_to(&mut data)
 }
 
+/// Entity loaded from an account. Changes are saved with `commit`,
+/// uncommitted changes are discarded on drop
 #[derive(Debug)]
 pub struct EntityGuard<'a, 'b: 'a, T: Entity> {
     acc: &'a AccountInfo<'b>,
     inner: T,
+    dirty: bool,
 }
 
 impl<'a, 'b: 'a, T: Entity> EntityGuard<'a, 'b, T> {
-    fn new(inner: T, acc: &'a AccountInfo<'b>) -> Self {
-        Self { inner, acc }
+    fn new(inner: T, acc: &'a AccountInfo<'b>, dirty: bool) -> Self {
+        Self { inner, acc, dirty }
+    }
+
+    /// true if entity was mutably accessed or migrated since last save
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes entity back to the account if it was changed
+    pub fn save(&mut self) -> ProgramResult {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let mut data = self.acc.try_borrow_mut_data()?;
+        self.inner.serialize_to(&mut data)?;
+
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Saves entity and releases the guard
+    pub fn commit(mut self) -> ProgramResult {
+        self.save()
     }
 }
 
@@ -132,19 +321,336 @@ impl<'a, 'b: 'a, T: Entity> Deref for EntityGuard<'a, 'b, T> {
 
 impl<T: Entity> DerefMut for EntityGuard<'_, '_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.dirty = true;
         &mut self.inner
     }
 }
 
 impl<T: Entity> Drop for EntityGuard<'_, '_, T> {
     fn drop(&mut self) {
-        let mut data = self
-            .acc
-            .try_borrow_mut_data()
-            .expect("failed to borrow account data");
+        if self.dirty {
+            msg!("discarding uncommitted changes of {}", self.acc.key);
+        }
+    }
+}
 
+/// Entity loaded from an account that is never written back
+#[derive(Debug)]
+pub struct ReadonlyEntityGuard<T: Entity> {
+    inner: T,
+}
+
+impl<T: Entity> ReadonlyEntityGuard<T> {
+    pub fn into_inner(self) -> T {
         self.inner
-            .serialize_to(&mut data)
-            .expect("error saving entity")
+    }
+}
+
+impl<T: Entity> Deref for ReadonlyEntityGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, BorshDeserialize, BorshSerialize)]
+    struct V1 {
+        a: u8,
+    }
+
+    impl Entity for V1 {
+        const SIZE: usize = 8;
+        const MAGIC: u8 = 0x01;
+    }
+
+    impl Migrate for V1 {
+        type Next = V2;
+
+        fn upgrade(self) -> V2 {
+            V2 { a: self.a as u64 }
+        }
+    }
+
+    #[derive(Debug, BorshDeserialize, BorshSerialize)]
+    struct V2 {
+        a: u64,
+    }
+
+    impl Entity for V2 {
+        const SIZE: usize = 16;
+        const MAGIC: u8 = 0x02;
+
+        fn migrate(data: &[u8]) -> Result<Self, ProgramError> {
+            migrate_from::<V1>(data)
+        }
+    }
+
+    impl Migrate for V2 {
+        type Next = V3;
+
+        fn upgrade(self) -> V3 {
+            V3 { a: self.a, b: 42 }
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+    struct V3 {
+        a: u64,
+        b: u64,
+    }
+
+    impl Entity for V3 {
+        const SIZE: usize = 32;
+        const MAGIC: u8 = 0x03;
+
+        fn migrate(data: &[u8]) -> Result<Self, ProgramError> {
+            migrate_from::<V2>(data)
+        }
+    }
+
+    #[derive(Debug, BorshDeserialize, BorshSerialize)]
+    struct List {
+        items: Vec<u8>,
+    }
+
+    impl Entity for List {
+        const SIZE: usize = 8;
+        const MAGIC: u8 = 0x10;
+    }
+
+    fn encode<T: Entity>(ent: &T) -> Vec<u8> {
+        let mut data = vec![0; T::SIZE];
+        ent.serialize_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_load_current() {
+        let data = encode(&V3 { a: 1, b: 2 });
+
+        let (v3, migrated) = V3::load(&data).unwrap();
+        assert!(!migrated);
+        assert_eq!(v3, V3 { a: 1, b: 2 });
+    }
+
+    #[test]
+    fn test_migrate() {
+        let data = encode(&V2 { a: 7 });
+
+        let (v3, migrated) = V3::load(&data).unwrap();
+        assert!(migrated);
+        assert_eq!(v3, V3 { a: 7, b: 42 });
+    }
+
+    #[test]
+    fn test_migrate_chain() {
+        // smaller account of the oldest version
+        let data = encode(&V1 { a: 7 });
+
+        let (v3, migrated) = V3::load(&data).unwrap();
+        assert!(migrated);
+        assert_eq!(v3, V3 { a: 7, b: 42 });
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut data = encode(&V3 { a: 1, b: 2 });
+
+        data[0] = 0x10;
+        assert!(matches!(
+            V3::load(&data),
+            Err(ProgramError::InvalidAccountData)
+        ));
+
+        data[0] = 0;
+        assert!(matches!(
+            V3::load(&data),
+            Err(ProgramError::UninitializedAccount)
+        ));
+    }
+
+    #[test]
+    fn test_encoding_has_to_fit_account() {
+        let mut data = vec![0; List::SIZE];
+        let list = List { items: vec![1; 3] };
+        list.serialize_to(&mut data).unwrap();
+
+        let list = List { items: vec![1; 8] };
+        assert!(matches!(
+            list.serialize_to(&mut data),
+            Err(ProgramError::AccountDataTooSmall)
+        ));
+
+        // accounts larger than `SIZE` take larger encodings
+        let mut data = vec![0; 16];
+        list.serialize_to(&mut data).unwrap();
+        assert_eq!(List::deserialize_from(&data).unwrap().items, vec![1; 8]);
+    }
+
+    #[test]
+    fn test_guard_dirty_tracking() {
+        let key = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = encode(&V3 { a: 1, b: 2 });
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        let mut guard = entity_from_acc::<V3>(&acc, &program_id).unwrap();
+        assert_eq!(guard.a, 1);
+        assert!(!guard.is_dirty());
+
+        guard.a = 5;
+        assert!(guard.is_dirty());
+        guard.commit().unwrap();
+
+        let guard = readonly_entity_from_acc::<V3>(&acc, &program_id).unwrap();
+        assert_eq!(guard.a, 5);
+
+        assert_eq!(guard.into_inner(), V3 { a: 5, b: 2 });
+
+        assert!(matches!(
+            entity_from_acc::<V3>(&acc, &Pubkey::new_unique()),
+            Err(ProgramError::IllegalOwner)
+        ));
+    }
+
+    #[test]
+    fn test_guard_commits_migration() {
+        let key = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = encode(&V2 { a: 3 });
+        data.resize(V3::SIZE, 0);
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        let guard = entity_from_acc::<V3>(&acc, &program_id).unwrap();
+        assert!(guard.is_dirty());
+        guard.commit().unwrap();
+
+        assert_eq!(acc.data.borrow()[0], V3::MAGIC);
+        assert_eq!(
+            V3::load(&acc.data.borrow()).unwrap(),
+            (V3 { a: 3, b: 42 }, false)
+        );
+    }
+
+    #[test]
+    fn test_migration_never_grows_account() {
+        let key = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = encode(&V2 { a: 3 });
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        assert!(matches!(
+            entity_from_acc::<V3>(&acc, &program_id),
+            Err(ProgramError::AccountDataTooSmall)
+        ));
+        assert_eq!(acc.data_len(), V2::SIZE);
+
+        // read only access upgrades in memory
+        let guard = readonly_entity_from_acc::<V3>(&acc, &program_id).unwrap();
+        assert_eq!(guard.into_inner(), V3 { a: 3, b: 42 });
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+    #[repr(C, packed)]
+    struct V4 {
+        a: u64,
+        b: u64,
+        c: u8,
+    }
+
+    crate::impl_borsh_for_pod!(V4);
+
+    impl Entity for V4 {
+        const SIZE: usize = 32;
+        const MAGIC: u8 = 0x04;
+
+        fn migrate(data: &[u8]) -> Result<Self, ProgramError> {
+            migrate_from::<V3>(data)
+        }
+    }
+
+    impl ZeroCopyEntity for V4 {}
+
+    impl Migrate for V3 {
+        type Next = V4;
+
+        fn upgrade(self) -> V4 {
+            V4 {
+                a: self.a,
+                b: self.b,
+                c: 1,
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_copy() {
+        let key = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = encode(&V3 { a: 1, b: 2 });
+        let acc = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        // not upgraded in place when read only
+        assert!(matches!(
+            zero_copy_from_acc::<V4>(&acc, &program_id),
+            Err(ProgramError::InvalidAccountData)
+        ));
+
+        {
+            let mut v4 = zero_copy_from_acc_mut::<V4>(&acc, &program_id).unwrap();
+            assert_eq!(*v4, V4 { a: 1, b: 2, c: 1 });
+            v4.b = 5;
+        }
+
+        let v4 = *zero_copy_from_acc::<V4>(&acc, &program_id).unwrap();
+        assert_eq!(v4, V4 { a: 1, b: 5, c: 1 });
+
+        // same layout as borsh
+        assert_eq!(V4::load(&acc.data.borrow()).unwrap(), (v4, false));
     }
 }
@@ -0,0 +1,97 @@
+//! Custom error codes of native programs in this repo.
+//! Every program numbers its errors within its own range, so a code alone tells which enum it belongs to.
+//! `royalty` predates the ranges and keeps its original codes
+
+use std::{fmt, ops::Range};
+
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{msg, program_error::ProgramError};
+use thiserror::Error;
+
+/// `human_program::error::Error`
+pub const HUMAN_ERRORS: Range<u32> = 0x000..0x400;
+/// `human_escrow::error::Error`
+pub const ESCROW_ERRORS: Range<u32> = 0x400..0x800;
+/// `Error` of helpers in this crate, shared by all programs using them
+pub const COMMON_ERRORS: Range<u32> = 0x800..0xC00;
+
+/// Error enum of a program, with codes within `CODES`
+pub trait CustomError: Sized + fmt::Display + FromPrimitive {
+    const CODES: Range<u32>;
+}
+
+/// Decodes custom `ProgramError` of `E`, e.g. to map it to a user facing message
+pub fn decode<E: CustomError>(err: &ProgramError) -> Option<E> {
+    match err {
+        ProgramError::Custom(code) if E::CODES.contains(code) => E::from_u32(*code),
+        _ => None,
+    }
+}
+
+/// Logs description of `err` if it's `E` or `Error` of this crate, like `PrintProgramError::print`
+pub fn print_error<E: CustomError>(err: &ProgramError) {
+    if let Some(e) = decode::<E>(err) {
+        msg!("error: {}", e);
+    } else if let Some(e) = decode::<Error>(err) {
+        msg!("error: {}", e);
+    } else {
+        msg!("error: {}", err);
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(u32)]
+pub enum Error {
+    #[error("unexpected account address")]
+    UnexpectedAccount = 0x800,
+    #[error("unexpected token wallet address")]
+    UnexpectedWallet,
+    #[error("multisig must have 1 to 11 unique signers")]
+    InvalidMultisigSigners,
+    #[error("invalid multisig threshold")]
+    InvalidMultisigThreshold,
+    #[error("account is not a multisig signer")]
+    NotMultisigSigner,
+}
+
+impl CustomError for Error {
+    const CODES: Range<u32> = COMMON_ERRORS;
+}
+
+impl From<Error> for ProgramError {
+    fn from(e: Error) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> From<Error> for Result<T, ProgramError> {
+    fn from(e: Error) -> Self {
+        Err(ProgramError::Custom(e as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges_dont_overlap() {
+        let ranges = [HUMAN_ERRORS, ESCROW_ERRORS, COMMON_ERRORS];
+
+        for (i, a) in ranges.iter().enumerate() {
+            for b in &ranges[i + 1..] {
+                assert!(a.end <= b.start || b.end <= a.start);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let err: ProgramError = Error::UnexpectedWallet.into();
+
+        assert_eq!(decode::<Error>(&err), Some(Error::UnexpectedWallet));
+        assert_eq!(decode::<Error>(&ProgramError::Custom(0)), None);
+        assert_eq!(decode::<Error>(&ProgramError::InvalidArgument), None);
+    }
+}
@@ -1,5 +1,9 @@
 #![forbid(unsafe_code)]
-#![forbid(clippy::all)]
+#![deny(clippy::all)]
 
 pub mod entity;
+pub mod error;
+pub mod multisig;
+pub mod pod;
+pub mod split;
 pub mod utils;
@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{msg, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::{entity::Entity, error::Error};
+
+pub const MAX_MULTISIG_SIGNERS: usize = 11;
+
+/// M-of-N signer set. Address of the account holding it can be used wherever a single admin key is expected
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct Multisig {
+    /// signatures required
+    pub threshold: u8,
+    /// allowed signers
+    pub signers: Vec<Pubkey>,
+}
+
+impl Entity for Multisig {
+    const SIZE: usize = 1 + 1 + 4 + 32 * MAX_MULTISIG_SIGNERS + 1;
+    const MAGIC: u8 = 0x4D;
+}
+
+impl Multisig {
+    pub fn new(threshold: u8, signers: Vec<Pubkey>) -> Result<Self, ProgramError> {
+        if signers.is_empty() || signers.len() > MAX_MULTISIG_SIGNERS {
+            msg!("multisig must have 1 to {} signers", MAX_MULTISIG_SIGNERS);
+            return Error::InvalidMultisigSigners.into();
+        }
+
+        if threshold == 0 || threshold as usize > signers.len() {
+            msg!("invalid multisig threshold {}", threshold);
+            return Error::InvalidMultisigThreshold.into();
+        }
+
+        let unique: HashSet<_> = signers.iter().collect();
+        if unique.len() != signers.len() {
+            msg!("duplicate multisig signer");
+            return Error::InvalidMultisigSigners.into();
+        }
+
+        Ok(Self { threshold, signers })
+    }
+
+    /// checks that at least `threshold` distinct members signed
+    pub fn check_signers<'a>(
+        &self,
+        signers: impl IntoIterator<Item = (&'a Pubkey, bool)>,
+    ) -> Result<(), ProgramError> {
+        let mut signed = HashSet::new();
+
+        for (key, is_signer) in signers {
+            if !is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            if !self.signers.contains(key) {
+                msg!("{} is not a multisig signer", key);
+                return Error::NotMultisigSigner.into();
+            }
+
+            signed.insert(key);
+        }
+
+        if signed.len() < self.threshold as usize {
+            msg!(
+                "multisig requires {} signatures, got {}",
+                self.threshold,
+                signed.len()
+            );
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let keys: Vec<_> = (0..3).map(|_| Pubkey::new_unique()).collect();
+
+        Multisig::new(2, keys.clone()).unwrap();
+
+        assert!(Multisig::new(0, keys.clone()).is_err());
+        assert!(Multisig::new(4, keys.clone()).is_err());
+        assert!(Multisig::new(1, vec![]).is_err());
+        assert!(Multisig::new(1, vec![keys[0], keys[0]]).is_err());
+        assert!(Multisig::new(1, vec![keys[0]; MAX_MULTISIG_SIGNERS + 1]).is_err());
+    }
+
+    #[test]
+    fn test_check_signers() {
+        let keys: Vec<_> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let multisig = Multisig::new(2, keys.clone()).unwrap();
+
+        multisig
+            .check_signers([(&keys[0], true), (&keys[2], true)])
+            .unwrap();
+
+        // not enough
+        assert!(multisig.check_signers([(&keys[0], true)]).is_err());
+        // same signer twice
+        assert!(multisig
+            .check_signers([(&keys[0], true), (&keys[0], true)])
+            .is_err());
+        // missing signature
+        assert!(multisig
+            .check_signers([(&keys[0], true), (&keys[1], false)])
+            .is_err());
+        // outsider
+        let outsider = Pubkey::new_unique();
+        assert!(multisig
+            .check_signers([(&keys[0], true), (&outsider, true)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_max_size() {
+        let multisig = Multisig::new(1, vec![Pubkey::new_unique(); 1]).unwrap();
+        let full = Multisig {
+            signers: vec![Pubkey::new_unique(); MAX_MULTISIG_SIGNERS],
+            ..multisig
+        };
+
+        let mut data = vec![0; Multisig::SIZE];
+        full.serialize_to(&mut data).unwrap();
+        assert_eq!(Multisig::deserialize_from(&data).unwrap(), full);
+    }
+}
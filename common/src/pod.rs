@@ -0,0 +1,39 @@
+use std::io;
+use std::mem::size_of;
+
+pub use bytemuck::{self, Pod, Zeroable};
+
+/// Reads unaligned `T` from the front of the buffer and advances it
+pub fn read_pod<T: Pod>(buf: &mut &[u8]) -> io::Result<T> {
+    if buf.len() < size_of::<T>() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected end of buffer",
+        ));
+    }
+
+    let (head, tail) = buf.split_at(size_of::<T>());
+    *buf = tail;
+
+    Ok(bytemuck::pod_read_unaligned(head))
+}
+
+/// Implements borsh for a `#[repr(C, packed)]` Pod type by copying its bytes.
+/// Without padding borsh and in-memory little endian encodings are identical,
+/// so such types can be used both as `Entity` and `ZeroCopyEntity`
+#[macro_export]
+macro_rules! impl_borsh_for_pod {
+    ($($ty:ty),+) => {$(
+        impl borsh::BorshSerialize for $ty {
+            fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+                writer.write_all($crate::pod::bytemuck::bytes_of(self))
+            }
+        }
+
+        impl borsh::BorshDeserialize for $ty {
+            fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+                $crate::pod::read_pod(buf)
+            }
+        }
+    )+};
+}
@@ -0,0 +1,173 @@
+use checked_math::{split_proportional, Bps};
+use solana_program::pubkey::Pubkey;
+
+/// Where lamports left after bps shares and flat fees go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remainder {
+    /// shares are taken from gross amount and rounded down,
+    /// whatever is left is appended as the last entry for this address
+    To(Pubkey),
+    /// shares are weights over amount minus flat fees,
+    /// rounding dust goes to recipients with the largest fractional part
+    Proportional,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub address: Pubkey,
+    pub share: Bps,
+    /// taken on top of share
+    pub flat_fee: u64,
+}
+
+/// Ordered list of recipients describing how an amount is split.
+/// Parts always add up to the amount being split
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitPlan {
+    pub recipients: Vec<Recipient>,
+    pub remainder: Remainder,
+}
+
+impl SplitPlan {
+    pub fn new(remainder: Remainder) -> Self {
+        Self {
+            recipients: Vec::new(),
+            remainder,
+        }
+    }
+
+    pub fn with_share(self, address: Pubkey, share: Bps) -> Self {
+        self.with_fees(address, share, 0)
+    }
+
+    pub fn with_fees(mut self, address: Pubkey, share: Bps, flat_fee: u64) -> Self {
+        self.recipients.push(Recipient {
+            address,
+            share,
+            flat_fee,
+        });
+        self
+    }
+
+    /// amount for each recipient in order, followed by the remainder entry for `Remainder::To`.
+    /// None if flat fees exceed amount, shares of `Remainder::To` plan exceed 100%
+    /// or `Remainder::Proportional` plan has no shares
+    pub fn split(&self, amount: u64) -> Option<Vec<(Pubkey, u64)>> {
+        let flat_fees = self
+            .recipients
+            .iter()
+            .try_fold(0u64, |acc, r| acc.checked_add(r.flat_fee))?;
+
+        let mut left = amount.checked_sub(flat_fees)?;
+
+        let shares = match self.remainder {
+            Remainder::To(_) => {
+                self.recipients
+                    .iter()
+                    .try_fold(Bps::ZERO, |acc, r| acc.checked_add(r.share))?;
+
+                self.recipients
+                    .iter()
+                    .map(|r| r.share.of(amount))
+                    .collect::<Option<Vec<_>>>()?
+            }
+            Remainder::Proportional => {
+                let weights: Vec<u64> = self
+                    .recipients
+                    .iter()
+                    .map(|r| r.share.get() as u64)
+                    .collect();
+
+                split_proportional(left, &weights)?
+            }
+        };
+
+        let mut parts = Vec::with_capacity(self.recipients.len() + 1);
+
+        for (r, share) in self.recipients.iter().zip(shares) {
+            left = left.checked_sub(share)?;
+            parts.push((r.address, share.checked_add(r.flat_fee)?));
+        }
+
+        match self.remainder {
+            Remainder::To(address) => parts.push((address, left)),
+            Remainder::Proportional => debug_assert_eq!(left, 0),
+        }
+
+        Some(parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_split_to_remainder() {
+        let (a, b, rest) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let plan = SplitPlan::new(Remainder::To(rest))
+            .with_share(a, Bps::new(1000))
+            .with_fees(b, Bps::new(500), 25_000);
+
+        assert_eq!(
+            plan.split(3_333_333).unwrap(),
+            vec![(a, 333_333), (b, 166_666 + 25_000), (rest, 2_808_334)]
+        );
+
+        // flat fees can't be paid
+        assert_eq!(plan.split(20_000), None);
+
+        let too_much = SplitPlan::new(Remainder::To(rest))
+            .with_share(a, Bps::new(6000))
+            .with_share(b, Bps::new(5000));
+        assert_eq!(too_much.split(0), None);
+    }
+
+    #[test]
+    fn test_split_proportional() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let plan = SplitPlan::new(Remainder::Proportional)
+            .with_share(a, Bps::new(3334))
+            .with_share(b, Bps::new(3333))
+            .with_share(c, Bps::new(3333));
+
+        assert_eq!(plan.split(10).unwrap(), vec![(a, 4), (b, 3), (c, 3)]);
+        assert_eq!(plan.split(0).unwrap(), vec![(a, 0), (b, 0), (c, 0)]);
+
+        assert_eq!(SplitPlan::new(Remainder::Proportional).split(10), None);
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_split_sums_to_amount(
+            amount: u64,
+            shares in prop::collection::vec((0u16..=10_000, 0u64..1_000_000), 1..10),
+            proportional: bool,
+        ) {
+            let remainder = if proportional {
+                Remainder::Proportional
+            } else {
+                Remainder::To(Pubkey::new_unique())
+            };
+
+            let plan = shares.iter().fold(SplitPlan::new(remainder), |plan, (share, fee)| {
+                plan.with_fees(Pubkey::new_unique(), Bps::new(*share), *fee)
+            });
+
+            if let Some(parts) = plan.split(amount) {
+                assert_eq!(parts.iter().map(|(_, p)| *p as u128).sum::<u128>(), amount as u128);
+            }
+        }
+    }
+}
@@ -1,14 +1,21 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
     program_pack::Pack,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
 };
 
 use spl_associated_token_account::get_associated_token_address;
 use spl_token::state as token_state;
 
+use crate::{entity::Entity, error::Error, multisig::Multisig};
+
 pub fn next_expected_token_wallet<'a, 'b: 'a, I>(
     i: &mut I,
     wallet_addr: &Pubkey,
@@ -24,7 +31,7 @@ where
             wallet_addr,
             wallet.key
         );
-        return Err(ProgramError::InvalidArgument);
+        return Error::UnexpectedWallet.into();
     }
 
     if !spl_token::check_id(wallet.owner) {
@@ -53,7 +60,7 @@ where
             expected,
             wallet_acc.key
         );
-        return Err(ProgramError::InvalidArgument);
+        return Error::UnexpectedWallet.into();
     }
 
     if !spl_token::check_id(wallet_acc.owner) {
@@ -73,7 +80,7 @@ pub fn next_signer_account<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
     let account = next_account_info(i)?;
 
     if account.key != expected_acc {
-        return Err(ProgramError::InvalidArgument);
+        return Error::UnexpectedAccount.into();
     }
 
     if !account.is_signer {
@@ -83,6 +90,55 @@ pub fn next_signer_account<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
     Ok(account)
 }
 
+/// returns next expected admin account that is either a signer itself,
+/// or a `Multisig` owned by `program_id` followed by `threshold` of its signers
+pub fn next_admin_signer<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    i: &mut I,
+    expected_admin: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    let admin = next_account_info(i)?;
+
+    if admin.key != expected_admin {
+        return Error::UnexpectedAccount.into();
+    }
+
+    if admin.is_signer {
+        return Ok(admin);
+    }
+
+    if admin.owner != program_id {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let multisig = Multisig::deserialize_from(&admin.try_borrow_data()?)?;
+
+    let signers = (0..multisig.threshold)
+        .map(|_| next_account_info(i))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    multisig.check_signers(signers.iter().map(|s| (s.key, s.is_signer)))?;
+
+    Ok(admin)
+}
+
+/// returns next account that is either `owner` signing,
+/// or admin as accepted by `next_admin_signer`
+pub fn next_owner_or_admin_signer<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>> + Clone>(
+    i: &mut I,
+    owner: &Pubkey,
+    admin: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    let next = i.clone().next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+    if next.key == owner {
+        return next_signer_account(i, owner);
+    }
+
+    next_admin_signer(i, admin, program_id)
+}
+
 /// returns next expected account while checking it's address
 pub fn next_expected_account<'a, 'b: 'a, I>(
     i: &mut I,
@@ -99,8 +155,45 @@ where
             expected_key,
             acc.key
         );
-        return Err(ProgramError::InvalidArgument);
+        return Error::UnexpectedAccount.into();
     }
 
     Ok(acc)
 }
+
+/// Creates rent exempt `account` of `space` bytes owned by `owner` at PDA of `seeds`.
+/// `create_account` fails on addresses holding lamports, so an address funded by
+/// anyone beforehand is topped up, allocated and assigned instead
+pub fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+    accounts: &[AccountInfo<'a>],
+) -> ProgramResult {
+    let lamports = Rent::get()?.minimum_balance(space);
+
+    if account.lamports() == 0 {
+        let create = system_instruction::create_account(
+            payer.key,
+            account.key,
+            lamports,
+            space as u64,
+            owner,
+        );
+        return invoke_signed(&create, accounts, &[seeds]);
+    }
+
+    let shortfall = lamports.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        let transfer = system_instruction::transfer(payer.key, account.key, shortfall);
+        invoke(&transfer, accounts)?;
+    }
+
+    let allocate = system_instruction::allocate(account.key, space as u64);
+    invoke_signed(&allocate, accounts, &[seeds])?;
+
+    let assign = system_instruction::assign(account.key, owner);
+    invoke_signed(&assign, accounts, &[seeds])
+}
@@ -1,11 +1,54 @@
+use std::ops::Range;
+
+use human_common::error::{CustomError, ESCROW_ERRORS};
+use num_derive::FromPrimitive;
 use solana_program::program_error::ProgramError;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+/// Codes are stable, new variants are only appended. See `human_common::error`
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 #[repr(u32)]
 pub enum Error {
     #[error("overflow occured")]
-    Overflow,
+    Overflow = 0x400,
+    #[error("request is not open")]
+    RequestNotOpen = 0x410,
+    #[error("request wallet is empty")]
+    EmptyWallet,
+    #[error("only unfunded requests support this")]
+    UnfundedOnly,
+    #[error("only funded requests support this")]
+    FundedOnly,
+    #[error("voucher amount exceeds collected amount")]
+    VoucherExceedsCollected,
+    #[error("request wallet is not empty after all refunds")]
+    WalletNotEmpty,
+    #[error("not enough collected to accept")]
+    ThresholdNotReached,
+    #[error("request deadline has passed")]
+    RequestExpired,
+    #[error("wallet is not a token account")]
+    NotTokenAccount,
+    #[error("unfunded request wallet must be empty")]
+    UnfundedWalletNotEmpty,
+    #[error("nothing is delegated to contribute")]
+    NothingDelegated,
+    #[error("voucher account must be writable")]
+    VoucherNotWritable,
+    #[error("request is still open")]
+    RequestStillOpen,
+    #[error("invalid user token wallet")]
+    InvalidUserWallet,
+    #[error("state address doesn't match request")]
+    InvalidStateAddress,
+    #[error("voucher address doesn't match state and user")]
+    InvalidVoucherAddress,
+    #[error("request deadline has not passed yet")]
+    RequestNotExpired,
+}
+
+impl CustomError for Error {
+    const CODES: Range<u32> = ESCROW_ERRORS;
 }
 
 impl From<Error> for ProgramError {
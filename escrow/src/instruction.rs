@@ -0,0 +1,346 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    clock::UnixTimestamp,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, rent},
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{authority_address, state_address, voucher_address};
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum EscrowInstruction {
+    Create(CreateInstruction),
+
+    // only for unfunded
+    // transfers all delegated balance as amount
+    // emits created token to atoken wallet where close_authority == our derived authority
+    // **discuss**: we can't automatically refund you (and receive our SOL's back) unless we are the owner of account
+    //      possible options:
+    //          require handing ownership (which kinda defeats all purpose of token)
+    //          creating another scheme for atoken wallets or new kind of account entirely
+    //          wait for all users to sign refund option (not happening)
+    Contribute(ContributeInstruction),
+
+    /// Refunds contributions of closed unfunded requests and closes their vouchers.
+    /// Also closes vouchers of accepted requests, without refunding them.
+    /// State is erased once nothing collected is left.
+    ///
+    /// Accounts:
+    /// 0. `[writable]` request state
+    /// 1. `[writable]` request wallet
+    /// 2. `[writable]` request payer, receives rent of closed accounts
+    /// 3. `[]` rent sysvar
+    /// 4. `[]` clock sysvar
+    /// 5. `[]` derived authority, see `authority_address`
+    /// 6. `[]` token program
+    /// 7. for every voucher refunded, in pairs:
+    ///    `[writable]` voucher and `[writable]` user associated token wallet
+    Refund,
+
+    // only for funded requests. made by author
+    // all funds go back to author. state erased
+    Cancel,
+
+    // for creator: receive all wallet funds.
+    // if funded: erase state
+    Accept,
+
+    // for creator: refund all tokens
+    // if funded: erase state
+    Decline,
+
+    // for anyone after request deadline
+    // funded: all funds go back to author. state erased
+    // unfunded: request declined, contributors can refund
+    Expire,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct ContributeInstruction {
+    pub user: Pubkey,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct CreateInstruction {
+    pub dest: Pubkey,
+    pub payer: Pubkey,
+    pub rtype: CreateInstructionRequest,
+    /// tells apart requests of the same author to the same destination
+    pub nonce: u64,
+}
+
+impl CreateInstruction {
+    /// Author of the request in state seeds: funded request author, or payer of unfunded one
+    pub fn author(&self) -> &Pubkey {
+        match &self.rtype {
+            CreateInstructionRequest::Funded { author, .. } => author,
+            CreateInstructionRequest::Unfunded { .. } => &self.payer,
+        }
+    }
+
+    /// Address of the state created by this instruction
+    pub fn state_address(&self, program_id: &Pubkey) -> Pubkey {
+        state_address(program_id, &self.dest, self.author(), self.nonce).0
+    }
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub enum CreateInstructionRequest {
+    Funded {
+        author: Pubkey,
+        deadline: Option<UnixTimestamp>,
+    },
+    Unfunded {
+        deadline: Option<UnixTimestamp>,
+        accept_threshold: u64,
+    },
+}
+
+/// `wallet` is owned by `authority_address` of the state, see `CreateInstruction::state_address`
+pub fn create(program_id: &Pubkey, wallet: &Pubkey, args: CreateInstruction) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new(args.state_address(program_id), false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new(args.payer, true),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Create(args).try_to_vec().unwrap(),
+    }
+}
+
+/// `source_wallet` has the contributed amount delegated to `delegate`,
+/// `payer` funds the voucher of `user` on the first contribution
+pub fn contribute(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    source_wallet: &Pubkey,
+    delegate: &Pubkey,
+    user: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let (voucher, _) = voucher_address(program_id, state, user);
+
+    let accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new(*source_wallet, true),
+        AccountMeta::new_readonly(*delegate, true),
+        AccountMeta::new(voucher, false),
+        AccountMeta::new_readonly(rent::ID, false),
+        AccountMeta::new_readonly(clock::ID, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Contribute(ContributeInstruction { user: *user })
+            .try_to_vec()
+            .unwrap(),
+    }
+}
+
+/// Refunds vouchers of `users` to their associated token wallets
+pub fn refund(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    users: &[Pubkey],
+) -> Instruction {
+    let vouchers: Vec<_> = users
+        .iter()
+        .map(|user| (voucher_address(program_id, state, user).0, *user))
+        .collect();
+
+    refund_vouchers(program_id, state, wallet, mint, payer, &vouchers)
+}
+
+/// Refunds `(voucher, user)` pairs, for vouchers created before `voucher_address`
+pub fn refund_vouchers(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    vouchers: &[(Pubkey, Pubkey)],
+) -> Instruction {
+    let (authority, _) = authority_address(program_id, state);
+
+    let mut accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new(*payer, false),
+        AccountMeta::new_readonly(rent::ID, false),
+        AccountMeta::new_readonly(clock::ID, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ];
+
+    for (voucher, user) in vouchers {
+        accounts.push(AccountMeta::new(*voucher, false));
+        accounts.push(AccountMeta::new(
+            get_associated_token_address(user, mint),
+            false,
+        ));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Refund.try_to_vec().unwrap(),
+    }
+}
+
+/// wSOL requests are refunded to `author` itself, others to its associated token wallet
+pub fn cancel(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    author: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let (authority, _) = authority_address(program_id, state);
+
+    let mut accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new(*author, true),
+        AccountMeta::new(*payer, false),
+        AccountMeta::new(authority, false),
+        AccountMeta::new(get_associated_token_address(author, mint), false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ];
+
+    if *mint == spl_token::native_mint::ID {
+        accounts.push(AccountMeta::new_readonly(system_program::ID, false));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Cancel.try_to_vec().unwrap(),
+    }
+}
+
+pub fn accept(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    destination: &Pubkey,
+    destination_wallet: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let (authority, _) = authority_address(program_id, state);
+
+    let accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new_readonly(*destination, true),
+        AccountMeta::new(*destination_wallet, false),
+        AccountMeta::new(*payer, false),
+        AccountMeta::new_readonly(clock::ID, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ];
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Accept.try_to_vec().unwrap(),
+    }
+}
+
+/// `author` of funded requests gets the funds back, `None` for unfunded requests
+pub fn decline(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    payer: &Pubkey,
+    author: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new_readonly(*destination, true),
+        AccountMeta::new(*payer, false),
+    ];
+
+    if let Some(author) = author {
+        accounts.extend(author_refund_accounts(program_id, state, mint, author));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Decline.try_to_vec().unwrap(),
+    }
+}
+
+/// `author` of funded requests gets the funds back, `None` for unfunded requests
+pub fn expire(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    payer: &Pubkey,
+    author: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*wallet, false),
+        AccountMeta::new(*payer, false),
+        AccountMeta::new_readonly(clock::ID, false),
+    ];
+
+    if let Some(author) = author {
+        accounts.extend(author_refund_accounts(program_id, state, mint, author));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: EscrowInstruction::Expire.try_to_vec().unwrap(),
+    }
+}
+
+fn author_refund_accounts(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    mint: &Pubkey,
+    author: &Pubkey,
+) -> Vec<AccountMeta> {
+    let (authority, _) = authority_address(program_id, state);
+
+    if *mint == spl_token::native_mint::ID {
+        vec![
+            AccountMeta::new(authority, false),
+            AccountMeta::new(*author, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ]
+    } else {
+        vec![
+            AccountMeta::new(authority, false),
+            AccountMeta::new(get_associated_token_address(author, mint), false),
+            AccountMeta::new(*author, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ]
+    }
+}
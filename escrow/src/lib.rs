@@ -1,21 +1,25 @@
 #![forbid(unsafe_code)]
 #![deny(clippy::all)]
 #![deny(clippy::integer_arithmetic)]
-#![cfg(not(feature = "no-entrypoint"))]
 
-mod error;
+pub mod error;
+pub mod instruction;
+pub mod state;
 
 use std::mem::{self};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use error::Error;
 use human_common::utils::{
-    next_atoken_wallet, next_expected_account, next_expected_token_wallet, next_signer_account,
+    create_pda_account, next_atoken_wallet, next_expected_account, next_expected_token_wallet,
+    next_signer_account,
+};
+use instruction::{
+    ContributeInstruction, CreateInstruction, CreateInstructionRequest, EscrowInstruction,
 };
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    clock::{Clock, UnixTimestamp},
-    entrypoint,
+    clock::Clock,
     entrypoint::ProgramResult,
     msg,
     program::{invoke, invoke_signed},
@@ -31,207 +35,63 @@ use solana_program::{
 use spl_associated_token_account::get_associated_token_address;
 use spl_token::instruction as token_inst;
 use spl_token::state as token_state;
+use state::{
+    FundedRequest, Request, RequestStatus, State, UnfundedRequest, Voucher, STATE_SIZE,
+    VOUCHER_SIZE,
+};
 
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-enum Request {
-    /// Request with fixed amount
-    Funded(FundedRequest),
-    /// Request with variable amount
-    Unfunded(UnfundedRequest),
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-struct FundedRequest {
-    // system account
-    author: Pubkey,
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-struct UnfundedRequest {
-    /// amount of token accumulated. needed in case of refund
-    collected: u64,
-    deadline: Option<UnixTimestamp>,
-    accept_threshold: u64,
-}
-
-#[repr(u8)]
-#[derive(Debug, BorshDeserialize, BorshSerialize, PartialEq)]
-enum RequestStatus {
-    Unitialized,
-    Open,
-    Declined,
-    Accepted,
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-struct State {
-    request_status: RequestStatus,
-    /// the moment request was created, so later we could add logic to close stale requests
-    created_at: UnixTimestamp,
-    /// temporary token wallet
-    wallet: Pubkey,
-    /// system account. if accepted, where tokens would go
-    destination: Pubkey,
-    /// account to refund SOL from closing state
-    payer: Pubkey,
-    /// request body
-    request: Request,
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize, PartialEq)]
-struct Voucher {
-    /// state address will be used for reverse RPC lookup (getProgramAccounts)
-    state: Pubkey,
-    /// user (again, for reverse lookup)
-    user: Pubkey,
-    /// amount contributed by user
-    amount: u64,
-}
-
-impl State {
-    /// returns whether unfunded request if expired. funded requests cannot expire
-    fn expired(&self, now: UnixTimestamp) -> bool {
-        if !self.is_open() {
-            return false;
-        }
-
-        if let Request::Unfunded(UnfundedRequest {
-            deadline: Some(deadline),
-            collected,
-            accept_threshold,
-        }) = self.request
-        {
-            return now > deadline && collected < accept_threshold;
-        }
-
-        false
-    }
-
-    fn try_accept(&mut self) -> Result<(), ProgramError> {
-        if self.request_status != RequestStatus::Open {
-            return Err(ProgramError::Custom(0x16));
-        }
-
-        if let Request::Unfunded(request) = &self.request {
-            if request.collected < request.accept_threshold {
-                msg!("not enough collected to accept");
-                return Err(ProgramError::Custom(0x16));
-            }
-        }
-
-        self.request_status = RequestStatus::Accepted;
-
-        Ok(())
-    }
-
-    fn is_funded(&self) -> bool {
-        matches!(self.request, Request::Funded(_))
-    }
-
-    fn is_open(&self) -> bool {
-        self.request_status == RequestStatus::Open
-    }
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-#[repr(u8)]
-#[non_exhaustive]
-enum Instruction {
-    Create(CreateInstruction),
-
-    // only for unfunded
-    // transfers all delegated balance as amount
-    // emits created token to atoken wallet where close_authority == our derived authority
-    // **discuss**: we can't automatically refund you (and receive our SOL's back) unless we are the owner of account
-    //      possible options:
-    //          require handing ownership (which kinda defeats all purpose of token)
-    //          creating another scheme for atoken wallets or new kind of account entirely
-    //          wait for all users to sign refund option (not happening)
-    Contribute(ContributeInstruction),
-
-    // refund contribution for contribution token. burn token and close account
-    // can also be used to close accounts of accepted requests
-    // if all collected == 0: erase state.
-    Refund,
-
-    // only for funded requests. made by author
-    // all funds go back to author. state erased
-    Cancel,
-
-    // for creator: receive all wallet funds.
-    // if funded: erase state
-    Accept,
-
-    // for creator: refund all tokens
-    // if funded: erase state
-    Decline,
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-struct ContributeInstruction {
-    user: Pubkey,
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-struct CreateInstruction {
-    dest: Pubkey,
-    payer: Pubkey,
-    rtype: CreateInstructionRequest,
-}
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
-enum CreateInstructionRequest {
-    Funded {
-        author: Pubkey,
-    },
-    Unfunded {
-        deadline: Option<UnixTimestamp>,
-        accept_threshold: u64,
-    },
-}
-
-entrypoint!(process_instruction);
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    let instruction = Instruction::try_from_slice(instruction_data).map_err(|e| {
+    let instruction = EscrowInstruction::try_from_slice(instruction_data).map_err(|e| {
         msg!("error parsing instruction: {}", e);
         ProgramError::InvalidInstructionData
     })?;
 
     match instruction {
-        Instruction::Create(inst) => {
+        EscrowInstruction::Create(inst) => {
             msg!("creating funded request");
             process_create(program_id, accounts, inst)
         }
-        Instruction::Contribute(ContributeInstruction { user }) => {
+        EscrowInstruction::Contribute(ContributeInstruction { user }) => {
             msg!("contributing to unfunded request");
             process_contribute(program_id, accounts, &user)
         }
-        Instruction::Refund => {
+        EscrowInstruction::Refund => {
             msg!("refunding request");
             process_refund(program_id, accounts)
         }
 
-        Instruction::Accept => {
+        EscrowInstruction::Accept => {
             msg!("accepting request");
             process_accept(program_id, accounts)
         }
-        Instruction::Decline => {
+        EscrowInstruction::Decline => {
             msg!("rejecting redeem request");
             process_decline(program_id, accounts)
         }
-        Instruction::Cancel => {
+        EscrowInstruction::Cancel => {
             msg!("cancelling funded redeem request");
             process_cancel(program_id, accounts)
         }
+        EscrowInstruction::Expire => {
+            msg!("expiring request");
+            process_expire(program_id, accounts)
+        }
     }
 }
 
 pub const V1: &[u8] = b"HMN_R1";
+/// Seed of the request authority, see `authority_address`
 pub const AUTHORITY_SEED: &[u8] = b"A";
+/// Seed of request states, see `state_address`
+pub const STATE_SEED: &[u8] = b"S";
+/// Seed of vouchers, see `voucher_address`
+pub const VOUCHER_SEED: &[u8] = b"V";
 
 #[macro_export]
 macro_rules! find_keyed_address {
@@ -241,10 +101,10 @@ macro_rules! find_keyed_address {
     ($program_id:expr, $seed:expr, $token:expr) => {{
         let _: (&Pubkey, &[u8]) = ($program_id, $token);
 
-        let seeds = &[V1, $seed, ($token)];
+        let seeds = &[$crate::V1, $seed, ($token)];
         let (addr, bump) = Pubkey::find_program_address(seeds, $program_id);
 
-        (addr, &[V1, $seed, $token, &[bump]])
+        (addr, &[$crate::V1, $seed, $token, &[bump]])
     }};
 }
 
@@ -252,14 +112,50 @@ macro_rules! find_keyed_address {
 #[macro_export]
 macro_rules! authority {
     ($program_id:expr, $state_addr:expr) => {
-        $crate::find_keyed_address!($program_id, AUTHORITY_SEED, $state_addr.as_ref())
+        $crate::find_keyed_address!($program_id, $crate::AUTHORITY_SEED, $state_addr.as_ref())
     };
 }
 
-const STATE_SIZE: usize = 138;
+/// Authority of a request, `[V1, AUTHORITY_SEED, state]`. Owns the request wallet,
+/// signs transfers out of it and closes it. Lamports of closed wSOL wallets pass through it
+pub fn authority_address(program_id: &Pubkey, state: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[V1, AUTHORITY_SEED, state.as_ref()], program_id)
+}
+
+/// Request state, `[V1, STATE_SEED, destination, author, nonce]`, created by `Create`.
+/// `nonce` tells apart requests of the same author to the same destination,
+/// see `CreateInstruction::author`
+pub fn state_address(
+    program_id: &Pubkey,
+    destination: &Pubkey,
+    author: &Pubkey,
+    nonce: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            V1,
+            STATE_SEED,
+            destination.as_ref(),
+            author.as_ref(),
+            &nonce.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Voucher of `user` contributions to a request, `[V1, VOUCHER_SEED, state, user]`,
+/// created by the first `Contribute` of the user
+pub fn voucher_address(program_id: &Pubkey, state: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[V1, VOUCHER_SEED, state.as_ref(), user.as_ref()],
+        program_id,
+    )
+}
 
-// [writable] new state account owned by this program
+// [writable] new state account, see `state_address`
 // [writable] new wallet with owner and close authority set to derived authority with tokens already on it
+// [writable, sign] payer
+// [] sysprog
 fn process_create(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -271,30 +167,37 @@ fn process_create(
     let (wallet_addr, wallet) =
         next_owned_token_wallet(account_info_iter, program_id, state_acc.key)?;
 
-    let rent = Rent::get()?;
+    let payer = next_signer_account(account_info_iter, &args.payer)?;
+    next_expected_account(account_info_iter, &system_program::ID)?;
+
     let clock = Clock::get()?;
 
-    let mut state_data = state_acc.try_borrow_mut_data()?;
+    let author = *args.author();
+    let nonce_bytes = args.nonce.to_le_bytes();
+    let (state_addr, state_bump) = state_address(program_id, &args.dest, &author, args.nonce);
 
-    if !rent.is_exempt(state_acc.lamports(), STATE_SIZE) {
-        return Err(ProgramError::AccountNotRentExempt);
+    if *state_acc.key != state_addr {
+        msg!("invalid state address {} != {}", state_acc.key, state_addr);
+        return Error::InvalidStateAddress.into();
     }
 
     // sanity check
     match args.rtype {
         CreateInstructionRequest::Funded { .. } if wallet.amount == 0 => {
             // should contain some funds (can't create empty request)
-            return Err(ProgramError::InsufficientFunds);
+            return Error::EmptyWallet.into();
         }
         CreateInstructionRequest::Unfunded { .. } if wallet.amount != 0 => {
             // should NOT contain any funds because we are unable to track them
-            return Err(ProgramError::InvalidAccountData);
+            return Error::UnfundedWalletNotEmpty.into();
         }
         _ => {}
     }
 
     let request = match args.rtype {
-        CreateInstructionRequest::Funded { author } => Request::Funded(FundedRequest { author }),
+        CreateInstructionRequest::Funded { author, deadline } => {
+            Request::Funded(FundedRequest { author, deadline })
+        }
         CreateInstructionRequest::Unfunded {
             deadline,
             accept_threshold,
@@ -314,7 +217,24 @@ fn process_create(
         request,
     };
 
-    write_state(&mut state_data, state)?;
+    let state_seeds: &[&[u8]] = &[
+        V1,
+        STATE_SEED,
+        args.dest.as_ref(),
+        author.as_ref(),
+        &nonce_bytes,
+        &[state_bump],
+    ];
+    create_pda_account(
+        payer,
+        state_acc,
+        STATE_SIZE,
+        program_id,
+        state_seeds,
+        accounts,
+    )?;
+
+    write_state(&mut state_acc.try_borrow_mut_data()?, state)?;
 
     Ok(())
 }
@@ -325,8 +245,7 @@ fn write_state(data: &mut [u8], state: State) -> Result<(), ProgramError> {
     }
 
     // is this enough?
-    if matches!(State::try_from_slice(data), Ok(State {  request_status, .. }) if request_status != RequestStatus::Unitialized)
-    {
+    if State::decode(data).is_ok() {
         return Err(ProgramError::AccountAlreadyInitialized);
     }
 
@@ -348,7 +267,7 @@ where
     let wallet = next_account_info(i)?;
 
     if !spl_token::check_id(wallet.owner) {
-        return Err(ProgramError::InvalidArgument);
+        return Error::NotTokenAccount.into();
     }
 
     let account = token_state::Account::unpack(&wallet.data.borrow())?;
@@ -380,25 +299,15 @@ fn next_state_account<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
         return Err(ProgramError::IllegalOwner);
     }
 
-    let data = state_acc.try_borrow_data()?;
-
-    let state = State::try_from_slice(&data)?;
-
-    if state.request_status == RequestStatus::Unitialized {
-        return Err(ProgramError::UninitializedAccount);
-    }
+    let state = State::decode(&state_acc.try_borrow_data()?)?;
 
     Ok((state, state_acc))
 }
 
 fn save_state(state: State, state_acc: &AccountInfo) -> Result<(), ProgramError> {
-    let serialized = state.try_to_vec()?;
-
     let mut data = state_acc.try_borrow_mut_data()?;
 
-    copy_slice(&mut data[..], &serialized);
-
-    Ok(())
+    state.encode(&mut data)
 }
 
 fn erase_state(
@@ -418,9 +327,7 @@ fn erase_state(
 
     **state_acc.try_borrow_mut_lamports()? = 0;
     let mut payer_lamports = payer_acc.try_borrow_mut_lamports()?;
-    **payer_lamports = payer_lamports
-        .checked_add(lamports)
-        .ok_or(Error::Overflow)?;
+    **payer_lamports = checked_math::add(**payer_lamports, lamports).ok_or(Error::Overflow)?;
 
     let mut data = state_acc.try_borrow_mut_data()?;
     let l = data.len();
@@ -438,9 +345,12 @@ fn copy_slice(dst: &mut [u8], src: &[u8]) {
 // [writable] request wallet
 // [writable] token wallet with delegated amount
 // [sign] delegate
-// [writable] voucher account
+// [writable] voucher account, see `voucher_address`
 // [] rent var
 // [] clock var
+// [writable, sign] payer of the voucher account
+// [] sysprog
+// [] token prog
 fn process_contribute(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -461,12 +371,10 @@ fn process_contribute(
 
     let amount = source_wallet_data.delegated_amount;
     if amount == 0 {
-        return Err(ProgramError::InvalidArgument);
+        return Error::NothingDelegated.into();
     }
 
-    let delegate = source_wallet_data
-        .delegate
-        .ok_or(ProgramError::InvalidArgument)?;
+    let delegate = source_wallet_data.delegate.ok_or(Error::NothingDelegated)?;
 
     next_signer_account(account_info_iter, &delegate)?;
 
@@ -474,18 +382,27 @@ fn process_contribute(
     let rent = Rent::from_account_info(next_account_info(account_info_iter)?)?;
     let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
 
+    let payer = next_account_info(account_info_iter)?;
+    next_expected_account(account_info_iter, &system_program::ID)?;
+
+    let (voucher_addr, voucher_bump) = voucher_address(program_id, state_acc.key, user);
+    if *voucher_acc.key != voucher_addr {
+        msg!("invalid voucher {} != {}", voucher_acc.key, voucher_addr);
+        return Error::InvalidVoucherAddress.into();
+    }
+
     if state.expired(clock.unix_timestamp) {
         state.request_status = RequestStatus::Declined;
         save_state(state, state_acc)?;
-        return Err(ProgramError::Custom(0x17));
+        return Error::RequestExpired.into();
     }
 
     if !state.is_open() {
-        return Err(ProgramError::Custom(0x12));
+        return Error::RequestNotOpen.into();
     }
 
-    let mut unfunded_state = match state.request {
-        Request::Funded(_) => return Err(ProgramError::Custom(0x12)),
+    let unfunded_state = match state.request {
+        Request::Funded(_) => return Error::UnfundedOnly.into(),
         Request::Unfunded(ref mut s) => s,
     };
 
@@ -504,19 +421,30 @@ fn process_contribute(
     // issue voucher (or update amount on existing one)
     let mut previous_amount = 0;
 
-    if let Some(v) = get_voucher(voucher_acc, program_id, &rent)? {
-        if v.user != *user {
-            msg!("refusing to override another user's voucher");
-            return Err(ProgramError::IllegalOwner);
-        }
-
+    if let Some(v) = get_voucher(voucher_acc, program_id, state_acc.key, &rent)? {
         previous_amount = v.amount
+    } else if voucher_acc.data_is_empty() {
+        let voucher_seeds: &[&[u8]] = &[
+            V1,
+            VOUCHER_SEED,
+            state_acc.key.as_ref(),
+            user.as_ref(),
+            &[voucher_bump],
+        ];
+        create_pda_account(
+            payer,
+            voucher_acc,
+            VOUCHER_SIZE,
+            program_id,
+            voucher_seeds,
+            accounts,
+        )?;
     }
 
     let voucher = Voucher {
         state: *state_acc.key,
         user: *user,
-        amount: previous_amount.checked_add(amount).ok_or(Error::Overflow)?,
+        amount: checked_math::add(previous_amount, amount).ok_or(Error::Overflow)?,
     }
     .try_to_vec()?;
 
@@ -524,23 +452,27 @@ fn process_contribute(
     copy_slice(&mut data, &voucher);
 
     // update total contributed counter
-    unfunded_state.collected = unfunded_state
-        .collected
-        .checked_add(amount)
-        .ok_or(Error::Overflow)?;
+    unfunded_state.collected =
+        checked_math::add(unfunded_state.collected, amount).ok_or(Error::Overflow)?;
 
     save_state(state, state_acc)?;
 
     Ok(())
 }
 
+/// returns voucher of `state`, `None` if voucher account is not created yet or redeemed
 fn get_voucher(
     voucher_acc: &AccountInfo,
     program_id: &Pubkey,
+    state_addr: &Pubkey,
     rent: &Rent,
 ) -> Result<Option<Voucher>, ProgramError> {
     if !voucher_acc.is_writable {
-        return Err(ProgramError::InvalidArgument);
+        return Error::VoucherNotWritable.into();
+    }
+
+    if voucher_acc.data_is_empty() {
+        return Ok(None);
     }
 
     if voucher_acc.owner != program_id {
@@ -551,12 +483,19 @@ fn get_voucher(
         return Err(ProgramError::AccountNotRentExempt);
     }
 
-    let data = voucher_acc.try_borrow_data()?;
+    let voucher = match Voucher::decode(&voucher_acc.try_borrow_data()?) {
+        Some(voucher) => voucher,
+        None => return Ok(None),
+    };
 
-    match Voucher::try_from_slice(&data) {
-        Ok(v) if v.amount > 0 => Ok(Some(v)),
-        _ => Ok(None),
+    // vouchers created before `voucher_address` live at arbitrary addresses,
+    // being written by the program they are trusted as long as they belong to the state
+    if voucher.state != *state_addr {
+        msg!("voucher {} is not issued for this state", voucher_acc.key);
+        return Error::InvalidVoucherAddress.into();
     }
+
+    Ok(Some(voucher))
 }
 
 // [writable] request state
@@ -564,6 +503,8 @@ fn get_voucher(
 // [writable] payer
 // [] rent var
 // [] clock var
+// [] derived authority
+// [] token program
 // for n..10:
 // [writable] voucher
 // [writable] user atoken wallet
@@ -577,72 +518,81 @@ fn process_refund(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     let rent = Rent::from_account_info(next_account_info(account_info_iter)?)?;
     let _clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
 
+    let (derived_authority, authority_seed) = authority!(program_id, state_acc.key);
+    next_expected_account(account_info_iter, &derived_authority)?;
+    next_expected_account(account_info_iter, &spl_token::ID)?;
+
     if *payer.key != state.payer {
         // not fair
         return Err(ProgramError::IllegalOwner);
     }
 
     if state.is_open() {
-        return Err(ProgramError::InvalidArgument);
+        return Error::RequestStillOpen.into();
     }
 
     let request = match state.request {
-        Request::Funded(_) => return Err(ProgramError::Custom(0x12)),
+        Request::Funded(_) => return Error::UnfundedOnly.into(),
         Request::Unfunded(ref mut s) => s,
     };
 
-    let (derived_authority, authority_seed) = authority!(program_id, state_acc.key);
-
-    let mut payer_lamports = payer.try_borrow_mut_lamports()?;
+    // `wallet` is read before refunds
+    let mut wallet_amount = wallet.amount;
+    let mut redeemed = Vec::new();
 
     // get our sweet money back
     while account_info_iter.peek().is_some() {
         let voucher_acc = next_account_info(account_info_iter)?;
 
-        let voucher = get_voucher(voucher_acc, program_id, &rent)?
+        let voucher = get_voucher(voucher_acc, program_id, state_acc.key, &rent)?
             .ok_or(ProgramError::UninitializedAccount)?;
 
-        // close voucher
-        redeem_voucher(voucher_acc, &mut payer_lamports)?;
-
-        request.collected = request
-            .collected
-            .checked_sub(voucher.amount)
-            .ok_or(ProgramError::Custom(0x14))?;
+        request.collected = checked_math::sub(request.collected, voucher.amount)
+            .ok_or(Error::VoucherExceedsCollected)?;
 
         let user_wallet = next_account_info(account_info_iter)?;
 
         let derived_wallet = get_associated_token_address(&voucher.user, &wallet.mint);
         if derived_wallet != *user_wallet.key {
-            return Err(ProgramError::InvalidArgument);
+            return Error::InvalidUserWallet.into();
         }
 
-        if state.request_status == RequestStatus::Accepted {
-            // nothing to refund
-            continue;
+        // nothing to refund of accepted requests
+        if state.request_status != RequestStatus::Accepted {
+            // refund user
+            let transfer = token_inst::transfer(
+                &spl_token::ID,
+                &state.wallet,
+                user_wallet.key,
+                &derived_authority,
+                &[],
+                voucher.amount,
+            )?;
+
+            invoke_signed(&transfer, accounts, &[authority_seed])?;
+
+            wallet_amount =
+                checked_math::sub(wallet_amount, voucher.amount).ok_or(Error::Overflow)?;
         }
 
-        // refund user
-        let transfer = token_inst::transfer(
-            &spl_token::ID,
-            &state.wallet,
-            user_wallet.key,
-            &derived_authority,
-            &[],
-            voucher.amount,
-        )?;
+        // wiped right away, so the voucher can't be passed twice
+        let mut data = voucher_acc.try_borrow_mut_data()?;
+        let l = data.len();
+        sol_memset(&mut data, 0, l);
 
-        invoke_signed(&transfer, accounts, &[authority_seed])?;
+        redeemed.push(voucher_acc);
     }
 
     if request.collected > 0 {
         // some refunding still required
+        redeem_vouchers(&redeemed, payer)?;
+        save_state(state, state_acc)?;
         return Ok(());
     }
 
-    if wallet.amount != 0 {
+    if wallet_amount != 0 {
         msg!("sanity check failed: collected == 0 but token amount is still not zero");
-        return Err(ProgramError::Custom(0x15));
+        return Error::WalletNotEmpty.into();
     }
 
     // all accounts closed and refunded
@@ -656,20 +606,21 @@ fn process_refund(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
 
     invoke_signed(&close, accounts, &[authority_seed])?;
 
+    redeem_vouchers(&redeemed, payer)?;
     erase_state(state, state_acc, payer)?;
 
     Ok(())
 }
 
-fn redeem_voucher(state_acc: &AccountInfo, payer_lamports: &mut u64) -> Result<(), ProgramError> {
-    // withdraw all lamports from state
-    *payer_lamports = payer_lamports
-        .checked_add(mem::take(*state_acc.try_borrow_mut_lamports()?))
-        .ok_or(Error::Overflow)?;
+/// Withdraws lamports of redeemed vouchers. Done after the last CPI, since CPIs with `payer`
+/// would see its lamports grow without vouchers losing theirs
+fn redeem_vouchers(vouchers: &[&AccountInfo], payer: &AccountInfo) -> ProgramResult {
+    let mut payer_lamports = payer.try_borrow_mut_lamports()?;
 
-    let mut data = state_acc.try_borrow_mut_data()?;
-    let l = data.len();
-    sol_memset(&mut data, 0, l);
+    for voucher_acc in vouchers {
+        let lamports = mem::take(*voucher_acc.try_borrow_mut_lamports()?);
+        **payer_lamports = checked_math::add(**payer_lamports, lamports).ok_or(Error::Overflow)?;
+    }
 
     Ok(())
 }
@@ -702,16 +653,16 @@ fn process_accept(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     if state.expired(clock.unix_timestamp) {
         state.request_status = RequestStatus::Declined;
         save_state(state, state_acc)?;
-        return Err(ProgramError::Custom(0x17));
+        return Error::RequestExpired.into();
     }
 
     if !state.is_open() {
-        return Err(ProgramError::Custom(0x10));
+        return Error::RequestNotOpen.into();
     }
 
     // sanity check
     if wallet.amount == 0 {
-        return Err(ProgramError::Custom(0x11));
+        return Error::EmptyWallet.into();
     }
 
     state.try_accept()?;
@@ -766,15 +717,15 @@ fn process_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     let wallet = next_expected_token_wallet(account_info_iter, &state.wallet)?;
 
     let author = match state.request {
-        Request::Funded(FundedRequest { author }) => author,
-        Request::Unfunded { .. } => return Err(ProgramError::Custom(0x13)),
+        Request::Funded(FundedRequest { author, .. }) => author,
+        Request::Unfunded { .. } => return Error::FundedOnly.into(),
     };
 
     let _dest = next_signer_account(account_info_iter, &author)?;
     let payer = next_expected_account(account_info_iter, &state.payer)?;
 
     if !state.is_open() {
-        return Err(ProgramError::Custom(0x10));
+        return Error::RequestNotOpen.into();
     }
 
     state.request_status = RequestStatus::Declined;
@@ -857,70 +808,22 @@ fn process_decline(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
     let payer = next_expected_account(account_info_iter, &state.payer)?;
 
     if !state.is_open() {
-        return Err(ProgramError::Custom(0x10));
+        return Error::RequestNotOpen.into();
     }
 
     state.request_status = RequestStatus::Declined;
 
     match state.request {
-        Request::Funded(ref r) => {
-            let (derived_authority, authority_seed) = authority!(program_id, state_acc.key);
-            let _authority = next_expected_account(account_info_iter, &derived_authority)?;
-
-            if let COption::Some(rent_balance) = wallet.is_native {
-                let _author = next_expected_account(account_info_iter, &r.author)?;
-
-                // close token wallet
-                let close = token_inst::close_account(
-                    &spl_token::ID,
-                    &state.wallet,
-                    &derived_authority,
-                    &derived_authority,
-                    &[],
-                )?;
-                invoke_signed(&close, accounts, &[authority_seed])?;
-
-                next_expected_account(account_info_iter, &spl_token::ID)?; // 8
-                next_expected_account(account_info_iter, &system_program::ID)?; // 9
-
-                // system transfer amount to author
-                let transfer =
-                    system_instruction::transfer(&derived_authority, &r.author, wallet.amount);
-                invoke_signed(&transfer, accounts, &[authority_seed])?;
-
-                // transfer rent exemption to payer
-                let transfer =
-                    system_instruction::transfer(&derived_authority, payer.key, rent_balance);
-                invoke_signed(&transfer, accounts, &[authority_seed])?;
-            } else {
-                let (author_wallet, _) =
-                    next_atoken_wallet(account_info_iter, &r.author, &wallet.mint)?;
-
-                next_expected_account(account_info_iter, &r.author)?;
-
-                // transfer tokens to dest
-                let transfer = token_inst::transfer(
-                    &spl_token::ID,
-                    &state.wallet,
-                    &author_wallet,
-                    &derived_authority,
-                    &[],
-                    wallet.amount,
-                )?;
-
-                invoke_signed(&transfer, accounts, &[authority_seed])?;
-
-                // close token wallet
-                let close = token_inst::close_account(
-                    &spl_token::ID,
-                    &state.wallet,
-                    payer.key,
-                    &derived_authority,
-                    &[],
-                )?;
-
-                invoke_signed(&close, accounts, &[authority_seed])?;
-            }
+        Request::Funded(_) => {
+            refund_author(
+                program_id,
+                accounts,
+                account_info_iter,
+                state_acc,
+                &state,
+                &wallet,
+                payer,
+            )?;
 
             // we're done here. return our lamports
             erase_state(state, state_acc, payer)?;
@@ -933,12 +836,146 @@ fn process_decline(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
         }
     }
 }
+
+// [writable] request
+// [writable] request wallet
+// [writable] payer
+// [] clock var
+// [] if request is funded, accounts of `refund_author`
+fn process_expire(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, state_acc) = next_state_account(account_info_iter, program_id)?;
+    let wallet = next_expected_token_wallet(account_info_iter, &state.wallet)?;
+
+    let payer = next_expected_account(account_info_iter, &state.payer)?;
+    let clock = Clock::from_account_info(next_account_info(account_info_iter)?)?;
+
+    if !state.is_open() {
+        return Error::RequestNotOpen.into();
+    }
+
+    if !state.expired(clock.unix_timestamp) {
+        return Error::RequestNotExpired.into();
+    }
+
+    state.request_status = RequestStatus::Declined;
+
+    match state.request {
+        Request::Funded(_) => {
+            refund_author(
+                program_id,
+                accounts,
+                account_info_iter,
+                state_acc,
+                &state,
+                &wallet,
+                payer,
+            )?;
+
+            erase_state(state, state_acc, payer)?;
+            Ok(())
+        }
+        Request::Unfunded(_) => {
+            // contributors get their tokens back with refund
+            save_state(state, state_acc)?;
+            Ok(())
+        }
+    }
+}
+
+// refunds all funds of funded request to author and closes its wallet, rent goes to payer
+// [] derived authority
+// if native:
+//   [writable] author
+//   [] token prog
+//   [] sysprog
+// else:
+//   [writable] atoken address of author
+//   [writable] author
+//   [] token prog
+fn refund_author<'a, 'b: 'a, I>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'b>],
+    account_info_iter: &mut I,
+    state_acc: &AccountInfo,
+    state: &State,
+    wallet: &token_state::Account,
+    payer: &AccountInfo,
+) -> ProgramResult
+where
+    I: Iterator<Item = &'a AccountInfo<'b>>,
+{
+    let author = match state.request {
+        Request::Funded(FundedRequest { ref author, .. }) => author,
+        Request::Unfunded(_) => return Error::FundedOnly.into(),
+    };
+
+    let (derived_authority, authority_seed) = authority!(program_id, state_acc.key);
+    let _authority = next_expected_account(account_info_iter, &derived_authority)?;
+
+    if let COption::Some(rent_balance) = wallet.is_native {
+        let _author = next_expected_account(account_info_iter, author)?;
+
+        // close token wallet
+        let close = token_inst::close_account(
+            &spl_token::ID,
+            &state.wallet,
+            &derived_authority,
+            &derived_authority,
+            &[],
+        )?;
+        invoke_signed(&close, accounts, &[authority_seed])?;
+
+        next_expected_account(account_info_iter, &spl_token::ID)?;
+        next_expected_account(account_info_iter, &system_program::ID)?;
+
+        // system transfer amount to author
+        let transfer = system_instruction::transfer(&derived_authority, author, wallet.amount);
+        invoke_signed(&transfer, accounts, &[authority_seed])?;
+
+        // transfer rent exemption to payer
+        let transfer = system_instruction::transfer(&derived_authority, payer.key, rent_balance);
+        invoke_signed(&transfer, accounts, &[authority_seed])?;
+    } else {
+        let (author_wallet, _) = next_atoken_wallet(account_info_iter, author, &wallet.mint)?;
+
+        next_expected_account(account_info_iter, author)?;
+        next_expected_account(account_info_iter, &spl_token::ID)?;
+
+        // transfer tokens to author
+        let transfer = token_inst::transfer(
+            &spl_token::ID,
+            &state.wallet,
+            &author_wallet,
+            &derived_authority,
+            &[],
+            wallet.amount,
+        )?;
+
+        invoke_signed(&transfer, accounts, &[authority_seed])?;
+
+        // close token wallet
+        let close = token_inst::close_account(
+            &spl_token::ID,
+            &state.wallet,
+            payer.key,
+            &derived_authority,
+            &[],
+        )?;
+
+        invoke_signed(&close, accounts, &[authority_seed])?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use solana_program::pubkey::Pubkey;
 
     use crate::{
-        Request::{self, *},
+        state::Request::{self, *},
         *,
     };
 
@@ -957,6 +994,7 @@ mod tests {
         assert!(
             from_request(Funded(FundedRequest {
                 author: Pubkey::default(),
+                deadline: None,
             }))
             .is_funded()
                 == true
@@ -999,12 +1037,32 @@ mod tests {
         }));
 
         assert!(no_deadline.expired(now) == false);
+
+        // funded requests expire regardless of amount
+        let mut funded = from_request(Funded(FundedRequest {
+            author: Pubkey::default(),
+            deadline: Some(now - 100),
+        }));
+
+        assert!(funded.expired(now));
+        assert!(!funded.expired(now - 100));
+
+        funded.request_status = RequestStatus::Declined;
+        assert!(!funded.expired(now));
+
+        let funded_no_deadline = from_request(Funded(FundedRequest {
+            author: Pubkey::default(),
+            deadline: None,
+        }));
+
+        assert!(!funded_no_deadline.expired(now));
     }
 
     #[test]
     fn test_state_try_accept() {
         let mut funded = from_request(Funded(FundedRequest {
             author: Pubkey::default(),
+            deadline: None,
         }));
 
         // funded can always be accepted
@@ -1034,6 +1092,125 @@ mod tests {
         assert!(!acceptable.is_open());
     }
 
+    #[test]
+    fn test_error_codes() {
+        // former bare custom codes 0x10..0x17 moved to escrow range
+        assert_eq!(Error::RequestNotOpen as u32, 0x410);
+        assert_eq!(Error::RequestExpired as u32, 0x417);
+        assert_eq!(Error::RequestNotExpired as u32, 0x420);
+
+        let err: ProgramError = Error::ThresholdNotReached.into();
+        assert_eq!(
+            human_common::error::decode::<Error>(&err),
+            Some(Error::ThresholdNotReached)
+        );
+    }
+
+    #[test]
+    fn test_state_decode() {
+        // unfunded requests don't fill the account
+        let mut state = from_request(Unfunded(UnfundedRequest {
+            collected: 10,
+            deadline: Some(100),
+            accept_threshold: 1000,
+        }));
+
+        let mut data = vec![0; STATE_SIZE];
+        copy_slice(&mut data, &state.try_to_vec().unwrap());
+        assert_eq!(State::decode(&data).unwrap(), state);
+
+        state.request_status = RequestStatus::Unitialized;
+        copy_slice(&mut data, &state.try_to_vec().unwrap());
+        assert_eq!(
+            State::decode(&data).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+
+        // funded request with deadline is the largest one
+        let funded = from_request(Funded(FundedRequest {
+            author: Pubkey::new_unique(),
+            deadline: Some(100),
+        }));
+        assert_eq!(funded.try_to_vec().unwrap().len(), STATE_SIZE);
+
+        // legacy funded request ends right after the author
+        let mut legacy = from_request(Funded(FundedRequest {
+            author: Pubkey::new_unique(),
+            deadline: None,
+        }));
+        let mut data = legacy.try_to_vec().unwrap();
+        data.pop();
+        assert_eq!(data.len(), state::LEGACY_STATE_SIZE);
+        assert_eq!(State::decode(&data).unwrap(), legacy);
+
+        // and is written back at its size
+        legacy.request_status = RequestStatus::Declined;
+        legacy.encode(&mut data).unwrap();
+        assert_eq!(State::decode(&data).unwrap(), legacy);
+
+        // deadline doesn't fit
+        legacy.request = funded.request.clone();
+        assert_eq!(
+            legacy.encode(&mut data).unwrap_err(),
+            ProgramError::AccountDataTooSmall
+        );
+
+        // redeemed voucher
+        assert_eq!(Voucher::decode(&[0; state::VOUCHER_SIZE]), None);
+    }
+
+    #[test]
+    fn test_authority_address() {
+        let program_id = Pubkey::new_unique();
+        let state = Pubkey::new_unique();
+
+        let (addr, seeds) = authority!(&program_id, state);
+
+        assert_eq!(authority_address(&program_id, &state).0, addr);
+        assert_eq!(
+            Pubkey::create_program_address(seeds, &program_id).unwrap(),
+            addr
+        );
+    }
+
+    #[test]
+    fn test_state_address() {
+        let program_id = Pubkey::new_unique();
+        let dest = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let author = Pubkey::new_unique();
+
+        let mut args = CreateInstruction {
+            dest,
+            payer,
+            rtype: CreateInstructionRequest::Funded {
+                author,
+                deadline: None,
+            },
+            nonce: 0,
+        };
+
+        assert_eq!(
+            args.state_address(&program_id),
+            state_address(&program_id, &dest, &author, 0).0
+        );
+
+        args.nonce = 1;
+        assert_ne!(
+            args.state_address(&program_id),
+            state_address(&program_id, &dest, &author, 0).0
+        );
+
+        args.rtype = CreateInstructionRequest::Unfunded {
+            deadline: None,
+            accept_threshold: 10,
+        };
+        assert_eq!(
+            args.state_address(&program_id),
+            state_address(&program_id, &dest, &payer, 1).0
+        );
+    }
+
     fn from_request(request: Request) -> State {
         let s = State {
             request_status: RequestStatus::Open,
@@ -1060,18 +1237,30 @@ mod tests {
 
         let key = Pubkey::new_from_array([1; 32]);
         let pid = Pubkey::new_from_array([23; 32]);
+        let state_addr = Pubkey::new_from_array([66; 32]);
+        let user_addr = Pubkey::new_from_array([77; 32]);
+
+        // not created
+        {
+            let acc = AccountInfo::new(&key, false, true, &mut lamports, &mut [], &pid, false, 0);
+            assert!(get_voucher(&acc, &pid, &state_addr, &rent)
+                .unwrap()
+                .is_none());
+        }
 
         // not initialized
         {
             let acc = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &pid, false, 0);
-            assert!(get_voucher(&acc, &pid, &rent).unwrap().is_none());
+            assert!(get_voucher(&acc, &pid, &state_addr, &rent)
+                .unwrap()
+                .is_none());
         }
 
         {
             // invalid pid
             let acc = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &pid, false, 0);
             assert_eq!(
-                get_voucher(&acc, &Pubkey::default(), &rent).unwrap_err(),
+                get_voucher(&acc, &Pubkey::default(), &state_addr, &rent).unwrap_err(),
                 ProgramError::IncorrectProgramId
             );
         }
@@ -1082,26 +1271,67 @@ mod tests {
 
             let acc = AccountInfo::new(&key, false, true, &mut ins, &mut data, &pid, false, 0);
             assert_eq!(
-                get_voucher(&acc, &pid, &rent).unwrap_err(),
+                get_voucher(&acc, &pid, &state_addr, &rent).unwrap_err(),
                 ProgramError::AccountNotRentExempt
             );
         }
 
+        let v = Voucher {
+            state: state_addr,
+            user: user_addr,
+            amount: 123,
+        };
+
         {
-            // happy path
-            let state_addr = Pubkey::new_from_array([66; 32]);
-            let user_addr = Pubkey::new_from_array([77; 32]);
+            // issued for another state
+            let mut data = Voucher {
+                state: Pubkey::new_unique(),
+                ..v.clone()
+            }
+            .try_to_vec()
+            .unwrap();
 
-            let v = Voucher {
-                state: state_addr,
-                user: user_addr,
-                amount: 123,
-            };
+            let acc = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &pid, false, 0);
+            assert_eq!(
+                get_voucher(&acc, &pid, &state_addr, &rent).unwrap_err(),
+                Error::InvalidVoucherAddress.into()
+            );
+        }
 
+        {
+            // legacy voucher, not derived from state and user
             let mut data = v.try_to_vec().unwrap();
 
             let acc = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &pid, false, 0);
-            assert_eq!(get_voucher(&acc, &pid, &rent).unwrap().unwrap(), v);
+            assert_eq!(
+                get_voucher(&acc, &pid, &state_addr, &rent)
+                    .unwrap()
+                    .unwrap(),
+                v
+            );
+        }
+
+        {
+            // happy path
+            let (voucher_addr, _) = voucher_address(&pid, &state_addr, &user_addr);
+            let mut data = v.try_to_vec().unwrap();
+
+            let acc = AccountInfo::new(
+                &voucher_addr,
+                false,
+                true,
+                &mut lamports,
+                &mut data,
+                &pid,
+                false,
+                0,
+            );
+            assert_eq!(
+                get_voucher(&acc, &pid, &state_addr, &rent)
+                    .unwrap()
+                    .unwrap(),
+                v
+            );
         }
     }
 }
@@ -0,0 +1,182 @@
+use std::io;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{clock::UnixTimestamp, msg, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::error::Error;
+
+/// Size of request state accounts
+pub const STATE_SIZE: usize = 147;
+
+/// Size of state accounts created before funded requests had deadlines,
+/// funded ones end right after the author
+pub const LEGACY_STATE_SIZE: usize = 138;
+
+/// Size of voucher accounts
+pub const VOUCHER_SIZE: usize = 72;
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum Request {
+    /// Request with fixed amount
+    Funded(FundedRequest),
+    /// Request with variable amount
+    Unfunded(UnfundedRequest),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize)]
+pub struct FundedRequest {
+    // system account
+    pub author: Pubkey,
+    /// after deadline anyone can expire the request and refund the author.
+    /// Not stored in legacy states, see `LEGACY_STATE_SIZE`
+    pub deadline: Option<UnixTimestamp>,
+}
+
+impl BorshDeserialize for FundedRequest {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let author = Pubkey::deserialize(buf)?;
+
+        let deadline = if buf.is_empty() {
+            None
+        } else {
+            Option::deserialize(buf)?
+        };
+
+        Ok(Self { author, deadline })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct UnfundedRequest {
+    /// amount of token accumulated. needed in case of refund
+    pub collected: u64,
+    pub deadline: Option<UnixTimestamp>,
+    pub accept_threshold: u64,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, BorshDeserialize, BorshSerialize, PartialEq, Eq)]
+pub enum RequestStatus {
+    Unitialized,
+    Open,
+    Declined,
+    Accepted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct State {
+    pub request_status: RequestStatus,
+    /// the moment request was created, so later we could add logic to close stale requests
+    pub created_at: UnixTimestamp,
+    /// temporary token wallet
+    pub wallet: Pubkey,
+    /// system account. if accepted, where tokens would go
+    pub destination: Pubkey,
+    /// account to refund SOL from closing state
+    pub payer: Pubkey,
+    /// request body
+    pub request: Request,
+}
+
+#[derive(Debug, Clone, BorshDeserialize, BorshSerialize, PartialEq, Eq)]
+pub struct Voucher {
+    /// state address will be used for reverse RPC lookup (getProgramAccounts)
+    pub state: Pubkey,
+    /// user (again, for reverse lookup)
+    pub user: Pubkey,
+    /// amount contributed by user
+    pub amount: u64,
+}
+
+impl State {
+    /// Decodes state account data. Unfunded requests are shorter than the account,
+    /// trailing bytes are ignored
+    pub fn decode(data: &[u8]) -> Result<Self, ProgramError> {
+        let state = Self::deserialize(&mut &data[..])?;
+
+        if state.request_status == RequestStatus::Unitialized {
+            return Err(ProgramError::UninitializedAccount);
+        }
+
+        Ok(state)
+    }
+
+    /// Encodes state into account data without growing it. Legacy funded states
+    /// are only written without deadline, which they have no room for
+    pub fn encode(&self, data: &mut [u8]) -> Result<(), ProgramError> {
+        let mut encoded = self.try_to_vec()?;
+
+        if encoded.len() > data.len()
+            && matches!(
+                self.request,
+                Request::Funded(FundedRequest { deadline: None, .. })
+            )
+        {
+            // `None` tag of the deadline
+            encoded.pop();
+        }
+
+        if encoded.len() > data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        data[..encoded.len()].copy_from_slice(&encoded);
+
+        Ok(())
+    }
+
+    /// returns whether request is expired: funded after its deadline,
+    /// unfunded after its deadline if threshold is not reached
+    pub fn expired(&self, now: UnixTimestamp) -> bool {
+        if !self.is_open() {
+            return false;
+        }
+
+        match self.request {
+            Request::Funded(FundedRequest {
+                deadline: Some(deadline),
+                ..
+            }) => now > deadline,
+            Request::Unfunded(UnfundedRequest {
+                deadline: Some(deadline),
+                collected,
+                accept_threshold,
+            }) => now > deadline && collected < accept_threshold,
+            _ => false,
+        }
+    }
+
+    pub fn try_accept(&mut self) -> Result<(), ProgramError> {
+        if self.request_status != RequestStatus::Open {
+            return Error::RequestNotOpen.into();
+        }
+
+        if let Request::Unfunded(request) = &self.request {
+            if request.collected < request.accept_threshold {
+                msg!("not enough collected to accept");
+                return Error::ThresholdNotReached.into();
+            }
+        }
+
+        self.request_status = RequestStatus::Accepted;
+
+        Ok(())
+    }
+
+    pub fn is_funded(&self) -> bool {
+        matches!(self.request, Request::Funded(_))
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.request_status == RequestStatus::Open
+    }
+}
+
+impl Voucher {
+    /// Decodes voucher account data, `None` if the voucher is not issued or already redeemed
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        Self::deserialize(&mut &data[..])
+            .ok()
+            .filter(|voucher| voucher.amount > 0)
+    }
+}
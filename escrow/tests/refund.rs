@@ -0,0 +1,172 @@
+use borsh::BorshSerialize;
+use human_escrow::{
+    authority_address, instruction, process_instruction,
+    state::{Request, RequestStatus, State, UnfundedRequest, Voucher, STATE_SIZE, VOUCHER_SIZE},
+    voucher_address,
+};
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{signature::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::Account as TokenAccount;
+
+use solana_program_test::*;
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use common::{account, mint, token_account};
+
+fn padded(data: Vec<u8>, len: usize) -> Vec<u8> {
+    let mut padded = data;
+    padded.resize(len, 0);
+    padded
+}
+
+async fn process(ctx: &mut ProgramTestContext, ix: Instruction) {
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&ctx.payer.pubkey()),
+        &[&ctx.payer],
+        ctx.last_blockhash,
+    );
+    ctx.banks_client.process_transaction(tx).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_refund_legacy_voucher() {
+    let program_id = Pubkey::new_unique();
+    let token = Pubkey::new_unique();
+    let user = Pubkey::new_unique();
+    let pda_user = Pubkey::new_unique();
+    let payer = Pubkey::new_unique();
+
+    // state and voucher created before they were derived from seeds
+    let state_addr = Pubkey::new_unique();
+    let voucher_addr = Pubkey::new_unique();
+    let wallet = Pubkey::new_unique();
+    let (authority, _) = authority_address(&program_id, &state_addr);
+
+    let state = State {
+        request_status: RequestStatus::Declined,
+        created_at: 0,
+        wallet,
+        destination: Pubkey::new_unique(),
+        payer,
+        request: Request::Unfunded(UnfundedRequest {
+            collected: 150,
+            deadline: None,
+            accept_threshold: 0,
+        }),
+    };
+    let voucher = Voucher {
+        state: state_addr,
+        user,
+        amount: 100,
+    };
+
+    let mut pt = ProgramTest::new("human_escrow", program_id, processor!(process_instruction));
+    pt.add_program(
+        "spl_token",
+        spl_token::ID,
+        processor!(spl_token::processor::Processor::process),
+    );
+    pt.add_account(token, mint(150));
+    pt.add_account(wallet, token_account(&token, &authority, 150));
+    for user in [user, pda_user] {
+        pt.add_account(
+            get_associated_token_address(&user, &token),
+            token_account(&token, &user, 0),
+        );
+    }
+    pt.add_account(
+        state_addr,
+        account(program_id, padded(state.try_to_vec().unwrap(), STATE_SIZE)),
+    );
+    pt.add_account(
+        voucher_addr,
+        account(
+            program_id,
+            padded(voucher.try_to_vec().unwrap(), VOUCHER_SIZE),
+        ),
+    );
+    let pda_voucher = Voucher {
+        state: state_addr,
+        user: pda_user,
+        amount: 50,
+    };
+    pt.add_account(
+        voucher_address(&program_id, &state_addr, &pda_user).0,
+        account(
+            program_id,
+            padded(pda_voucher.try_to_vec().unwrap(), VOUCHER_SIZE),
+        ),
+    );
+    pt.add_account(payer, account(solana_program::system_program::ID, vec![]));
+
+    let mut ctx = pt.start_with_context().await;
+
+    let ix = instruction::refund_vouchers(
+        &program_id,
+        &state_addr,
+        &wallet,
+        &token,
+        &payer,
+        &[(voucher_addr, user)],
+    );
+    process(&mut ctx, ix).await;
+
+    // state is kept until every voucher is refunded
+    let state_acc = ctx
+        .banks_client
+        .get_account(state_addr)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        State::decode(&state_acc.data).unwrap().request,
+        Request::Unfunded(UnfundedRequest {
+            collected: 50,
+            deadline: None,
+            accept_threshold: 0,
+        })
+    );
+    assert!(ctx
+        .banks_client
+        .get_account(voucher_addr)
+        .await
+        .unwrap()
+        .is_none());
+
+    let ix = instruction::refund(
+        &program_id,
+        &state_addr,
+        &wallet,
+        &token,
+        &payer,
+        &[pda_user],
+    );
+    process(&mut ctx, ix).await;
+
+    for (user, amount) in [(user, 100), (pda_user, 50)] {
+        let user_wallet = ctx
+            .banks_client
+            .get_account(get_associated_token_address(&user, &token))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            TokenAccount::unpack(&user_wallet.data).unwrap().amount,
+            amount
+        );
+    }
+
+    // wallet and state are closed once everything is refunded
+    for closed in [wallet, state_addr] {
+        assert!(ctx
+            .banks_client
+            .get_account(closed)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -16,4 +16,18 @@ pub enum FanoutError {
     NonZeroBalance,
     #[msg("Close authority is not the signer")]
     InvalidCloseAuthority,
+    #[msg("Fanout was created by older version and has to be migrated")]
+    MigrationRequired,
+    #[msg("Fanout is a member of itself through nested fanouts")]
+    NestingCycle,
+    #[msg("Invalid page member count, should be between 1 and 32")]
+    InvalidPageSize,
+    #[msg("Page does not belong to the fanout")]
+    InvalidPage,
+    #[msg("Page was already distributed in current epoch")]
+    PageAlreadyDistributed,
+    #[msg("Nothing to distribute")]
+    NothingToDistribute,
+    #[msg("Update authority is not the signer")]
+    InvalidUpdateAuthority,
 }
@@ -0,0 +1,12 @@
+use anchor_lang::prelude::*;
+
+use crate::state::Member;
+
+/// Emitted when fanout is created and every time its members change,
+/// so splits in effect at any point can be reconstructed
+#[event]
+pub struct MembersUpdatedEvent {
+    pub fanout: Pubkey,
+    pub members_version: u32,
+    pub members: Vec<Member>,
+}
@@ -4,93 +4,588 @@
 #![allow(clippy::result_large_err)]
 
 pub mod error;
+pub mod event;
 pub mod state;
 
 use crate::error::FanoutError;
+use crate::event::*;
 use crate::state::*;
 
-use anchor_lang::{prelude::*, AnchorDeserialize, AnchorSerialize};
-use std::collections::HashSet;
+use anchor_lang::{prelude::*, AnchorDeserialize, AnchorSerialize, Discriminator};
+use anchor_spl::{
+    associated_token::{get_associated_token_address, AssociatedToken},
+    token::{self, Mint, Token, TokenAccount},
+};
+use std::collections::{HashMap, HashSet};
 
 declare_id!("FanoutYvahiZsDeFSjDmJymY12EZ6poVH1LydbJrLTRq");
 
+/// PDA owning token vaults of a fanout
+pub const AUTHORITY_SEED: &[u8] = b"AUTH";
+
+pub fn authority_address(fanout: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AUTHORITY_SEED, fanout.as_ref()], &crate::ID)
+}
+
+/// token vault of a fanout for given mint, send tokens here to have them fanned out
+pub fn vault_address(fanout: &Pubkey, mint: &Pubkey) -> Pubkey {
+    let (authority, _) = authority_address(fanout);
+    get_associated_token_address(&authority, mint)
+}
+
+/// PDA of a paged fanout member page
+pub const PAGE_SEED: &[u8] = b"PAGE";
+
+pub fn page_address(fanout: &Pubkey, index: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PAGE_SEED, fanout.as_ref(), &index.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// how many levels of member fanouts `distribute` cascades into
+pub const MAX_NESTING_DEPTH: usize = 3;
+
+/// how many member fanouts one `distribute` cascades into, keeps it within compute budget.
+/// Fanouts left out keep their lamports until the next distribute
+pub const MAX_NESTED_FANOUTS: usize = 8;
+
 #[program]
 pub mod fanout {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>, members: Vec<Member>) -> Result<()> {
-        if members.is_empty() || members.len() > 10 {
-            return err!(FanoutError::InvalidMemberCount);
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        members: Vec<Member>,
+        close_authority: Option<Pubkey>,
+        update_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        Fanout::check_members(&members)?;
+
+        emit!(MembersUpdatedEvent {
+            fanout: ctx.accounts.fanout.key(),
+            members_version: 0,
+            members: members.clone(),
+        });
+
+        ctx.accounts
+            .fanout
+            .set_inner(Fanout::new(members, close_authority, update_authority));
+        Ok(())
+    }
+
+    /// Pays member their share of everything fanout received so far.
+    /// Anyone can call it, lamports only go to member address
+    pub fn claim(ctx: Context<Claim>) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+        let fanout_acc = fanout.to_account_info();
+
+        let total_inflow = fanout.total_inflow(available_lamports(&fanout_acc)?)?;
+
+        let amount = fanout.claim(ctx.accounts.member.key, total_inflow)?;
+
+        transfer_lamports(&fanout_acc, &ctx.accounts.member.to_account_info(), amount)
+    }
+
+    /// Convenience crank: claims for every member passed in remaining accounts.
+    /// Members that are not passed can claim later.
+    /// Members that are fanouts themselves are distributed in turn to their members
+    /// passed in the same remaining accounts, up to `MAX_NESTING_DEPTH` levels deep
+    pub fn distribute(ctx: Context<Distribute>) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+        let fanout_acc = fanout.to_account_info();
+        let accounts = ctx.remaining_accounts;
+
+        check_unique(accounts)?;
+
+        let mut cascade = Cascade {
+            path: vec![fanout_acc.key()],
+            nested_left: MAX_NESTED_FANOUTS,
+            matched: HashSet::new(),
+        };
+        cascade.distribute(fanout, &fanout_acc, accounts)?;
+
+        // every account has to be a member somewhere in the tree
+        if cascade.matched.len() != accounts.len() {
+            return err!(FanoutError::MemberNotFound);
         }
 
-        // check if shares in members add up to 10000
-        let mut total_shares = 0;
-        for member in members.iter() {
-            if member.share == 0 || member.share > 10000 {
-                return Err(FanoutError::InvalidShares.into());
-            }
-            total_shares += member.share as usize;
+        Ok(())
+    }
+
+    /// Sets new close authority, `None` makes fanout impossible to close.
+    /// Fanouts that predate close authority get their largest member on migration
+    pub fn set_close_authority(
+        ctx: Context<SetCloseAuthority>,
+        close_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+
+        if fanout.close_authority != Some(ctx.accounts.authority.key()) {
+            return err!(FanoutError::InvalidCloseAuthority);
         }
 
-        if total_shares != 10000 {
-            return Err(FanoutError::InvalidShares.into());
+        fanout.close_authority = close_authority;
+
+        Ok(())
+    }
+
+    /// Sets new update authority, `None` makes members immutable for good
+    pub fn set_update_authority(
+        ctx: Context<SetUpdateAuthority>,
+        update_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+
+        if fanout.update_authority != Some(ctx.accounts.update_authority.key()) {
+            return err!(FanoutError::InvalidUpdateAuthority);
         }
 
-        ctx.accounts.fanout.set_inner(Fanout {
-            distributed: 0,
-            members,
+        fanout.update_authority = update_authority;
+
+        Ok(())
+    }
+
+    /// Replaces members. Balance is first paid out under the old split,
+    /// so all old members have to be passed in remaining accounts.
+    /// Every token vault of the fanout has to be passed too and be empty, distribute them first
+    pub fn update_members(ctx: Context<UpdateMembers>, members: Vec<Member>) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+
+        if fanout.update_authority != Some(ctx.accounts.update_authority.key()) {
+            return err!(FanoutError::InvalidUpdateAuthority);
+        }
+
+        Fanout::check_members(&members)?;
+
+        let fanout_acc = fanout.to_account_info();
+        let old_members = skip_empty_vaults(fanout_acc.key, ctx.remaining_accounts)?;
+
+        let all_passed = fanout
+            .members
+            .iter()
+            .all(|m| old_members.iter().any(|acc| *acc.key == m.address));
+        if !all_passed {
+            return err!(FanoutError::MemberNotFound);
+        }
+
+        claim_for_accounts(fanout, &fanout_acc, &old_members)?;
+
+        // rounding dust stays and goes to new members
+        fanout.set_members(members)?;
+
+        // size depends on number of members, authority pays or gets back rent difference
+        let rent = Rent::get()?;
+        let space = Fanout::space(fanout.members.len());
+        let old_rent = rent.minimum_balance(fanout_acc.data_len());
+        let new_rent = rent.minimum_balance(space);
+
+        if new_rent > old_rent {
+            let ix = anchor_lang::solana_program::system_instruction::transfer(
+                ctx.accounts.update_authority.key,
+                fanout_acc.key,
+                new_rent - old_rent,
+            );
+            anchor_lang::solana_program::program::invoke(
+                &ix,
+                &[
+                    ctx.accounts.update_authority.to_account_info(),
+                    fanout_acc.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        } else {
+            transfer_lamports(
+                &fanout_acc,
+                &ctx.accounts.update_authority.to_account_info(),
+                old_rent - new_rent,
+            )?;
+        }
+
+        fanout_acc.realloc(space, false)?;
+
+        emit!(MembersUpdatedEvent {
+            fanout: fanout_acc.key(),
+            members_version: fanout.members_version,
+            members: fanout.members.clone(),
         });
+
         Ok(())
     }
 
-    pub fn distribute(ctx: Context<Distribute>) -> Result<()> {
+    /// Closes fanout and sends its rent to destination.
+    /// Every token vault of the fanout has to be passed in remaining accounts and be empty,
+    /// distribute them first.
+    /// If there is anything left to distribute, all members have to be passed in remaining
+    /// accounts too: they get what they can claim, and rounding dust is split between them
+    pub fn close(ctx: Context<Close>) -> Result<()> {
         let fanout = &mut ctx.accounts.fanout;
 
+        if fanout.close_authority != Some(ctx.accounts.close_authority.key()) {
+            return err!(FanoutError::InvalidCloseAuthority);
+        }
+
         let fanout_acc = fanout.to_account_info();
-        let mut fanout_lamports = fanout_acc.try_borrow_mut_lamports()?;
+        let members = skip_empty_vaults(fanout_acc.key, ctx.remaining_accounts)?;
+
+        if available_lamports(&fanout_acc)? > 0 {
+            claim_for_accounts(fanout, &fanout_acc, &members)?;
+
+            let dust = available_lamports(&fanout_acc)?;
+            let split = fanout
+                .calculate_split(dust)
+                .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+            for (member, amount) in split {
+                let acc = members
+                    .iter()
+                    .find(|acc| *acc.key == member)
+                    .ok_or_else(|| error!(FanoutError::NonZeroBalance))?;
+
+                transfer_lamports(&fanout_acc, acc, amount)?;
+            }
+        }
+
+        if available_lamports(&fanout_acc)? != 0 {
+            return err!(FanoutError::NonZeroBalance);
+        }
 
-        // subtract the rent exemption
-        let rent_minimum = Rent::get()?.minimum_balance(fanout_acc.data_len());
+        // rent goes to destination with `close` constraint
+        Ok(())
+    }
+
+    /// Creates token vault of the fanout for a mint
+    pub fn init_vault(_ctx: Context<InitVault>) -> Result<()> {
+        Ok(())
+    }
 
-        let distribution_amount = fanout_lamports
-            .checked_sub(rent_minimum)
-            .ok_or(FanoutError::Overflow)?;
+    /// Splits whole vault balance by member shares, same as lamports in `calculate_split`.
+    /// Remaining accounts are token accounts of every member, associated with the vault mint
+    pub fn distribute_token<'info>(
+        ctx: Context<'_, '_, '_, 'info, DistributeToken<'info>>,
+    ) -> Result<()> {
+        let fanout = &ctx.accounts.fanout;
+        let mint = ctx.accounts.mint.key();
 
         let split = fanout
-            .calculate_split(distribution_amount)
+            .calculate_split(ctx.accounts.vault.amount)
             .ok_or_else(|| error!(FanoutError::Overflow))?;
 
-        let mut seen = HashSet::new();
+        // member may appear more than once, sum up their parts
+        let mut to_send: HashMap<Pubkey, u64> = HashMap::new();
+        for (member, amount) in split {
+            let wallet = get_associated_token_address(&member, &mint);
+            let total = to_send.entry(wallet).or_default();
+            *total = total
+                .checked_add(amount)
+                .ok_or_else(|| error!(FanoutError::Overflow))?;
+        }
+
+        let fanout_key = fanout.key();
+        let authority_seeds: &[&[&[u8]]] = &[&[
+            AUTHORITY_SEED,
+            fanout_key.as_ref(),
+            &[*ctx.bumps.get("authority").unwrap()],
+        ]];
 
-        // increment lamports of each remaining account
         for acc in ctx.remaining_accounts.iter() {
-            if !seen.insert(acc.key) {
-                return err!(FanoutError::DuplicateMember);
+            let amount = to_send
+                .remove(acc.key)
+                .ok_or_else(|| error!(FanoutError::MemberNotFound))?;
+
+            let cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: acc.clone(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+                authority_seeds,
+            );
+            token::transfer(cpi, amount)?;
+        }
+
+        // every member wallet has to be passed exactly once
+        if !to_send.is_empty() {
+            return err!(FanoutError::MemberNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Creates fanout with members spread over pages, for more members than fit in `Fanout`.
+    /// Authority adds pages with `add_page` until shares add up to 10000
+    pub fn initialize_paged(ctx: Context<InitializePaged>, authority: Pubkey) -> Result<()> {
+        ctx.accounts.fanout.set_inner(PagedFanout {
+            authority,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    /// Creates next page of paged fanout, up to `PAGE_SIZE` members
+    pub fn add_page(ctx: Context<AddPage>, members: Vec<Member>) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+
+        let index = fanout.add_page(&members)?;
+
+        ctx.accounts.page.set_inner(FanoutPage {
+            fanout: fanout.key(),
+            index,
+            epoch: fanout.epoch,
+            members,
+        });
+
+        Ok(())
+    }
+
+    /// Pays every member of a page their share of current epoch.
+    /// First call of an epoch fixes the amount: balance of fanout at that moment.
+    /// Remaining accounts are members of the page, in order
+    pub fn distribute_page(ctx: Context<DistributePage>) -> Result<()> {
+        let fanout = &mut ctx.accounts.fanout;
+        let fanout_acc = fanout.to_account_info();
+
+        fanout.start_distribution(available_lamports(&fanout_acc)?)?;
+
+        let parts = fanout.distribute_page(&mut ctx.accounts.page)?;
+
+        if parts.len() != ctx.remaining_accounts.len() {
+            return err!(FanoutError::MemberNotFound);
+        }
+
+        for ((member, amount), acc) in parts.into_iter().zip(ctx.remaining_accounts) {
+            if *acc.key != member {
+                return err!(FanoutError::MemberNotFound);
             }
 
-            let amount = *split.get(acc.key).unwrap();
-            let mut acc_lamports = acc.try_borrow_mut_lamports()?;
+            transfer_lamports(&fanout_acc, acc, amount)?;
+        }
 
-            **acc_lamports = acc_lamports
-                .checked_add(amount)
-                .ok_or_else(|| error!(FanoutError::Overflow))?;
+        if fanout.distribution.is_none() {
+            msg!("epoch {} completed", fanout.epoch);
+        }
 
-            **fanout_lamports = fanout_lamports
-                .checked_sub(amount)
-                .ok_or_else(|| error!(FanoutError::Overflow))?;
+        Ok(())
+    }
+
+    /// Rewrites fanout created by older version of the program into current layout
+    pub fn migrate(ctx: Context<Migrate>) -> Result<()> {
+        let fanout_acc = &ctx.accounts.fanout;
+
+        let (fanout, migrated) = {
+            let data = fanout_acc.try_borrow_data()?;
+
+            if data.len() < 8 || data[..8] != Fanout::discriminator() {
+                return err!(ErrorCode::AccountDiscriminatorMismatch);
+            }
+
+            Fanout::read_versioned(&data[8..])?
+        };
 
-            fanout.distributed += amount;
+        if !migrated {
+            msg!("fanout is already migrated");
+            return Ok(());
         }
 
-        // make sure no account is missing or placed more than once
-        if seen.len() != fanout.members.len() {
-            return err!(FanoutError::MemberNotFound);
+        let rent = Rent::get()?;
+        let space = Fanout::space(fanout.members.len()).max(fanout_acc.data_len());
+
+        // new layout is larger, payer covers extra rent
+        let extra_rent = rent
+            .minimum_balance(space)
+            .saturating_sub(rent.minimum_balance(fanout_acc.data_len()));
+
+        if extra_rent > 0 {
+            let ix = anchor_lang::solana_program::system_instruction::transfer(
+                ctx.accounts.payer.key,
+                fanout_acc.key,
+                extra_rent,
+            );
+            anchor_lang::solana_program::program::invoke(
+                &ix,
+                &[
+                    ctx.accounts.payer.to_account_info(),
+                    fanout_acc.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        fanout_acc.realloc(space, false)?;
+
+        let mut data = fanout_acc.try_borrow_mut_data()?;
+        fanout.try_serialize(&mut data.as_mut())?;
+
+        Ok(())
+    }
+}
+
+/// claims for every member passed in accounts, once per member
+fn claim_for_accounts(
+    fanout: &mut Fanout,
+    fanout_acc: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> Result<()> {
+    check_unique(accounts)?;
+
+    let total_inflow = fanout.total_inflow(available_lamports(fanout_acc)?)?;
+
+    for acc in accounts.iter() {
+        let amount = fanout.claim(acc.key, total_inflow)?;
+
+        transfer_lamports(fanout_acc, acc, amount)?;
+    }
+
+    Ok(())
+}
+
+/// Returns accounts that are not token vaults of the fanout.
+/// Vaults have to be empty: their balance can't be split once fanout changes
+fn skip_empty_vaults<'info>(
+    fanout: &Pubkey,
+    accounts: &[AccountInfo<'info>],
+) -> Result<Vec<AccountInfo<'info>>> {
+    let (authority, _) = authority_address(fanout);
+    let mut rest = Vec::with_capacity(accounts.len());
+
+    for acc in accounts.iter() {
+        let vault = if acc.owner == &token::ID {
+            TokenAccount::try_deserialize(&mut &acc.try_borrow_data()?[..]).ok()
+        } else {
+            None
+        };
+
+        let vault = match vault {
+            Some(vault) if vault.owner == authority => vault,
+            _ => {
+                rest.push(acc.clone());
+                continue;
+            }
+        };
+
+        if vault.amount != 0 {
+            msg!("token vault {} is not empty, distribute it first", acc.key);
+            return err!(FanoutError::NonZeroBalance);
+        }
+    }
+
+    Ok(rest)
+}
+
+fn check_unique(accounts: &[AccountInfo]) -> Result<()> {
+    let mut seen = HashSet::new();
+
+    for acc in accounts.iter() {
+        if !seen.insert(acc.key) {
+            return err!(FanoutError::DuplicateMember);
+        }
+    }
+
+    Ok(())
+}
+
+/// State of a distribution cascading through member fanouts
+struct Cascade {
+    /// fanouts being distributed, from the root down
+    path: Vec<Pubkey>,
+    nested_left: usize,
+    /// accounts that were found among members of some fanout
+    matched: HashSet<Pubkey>,
+}
+
+impl Cascade {
+    /// claims for members of fanout found in accounts, then distributes member fanouts
+    fn distribute(
+        &mut self,
+        fanout: &mut Fanout,
+        fanout_acc: &AccountInfo,
+        accounts: &[AccountInfo],
+    ) -> Result<()> {
+        let total_inflow = fanout.total_inflow(available_lamports(fanout_acc)?)?;
+
+        for acc in accounts.iter() {
+            if !fanout.members.iter().any(|m| m.address == *acc.key) {
+                continue;
+            }
+
+            self.matched.insert(*acc.key);
+
+            let amount = fanout.claim(acc.key, total_inflow)?;
+            transfer_lamports(fanout_acc, acc, amount)?;
+
+            if !is_fanout(acc) {
+                continue;
+            }
+
+            if self.path.contains(acc.key) {
+                return err!(FanoutError::NestingCycle);
+            }
+
+            if self.path.len() > MAX_NESTING_DEPTH || self.nested_left == 0 {
+                msg!(
+                    "not cascading into fanout {}, distribute it separately",
+                    acc.key
+                );
+                continue;
+            }
+
+            let (mut nested, migrated) = Fanout::read_versioned(&acc.try_borrow_data()?[8..])?;
+            if migrated {
+                msg!("not cascading into fanout {}, migrate it first", acc.key);
+                continue;
+            }
+
+            self.nested_left -= 1;
+            self.path.push(*acc.key);
+            self.distribute(&mut nested, acc, accounts)?;
+            self.path.pop();
+
+            let mut data = acc.try_borrow_mut_data()?;
+            nested.try_serialize(&mut data.as_mut())?;
         }
 
         Ok(())
     }
 }
 
+/// writable fanout account of this program
+fn is_fanout(acc: &AccountInfo) -> bool {
+    if acc.owner != &crate::ID || !acc.is_writable {
+        return false;
+    }
+
+    match acc.try_borrow_data() {
+        Ok(data) => data.len() >= 8 && data[..8] == Fanout::discriminator(),
+        Err(_) => false,
+    }
+}
+
+/// fanout balance above rent exemption
+fn available_lamports(fanout_acc: &AccountInfo) -> Result<u64> {
+    let rent_minimum = Rent::get()?.minimum_balance(fanout_acc.data_len());
+
+    fanout_acc
+        .lamports()
+        .checked_sub(rent_minimum)
+        .ok_or_else(|| error!(FanoutError::Overflow))
+}
+
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    let mut from_lamports = from.try_borrow_mut_lamports()?;
+    let mut to_lamports = to.try_borrow_mut_lamports()?;
+
+    **from_lamports = from_lamports
+        .checked_sub(amount)
+        .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+    **to_lamports = to_lamports
+        .checked_add(amount)
+        .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+    Ok(())
+}
+
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     /// allows account to be created at any address (e.g. via CreateAccountWithSeed)
@@ -103,3 +598,125 @@ pub struct Distribute<'info> {
     #[account(mut)]
     pub fanout: Account<'info, Fanout>,
 }
+
+#[derive(Accounts)]
+pub struct Claim<'info> {
+    #[account(mut)]
+    pub fanout: Account<'info, Fanout>,
+    /// CHECK: checked to be one of the members, only receives lamports
+    #[account(mut)]
+    pub member: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Migrate<'info> {
+    /// CHECK: old layout can't be loaded as `Account<Fanout>`, checked by discriminator
+    #[account(mut, owner = crate::ID)]
+    pub fanout: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitVault<'info> {
+    pub fanout: Account<'info, Fanout>,
+    /// CHECK: seeds are checked
+    #[account(seeds = [AUTHORITY_SEED, fanout.key().as_ref()], bump)]
+    pub authority: UncheckedAccount<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = authority,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeToken<'info> {
+    pub fanout: Account<'info, Fanout>,
+    /// CHECK: seeds are checked
+    #[account(seeds = [AUTHORITY_SEED, fanout.key().as_ref()], bump)]
+    pub authority: UncheckedAccount<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = authority,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePaged<'info> {
+    #[account(zero)]
+    pub fanout: Account<'info, PagedFanout>,
+}
+
+#[derive(Accounts)]
+#[instruction(members: Vec<Member>)]
+pub struct AddPage<'info> {
+    #[account(mut, has_one = authority)]
+    pub fanout: Account<'info, PagedFanout>,
+    #[account(
+        init,
+        payer = authority,
+        space = FanoutPage::space(members.len()),
+        seeds = [PAGE_SEED, fanout.key().as_ref(), &fanout.page_count.to_le_bytes()],
+        bump,
+    )]
+    pub page: Account<'info, FanoutPage>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DistributePage<'info> {
+    #[account(mut)]
+    pub fanout: Account<'info, PagedFanout>,
+    #[account(mut, has_one = fanout)]
+    pub page: Account<'info, FanoutPage>,
+}
+
+#[derive(Accounts)]
+pub struct SetCloseAuthority<'info> {
+    #[account(mut)]
+    pub fanout: Account<'info, Fanout>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetUpdateAuthority<'info> {
+    #[account(mut)]
+    pub fanout: Account<'info, Fanout>,
+    pub update_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMembers<'info> {
+    #[account(mut)]
+    pub fanout: Account<'info, Fanout>,
+    #[account(mut)]
+    pub update_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Close<'info> {
+    #[account(mut, close = destination)]
+    pub fanout: Account<'info, Fanout>,
+    pub close_authority: Signer<'info>,
+    /// CHECK: only receives rent
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+}
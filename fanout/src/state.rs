@@ -1,43 +1,236 @@
-use std::collections::HashMap;
-
 use anchor_lang::prelude::*;
-use spl_math::precise_number::PreciseNumber;
+use checked_math::{mul_div_ceil, mul_div_floor, Bps};
+use human_common::split::{Remainder, SplitPlan};
+
+use crate::error::FanoutError;
+
+/// bumped when fields are appended after `members`
+pub const FANOUT_VERSION: u8 = 3;
 
 #[account]
 #[derive(Default)]
 pub struct Fanout {
-    /// some basic stats
+    /// total lamports paid out to members
+    pub distributed: u64,
+    pub members: Vec<Member>,
+    /// 0 means account was created before claims and has to be migrated
+    pub version: u8,
+    /// lamports paid to each member so far, same order as members
+    pub claimed: Vec<u64>,
+    /// can close fanout and take its rent, since version 2
+    pub close_authority: Option<Pubkey>,
+    /// can replace members, since version 3. Fanouts without one are immutable
+    pub update_authority: Option<Pubkey>,
+    /// bumped on every members update, see `MembersUpdatedEvent`
+    pub members_version: u32,
+}
+
+/// Fanout as it was stored before claims were introduced.
+/// Later versions append fields after it
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FanoutV0 {
     pub distributed: u64,
     pub members: Vec<Member>,
 }
 
 impl Fanout {
-    pub fn calculate_split(&self, amount: u64) -> Option<HashMap<Pubkey, u64>> {
-        let bsp: PreciseNumber = PreciseNumber::new(10_000).unwrap();
+    pub fn space(members: usize) -> usize {
+        8 + 8 + (4 + (32 + 2) * members) + 1 + (4 + 8 * members) + (1 + 32) + (1 + 32) + 4
+    }
+
+    pub fn new(
+        members: Vec<Member>,
+        close_authority: Option<Pubkey>,
+        update_authority: Option<Pubkey>,
+    ) -> Self {
+        Self {
+            distributed: 0,
+            claimed: vec![0; members.len()],
+            members,
+            version: FANOUT_VERSION,
+            close_authority,
+            update_authority,
+            members_version: 0,
+        }
+    }
+
+    /// between 1 and 10 distinct members with non-zero shares adding up to 10000
+    pub fn check_members(members: &[Member]) -> Result<()> {
+        if members.is_empty() || members.len() > 10 {
+            return err!(FanoutError::InvalidMemberCount);
+        }
+
+        // check if shares in members add up to 10000
+        let mut total_shares = 0;
+        for (i, member) in members.iter().enumerate() {
+            if member.share == 0 || member.share > 10000 {
+                return Err(FanoutError::InvalidShares.into());
+            }
+
+            if members[..i].iter().any(|m| m.address == member.address) {
+                return err!(FanoutError::DuplicateMember);
+            }
+            total_shares += member.share as usize;
+        }
+
+        if total_shares != 10000 {
+            return Err(FanoutError::InvalidShares.into());
+        }
+
+        Ok(())
+    }
+
+    /// Replaces members and starts accounting from scratch.
+    /// Everything claimable under old members has to be paid out before
+    pub fn set_members(&mut self, members: Vec<Member>) -> Result<()> {
+        self.members_version = self
+            .members_version
+            .checked_add(1)
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        self.distributed = 0;
+        self.claimed = vec![0; members.len()];
+        self.members = members;
+
+        Ok(())
+    }
+
+    /// Reads fanout of any version (data after discriminator) and upgrades it.
+    /// Returns whether it was upgraded
+    pub fn read_versioned(data: &[u8]) -> Result<(Self, bool)> {
+        let mut rest = data;
+        let old = FanoutV0::deserialize(&mut rest)?;
+
+        // zero padded accounts created before versioning read as version 0
+        let version = rest.first().copied().unwrap_or(0);
+
+        match version {
+            0 => Ok((Self::from_v0(old)?, true)),
+            1 | 2 => {
+                rest = &rest[1..];
+                let claimed = Vec::<u64>::deserialize(&mut rest)?;
+
+                if claimed.len() != old.members.len() {
+                    return err!(FanoutError::MigrationRequired);
+                }
+
+                let mut fanout = Self {
+                    distributed: old.distributed,
+                    members: old.members,
+                    version: FANOUT_VERSION,
+                    claimed,
+                    close_authority: None,
+                    update_authority: None,
+                    members_version: 0,
+                };
+
+                fanout.close_authority = match version {
+                    2 => Option::<Pubkey>::deserialize(&mut rest)?,
+                    _ => fanout.adoptive_authority(),
+                };
+
+                Ok((fanout, true))
+            }
+            FANOUT_VERSION => Ok((Self::deserialize(&mut &data[..])?, false)),
+            _ => err!(FanoutError::MigrationRequired),
+        }
+    }
+
+    /// Old fanouts paid every member on each distribution, so checkpoints start at
+    /// what member was entitled to, rounded up: we'd rather keep a lamport than pay it twice
+    fn from_v0(old: FanoutV0) -> Result<Self> {
+        let claimed = old
+            .members
+            .iter()
+            .map(|m| mul_div_ceil(old.distributed, m.share as u64, Bps::DENOMINATOR as u64))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        let mut fanout = Self {
+            distributed: old.distributed,
+            members: old.members,
+            version: FANOUT_VERSION,
+            claimed,
+            close_authority: None,
+            update_authority: None,
+            members_version: 0,
+        };
+
+        fanout.close_authority = fanout.adoptive_authority();
+
+        Ok(fanout)
+    }
+
+    /// Close authority given to fanouts that predate it: member with the largest share.
+    /// Fanouts created without one keep none
+    pub fn adoptive_authority(&self) -> Option<Pubkey> {
+        // max_by_key returns last of equal elements, prefer first one
+        self.members
+            .iter()
+            .rev()
+            .max_by_key(|m| m.share)
+            .map(|m| m.address)
+    }
 
-        let amount_precise = PreciseNumber::new(amount as u128)?;
+    /// each member gets (amount * share/10000), rounding dust goes to largest remainders
+    pub fn split_plan(&self) -> Option<SplitPlan> {
+        self.members
+            .iter()
+            .try_fold(SplitPlan::new(Remainder::Proportional), |plan, m| {
+                Some(plan.with_share(m.address, Bps::try_new(m.share)?))
+            })
+    }
 
-        // each member gets (amount * share/10000)
-        let mut split: HashMap<Pubkey, u64> = HashMap::new();
+    pub fn calculate_split(&self, amount: u64) -> Option<Vec<(Pubkey, u64)>> {
+        self.split_plan()?.split(amount)
+    }
 
-        for m in self.members.iter() {
-            let share = PreciseNumber::new(m.share as u128)?;
-            let share_amount = share.checked_mul(&amount_precise)?.checked_div(&bsp)?;
+    /// everything fanout ever received: current balance above rent plus what was paid out
+    pub fn total_inflow(&self, available: u64) -> Result<u64> {
+        available
+            .checked_add(self.distributed)
+            .ok_or_else(|| error!(FanoutError::Overflow))
+    }
 
-            split.insert(m.address, share_amount.to_imprecise()?.try_into().ok()?);
+    /// Marks member's share of `total_inflow` as paid and returns amount to transfer.
+    /// Each member is entitled to share of total inflow rounded down,
+    /// so claims in any order never exceed what fanout received
+    pub fn claim(&mut self, member: &Pubkey, total_inflow: u64) -> Result<u64> {
+        if self.claimed.len() != self.members.len() {
+            return err!(FanoutError::MigrationRequired);
         }
 
-        // fix differences due to rounding
-        let total: u64 = split.values().sum();
-        let diff = amount as i64 - total as i64;
+        let mut found = false;
+        let mut amount: u64 = 0;
+
+        for (m, claimed) in self.members.iter().zip(self.claimed.iter_mut()) {
+            if m.address != *member {
+                continue;
+            }
+
+            found = true;
+
+            let entitled = mul_div_floor(total_inflow, m.share as u64, Bps::DENOMINATOR as u64)
+                .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+            let to_pay = entitled.saturating_sub(*claimed);
 
-        if diff != 0 {
-            // some lucky member gets one less or one more lamport
-            let v = split.get_mut(&self.members[0].address).unwrap();
-            *v = (*v as i64).checked_add(diff)?.try_into().ok()?;
+            *claimed = entitled.max(*claimed);
+            amount = amount
+                .checked_add(to_pay)
+                .ok_or_else(|| error!(FanoutError::Overflow))?;
         }
 
-        Some(split)
+        if !found {
+            return err!(FanoutError::MemberNotFound);
+        }
+
+        self.distributed = self
+            .distributed
+            .checked_add(amount)
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        Ok(amount)
     }
 }
 
@@ -47,6 +240,172 @@ pub struct Member {
     pub share: u16,
 }
 
+/// most members a single page can hold
+pub const PAGE_SIZE: usize = 32;
+
+/// Header of a fanout with members spread over `FanoutPage` accounts.
+/// Balance is distributed in epochs: amount is fixed when epoch starts,
+/// then each page is paid once, possibly across several transactions
+#[account]
+#[derive(Default)]
+pub struct PagedFanout {
+    /// adds pages until member shares add up to 10000
+    pub authority: Pubkey,
+    pub page_count: u16,
+    /// shares of members in all pages
+    pub total_shares: u16,
+    /// number of completed distributions
+    pub epoch: u32,
+    pub distribution: Option<PageDistribution>,
+    /// total lamports paid out to members
+    pub distributed: u64,
+}
+
+#[derive(Default, Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize, PartialEq, Eq)]
+pub struct PageDistribution {
+    pub distribute_amount: u64,
+    pub seen_pages: u16,
+}
+
+/// Members of a paged fanout, PDA of `PAGE_SEED`, header and page index
+#[account]
+#[derive(Default)]
+pub struct FanoutPage {
+    pub fanout: Pubkey,
+    pub index: u16,
+    /// epoch this page is paid in next, lags behind header while it waits for its turn
+    pub epoch: u32,
+    pub members: Vec<Member>,
+}
+
+impl PagedFanout {
+    pub fn space() -> usize {
+        8 + 32 + 2 + 2 + 4 + (1 + 8 + 2) + 8
+    }
+
+    /// all pages are added and distribution can start
+    pub fn is_complete(&self) -> bool {
+        self.total_shares == Bps::DENOMINATOR
+    }
+
+    /// Accounts for members of the next page, returns its index
+    pub fn add_page(&mut self, members: &[Member]) -> Result<u16> {
+        if members.is_empty() || members.len() > PAGE_SIZE {
+            return err!(FanoutError::InvalidPageSize);
+        }
+
+        let mut total_shares = self.total_shares;
+        for member in members.iter() {
+            if member.share == 0 {
+                return err!(FanoutError::InvalidShares);
+            }
+
+            total_shares = total_shares
+                .checked_add(member.share)
+                .filter(|total| *total <= Bps::DENOMINATOR)
+                .ok_or_else(|| error!(FanoutError::InvalidShares))?;
+        }
+
+        let index = self.page_count;
+
+        self.total_shares = total_shares;
+        self.page_count = self
+            .page_count
+            .checked_add(1)
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        Ok(index)
+    }
+
+    /// Starts new epoch with `available` lamports unless one is in progress.
+    /// Lamports that arrive during distribution wait for the next epoch
+    pub fn start_distribution(&mut self, available: u64) -> Result<()> {
+        if self.distribution.is_some() {
+            return Ok(());
+        }
+
+        if !self.is_complete() {
+            return err!(FanoutError::InvalidShares);
+        }
+
+        if available == 0 {
+            return err!(FanoutError::NothingToDistribute);
+        }
+
+        self.distribution = Some(PageDistribution {
+            distribute_amount: available,
+            seen_pages: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Marks page as paid in current epoch and returns amount for each of its members.
+    /// Amounts are rounded down, dust is carried over to the next epoch
+    pub fn distribute_page(&mut self, page: &mut FanoutPage) -> Result<Vec<(Pubkey, u64)>> {
+        let mut distribution = self
+            .distribution
+            .ok_or_else(|| error!(FanoutError::NothingToDistribute))?;
+
+        if page.index >= self.page_count {
+            return err!(FanoutError::InvalidPage);
+        }
+
+        if page.epoch != self.epoch {
+            return err!(FanoutError::PageAlreadyDistributed);
+        }
+
+        let parts = page
+            .members
+            .iter()
+            .map(|m| {
+                let amount = mul_div_floor(
+                    distribution.distribute_amount,
+                    m.share as u64,
+                    Bps::DENOMINATOR as u64,
+                )?;
+                Some((m.address, amount))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        let total = parts
+            .iter()
+            .try_fold(0u64, |acc, (_, amount)| acc.checked_add(*amount))
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        self.distributed = self
+            .distributed
+            .checked_add(total)
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        page.epoch = page
+            .epoch
+            .checked_add(1)
+            .ok_or_else(|| error!(FanoutError::Overflow))?;
+
+        distribution.seen_pages += 1;
+
+        if distribution.seen_pages == self.page_count {
+            self.epoch = self
+                .epoch
+                .checked_add(1)
+                .ok_or_else(|| error!(FanoutError::Overflow))?;
+            self.distribution = None;
+        } else {
+            self.distribution = Some(distribution);
+        }
+
+        Ok(parts)
+    }
+}
+
+impl FanoutPage {
+    pub fn space(members: usize) -> usize {
+        8 + 32 + 2 + 4 + (4 + (32 + 2) * members)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,7 +435,222 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_claim() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut fanout = Fanout::new(
+            vec![
+                Member {
+                    address: a,
+                    share: 7500,
+                },
+                Member {
+                    address: b,
+                    share: 2500,
+                },
+            ],
+            None,
+            None,
+        );
+
+        assert_eq!(fanout.claim(&a, 1000).unwrap(), 750);
+        // nothing new came in
+        assert_eq!(fanout.claim(&a, 1000).unwrap(), 0);
+
+        // b still gets share of first 1000 after more lamports arrive
+        assert_eq!(fanout.claim(&b, 2000).unwrap(), 500);
+        assert_eq!(fanout.claim(&a, 2000).unwrap(), 750);
+        assert_eq!(fanout.distributed, 2000);
+
+        assert!(fanout.claim(&Pubkey::new_unique(), 2000).is_err());
+    }
+
+    #[test]
+    fn test_set_members() {
+        let members = members();
+        let mut fanout = Fanout::new(members.clone(), None, None);
+
+        fanout.claim(&members[0].address, 1000).unwrap();
+        fanout.claim(&members[1].address, 1000).unwrap();
+
+        let new_members = vec![Member {
+            address: Pubkey::new_unique(),
+            share: 10000,
+        }];
+        fanout.set_members(new_members.clone()).unwrap();
+
+        assert_eq!(fanout.members_version, 1);
+        assert_eq!(fanout.distributed, 0);
+        assert_eq!(fanout.claimed, vec![0]);
+        assert!(fanout.claim(&members[0].address, 1).is_err());
+
+        // dust left after old members is split by new ones
+        assert_eq!(fanout.claim(&new_members[0].address, 1).unwrap(), 1);
+
+        assert!(Fanout::check_members(&new_members).is_ok());
+        assert!(Fanout::check_members(&members[..1]).is_err());
+        assert!(Fanout::check_members(&[]).is_err());
+
+        let mut duplicate = members.clone();
+        duplicate[1].address = duplicate[0].address;
+        assert!(Fanout::check_members(&duplicate).is_err());
+    }
+
+    fn members() -> Vec<Member> {
+        vec![
+            Member {
+                address: Pubkey::new_unique(),
+                share: 3333,
+            },
+            Member {
+                address: Pubkey::new_unique(),
+                share: 6667,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        let mut data = FanoutV0 {
+            distributed: 10,
+            members: members(),
+        }
+        .try_to_vec()
+        .unwrap();
+
+        let (fanout, migrated) = Fanout::read_versioned(&data).unwrap();
+        assert!(migrated);
+        // rounded up, so at least everything that was pushed is accounted for
+        assert_eq!(fanout.claimed, vec![4, 7]);
+        assert_eq!(fanout.version, FANOUT_VERSION);
+        // largest member adopts fanouts that predate close authority
+        assert_eq!(fanout.close_authority, Some(fanout.members[1].address));
+
+        // zero padded
+        data.extend([0; 16]);
+        let (padded, _) = Fanout::read_versioned(&data).unwrap();
+        assert_eq!(padded.claimed, fanout.claimed);
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let members = members();
+
+        let mut data = FanoutV0 {
+            distributed: 10,
+            members: members.clone(),
+        }
+        .try_to_vec()
+        .unwrap();
+        data.push(1);
+        data.extend(vec![1u64, 2].try_to_vec().unwrap());
+
+        let (fanout, migrated) = Fanout::read_versioned(&data).unwrap();
+        assert!(migrated);
+        assert_eq!(fanout.claimed, vec![1, 2]);
+        assert_eq!(fanout.close_authority, Some(members[1].address));
+
+        // version 2 keeps close authority
+        let close_authority = Pubkey::new_unique();
+        let mut data = FanoutV0 {
+            distributed: 10,
+            members,
+        }
+        .try_to_vec()
+        .unwrap();
+        data.push(2);
+        data.extend(vec![1u64, 2].try_to_vec().unwrap());
+        data.extend(Some(close_authority).try_to_vec().unwrap());
+
+        let (v2, migrated) = Fanout::read_versioned(&data).unwrap();
+        assert!(migrated);
+        assert_eq!(v2.close_authority, Some(close_authority));
+        assert_eq!(v2.update_authority, None);
+
+        // version 2 created without close authority is not adopted
+        data.truncate(data.len() - 33);
+        data.extend(None::<Pubkey>.try_to_vec().unwrap());
+        let (v2, _) = Fanout::read_versioned(&data).unwrap();
+        assert_eq!(v2.close_authority, None);
+
+        // current version is read as is
+        let data = fanout.try_to_vec().unwrap();
+        let (same, migrated) = Fanout::read_versioned(&data).unwrap();
+        assert!(!migrated);
+        assert_eq!(same.claimed, fanout.claimed);
+    }
+
+    #[test]
+    fn test_paged_distribution() {
+        let mut fanout = PagedFanout::default();
+
+        let members = members();
+        let mut pages = [&members[..1], &members[1..]].map(|members| {
+            let index = fanout.add_page(members).unwrap();
+            FanoutPage {
+                index,
+                members: members.to_vec(),
+                ..Default::default()
+            }
+        });
+        assert!(fanout.is_complete());
+
+        // no room for more shares
+        assert!(fanout.add_page(&members[..1]).is_err());
+
+        fanout.start_distribution(1000).unwrap();
+        assert_eq!(fanout.distribute_page(&mut pages[1]).unwrap()[0].1, 666);
+
+        // more lamports arrived, distribution in progress is not affected
+        fanout.start_distribution(5000).unwrap();
+        assert!(fanout.distribute_page(&mut pages[1]).is_err());
+        assert_eq!(fanout.distribute_page(&mut pages[0]).unwrap()[0].1, 333);
+
+        assert_eq!(fanout.epoch, 1);
+        assert_eq!(fanout.distribution, None);
+        assert_eq!(fanout.distributed, 999);
+        assert!(fanout.distribute_page(&mut pages[0]).is_err());
+
+        fanout.start_distribution(1).unwrap();
+        assert_eq!(fanout.distribute_page(&mut pages[0]).unwrap()[0].1, 0);
+    }
+
     proptest! {
+        #[test]
+        fn proptest_paged_distribution(
+            amount in 1u64..,
+            members in arb_members(),
+            page_size in 1usize..4,
+        ) {
+            let mut fanout = PagedFanout::default();
+
+            let mut pages: Vec<_> = members
+                .chunks(page_size)
+                .map(|members| FanoutPage {
+                    index: fanout.add_page(members).unwrap(),
+                    members: members.to_vec(),
+                    ..Default::default()
+                })
+                .collect();
+
+            assert!(fanout.is_complete());
+
+            fanout.start_distribution(amount).unwrap();
+
+            // pages can be distributed in any order
+            let mut paid = 0u128;
+            for page in pages.iter_mut().rev() {
+                let parts = fanout.distribute_page(page).unwrap();
+                paid += parts.iter().map(|(_, a)| *a as u128).sum::<u128>();
+            }
+
+            // only rounding dust stays
+            assert!(paid <= amount as u128);
+            assert!(amount as u128 - paid < members.len() as u128);
+            assert_eq!(paid, fanout.distributed as u128);
+            assert_eq!(fanout.epoch, 1);
+            assert!(pages.iter().all(|p| p.epoch == fanout.epoch));
+        }
         #[test]
         fn proptest_split(amount in 10u64..u64::MAX, members in arb_members()) {
             let fanout = Fanout {
@@ -84,8 +658,35 @@ mod tests {
                 ..Default::default()
             };
             let split = fanout.calculate_split(amount).unwrap();
-            let total: u64 = split.values().sum();
+            let total: u64 = split.iter().map(|(_, amount)| amount).sum();
             assert_eq!(total, amount);
         }
+
+        #[test]
+        fn proptest_claims(
+            members in arb_members(),
+            steps in prop::collection::vec((0u64..1_000_000_000_000, 0usize..10), 1..50),
+        ) {
+            let mut fanout = Fanout::new(members, None, None);
+            let mut available = 0u64;
+
+            for (inflow, idx) in steps {
+                available += inflow;
+
+                let member = fanout.members[idx % fanout.members.len()].address;
+                let total_inflow = fanout.total_inflow(available).unwrap();
+
+                available -= fanout.claim(&member, total_inflow).unwrap();
+            }
+
+            // everyone claims what is left, only rounding dust stays
+            let total_inflow = fanout.total_inflow(available).unwrap();
+            for member in fanout.members.clone() {
+                available -= fanout.claim(&member.address, total_inflow).unwrap();
+            }
+
+            assert!(available < fanout.members.len() as u64);
+            assert_eq!(fanout.total_inflow(available).unwrap(), total_inflow);
+        }
     }
 }
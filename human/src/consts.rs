@@ -1,4 +1,4 @@
-use solana_program::clock::UnixTimestamp;
+use checked_math::Bps;
 
 pub const V1: &[u8] = b"HMN_V1";
 
@@ -13,22 +13,23 @@ pub const MASTER_POST_MINT_SEED: &[u8] = b"MASTER_POST";
 pub const POST_INFO_SEED: &[u8] = b"POST_INFO";
 pub const REPOST_RECORD_SEED: &[u8] = b"REPOST_RECORD";
 pub const COLLECTION_MINT_SEED: &[u8] = b"COLLECTION";
+pub const ROUND_RECORD_SEED: &[u8] = b"ROUND_RECORD"; // round registry, one per round number
+pub const ROUND_FANOUT_SEED: &[u8] = b"ROUND_FANOUT"; // fanout of round bids, one per fee version
 
-pub const BUY_COMMISSION: u16 = 1000; // 10%
-pub const TREASURY_COMMISSION: u16 = 8000; // 80%
+pub const BUY_COMMISSION: Bps = Bps::new(1000); // 10%
+pub const TREASURY_COMMISSION: Bps = Bps::new(8000); // 80%
 
 pub const MAX_REPOST_TIME: i64 = 24 * 60 * 60; // 24h
 pub const REPOST_REDEEM_COOLDOWN: i64 = 24 * 60 * 60; // 24h
 
-#[cfg(feature = "dev")]
-pub const VESTING_TOTAL_PARTS: u8 = 30;
-#[cfg(feature = "dev")]
-pub const VESTING_PART_LENGTH: UnixTimestamp = 86400; // seconds in a day
+// round fee split before it was configurable, LP share goes to the state account
+pub const LEGACY_ROUND_TREASURY_SHARE: u16 = 9650;
+pub const LEGACY_ROUND_ADMIN_SHARE: u16 = 250;
+pub const LEGACY_ROUND_LP_SHARE: u16 = 100;
+pub const LEGACY_OWNER_VESTING: Bps = Bps::new(1000); // 10% of offer for rounds without record
+pub const MAX_ROUND_FEE_MEMBERS: usize = 10; // same as fanout
 
-#[cfg(not(feature = "dev"))]
-pub const VESTING_TOTAL_PARTS: u8 = 12 * 3; // 12 months across 3 years
-#[cfg(not(feature = "dev"))]
-pub const VESTING_PART_LENGTH: UnixTimestamp = 2629800; // seconds in a month
+pub const MAX_VESTING_WEIGHTS: usize = 36; // keeps state within STATE_ACC_SIZE
 
 pub const DEFAULT_REPORT_PRICE_LAMPORTS: u64 = 10000000; // 0.01 SOL
 pub const POST_ROYALTY_COMMISSION_BSP: u16 = 1000; // 10%
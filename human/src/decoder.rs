@@ -0,0 +1,112 @@
+//! Decodes `Event`s from transaction log messages, e.g. `meta.logMessages` of a transaction.
+//! Only events logged by the given program itself are returned,
+//! events of programs it invokes are skipped
+
+use solana_program::pubkey::Pubkey;
+
+use crate::event::Event;
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+/// Events logged by `program_id`, in order
+pub fn decode_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<Event> {
+    let program_id = program_id.to_string();
+
+    // programs being executed, innermost last
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs.iter().map(AsRef::as_ref) {
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA) {
+            if stack.last() == Some(&program_id.as_str()) {
+                events.extend(decode_data(data));
+            }
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        if words.next() != Some("Program") {
+            continue;
+        }
+
+        match (words.next(), words.next()) {
+            (Some(program), Some("invoke")) => stack.push(program),
+            (Some(program), Some("success" | "failed:")) if stack.last() == Some(&program) => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Decodes base64 data of a single `Program data:` line
+pub fn decode_data(data: &str) -> Option<Event> {
+    // `sol_log_data` logs each slice as a separate base64 word, events are logged as one
+    let bytes = base64::decode(data.split_whitespace().next()?).ok()?;
+
+    Event::decode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EVENT_DISCRIMINATOR;
+    use borsh::BorshSerialize;
+
+    fn data_line(event: &Event) -> String {
+        let mut data = EVENT_DISCRIMINATOR.to_vec();
+        event.serialize(&mut data).unwrap();
+
+        format!("{}{}", PROGRAM_DATA, base64::encode(data))
+    }
+
+    #[test]
+    fn test_decode_logs() {
+        let program_id = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let claimed = Event::RoundVestingClaimed {
+            state: Pubkey::new_unique(),
+            round: Pubkey::new_unique(),
+            amount: 42,
+            completed: true,
+        };
+        let created = Event::RoundCreated {
+            state: Pubkey::new_unique(),
+            round: Pubkey::new_unique(),
+            number: 1,
+            offer_amount: 100,
+            fanout: Pubkey::new_unique(),
+            fee_version: 2,
+            owner_vesting_bps: 1000,
+            owner_release_duration: None,
+        };
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            "Program log: creating round".to_string(),
+            format!("Program {} invoke [2]", other),
+            // same data logged by invoked program is not ours
+            data_line(&claimed),
+            format!("Program {} success", other),
+            data_line(&created),
+            "Program data: bm90IGFuIGV2ZW50".to_string(),
+            format!(
+                "Program {} consumed 1000 of 200000 compute units",
+                program_id
+            ),
+            format!("Program {} success", program_id),
+            format!("Program {} invoke [1]", program_id),
+            data_line(&claimed),
+            format!("Program {} success", program_id),
+        ];
+
+        assert_eq!(
+            decode_logs(&program_id, &logs),
+            vec![created, claimed.clone()]
+        );
+        assert_eq!(decode_logs(&other, &logs), vec![claimed]);
+    }
+}
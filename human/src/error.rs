@@ -1,11 +1,16 @@
+use std::ops::Range;
+
+use human_common::error::{CustomError, HUMAN_ERRORS};
+use num_derive::FromPrimitive;
 use solana_program::program_error::ProgramError;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+/// Codes are stable, new variants are only appended. See `human_common::error`
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 #[repr(u32)]
 pub enum Error {
     #[error("overflow occured")]
-    Overflow,
+    Overflow = 0x000,
     #[error("drop price can't be zero")]
     DropPriceZero,
     #[error("drop start and end dates overlap")]
@@ -22,6 +27,78 @@ pub enum Error {
     RepostWindowExpired,
     #[error("can't redeem yet")]
     CantRedeemNow,
+    #[error("not enough tokens left in drop")]
+    DropAmountExceeded,
+    #[error("invalid vesting schedule")]
+    InvalidVestingSchedule,
+    #[error("commission account must be a wSOL token account")]
+    InvalidCommissionAccount,
+    #[error("swap state does not match the token")]
+    InvalidSwapState,
+    #[error("no admin proposed")]
+    NoPendingAdmin,
+    #[error("round fee shares must be non-zero, unique and add up to 10000")]
+    InvalidRoundFee,
+    #[error("invalid round policy")]
+    InvalidRoundPolicy,
+    #[error("round offers too much of the vault")]
+    RoundOfferTooLarge,
+    #[error("too soon after the previous round")]
+    RoundTooSoon,
+    #[error("no more rounds allowed in this period")]
+    RoundLimitReached,
+    #[error("round is not registered for this state")]
+    RoundNotRegistered,
+    #[error("round vesting is already claimed")]
+    RoundAlreadyClaimed,
+    #[error("invalid owner vesting of the round")]
+    InvalidOwnerVesting,
+    #[error("token mint is not owned by the token program")]
+    InvalidMint,
+    #[error("token must have fixed supply")]
+    MintNotFixedSupply,
+    #[error("invalid state address")]
+    InvalidStateAddress,
+    #[error("invalid contract wallet address")]
+    InvalidWallet,
+    #[error("invalid contract vault address")]
+    InvalidVault,
+    #[error("invalid owner token wallet")]
+    InvalidOwnerWallet,
+    #[error("invalid token program")]
+    InvalidTokenProgram,
+    #[error("drop amount must be positive and within wallet balance")]
+    InvalidDropAmount,
+    #[error("amount can't be zero")]
+    ZeroAmount,
+    #[error("invalid post info address")]
+    InvalidPostInfoAddress,
+    #[error("post belongs to a different state")]
+    PostStateMismatch,
+    #[error("master mint does not match the post")]
+    InvalidMasterMint,
+    #[error("invalid master edition address")]
+    InvalidMasterEdition,
+    #[error("repost belongs to a different state")]
+    RepostStateMismatch,
+    #[error("invalid round fanout account")]
+    InvalidRoundFanout,
+    #[error("round fanout members do not match the round fee")]
+    RoundFanoutMismatch,
+    #[error("invalid round record address")]
+    InvalidRoundRecordAddress,
+    #[error("round is not accepted")]
+    RoundNotAccepted,
+    #[error("SetAdmin is replaced by ProposeAdmin and AcceptAdmin")]
+    SetAdminDeprecated,
+    #[error("state has to be migrated with MigrateState first")]
+    StateMigrationRequired,
+    #[error("owner vesting is above the round policy cap")]
+    OwnerVestingAboveCap,
+}
+
+impl CustomError for Error {
+    const CODES: Range<u32> = HUMAN_ERRORS;
 }
 
 impl From<Error> for ProgramError {
@@ -35,3 +112,24 @@ impl<T> From<Error> for Result<T, ProgramError> {
         Err(ProgramError::Custom(e as u32))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use human_common::error::decode;
+
+    #[test]
+    fn test_codes() {
+        // codes of deployed versions are kept
+        assert_eq!(Error::Overflow as u32, 0);
+        assert_eq!(Error::InvalidOwnerVesting as u32, 21);
+
+        let last = Error::OwnerVestingAboveCap as u32;
+        assert!(HUMAN_ERRORS.contains(&last));
+
+        for code in 0..=last {
+            let err = decode::<Error>(&ProgramError::Custom(code)).unwrap();
+            assert_eq!(err as u32, code);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use fanout::state::Member;
+use solana_program::{
+    clock::UnixTimestamp, entrypoint::ProgramResult, log::sol_log_data, pubkey::Pubkey,
+};
+
+use crate::state::RoundPolicy;
+
+/// Prefix of every event logged by this program, tells them apart from events of
+/// programs it invokes (round, fanout) in the same transaction. See `decoder`
+pub const EVENT_DISCRIMINATOR: [u8; 8] = *b"HMN_EVT1";
+
+/// Config field changed by `UpdateConfig`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[repr(u8)]
+pub enum ConfigField {
+    Commission,
+    Treasury,
+    SwapState,
+    Owner,
+}
+
+/// Program events, logged with `sol_log_data` as `EVENT_DISCRIMINATOR` followed by borsh,
+/// so clients can parse them from transaction logs. Variants are only ever appended
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum Event {
+    ConfigUpdated {
+        state: Pubkey,
+        field: ConfigField,
+        old: Pubkey,
+        new: Pubkey,
+    },
+    AdminProposed {
+        state: Pubkey,
+        admin: Pubkey,
+    },
+    AdminAccepted {
+        state: Pubkey,
+        old: Pubkey,
+        new: Pubkey,
+    },
+    RoundFeeUpdated {
+        state: Pubkey,
+        version: u32,
+        members: Vec<Member>,
+    },
+    RoundPolicyUpdated {
+        state: Pubkey,
+        policy: Option<RoundPolicy>,
+    },
+    PostRegistered {
+        state: Pubkey,
+        post_id: [u8; 32],
+        master_mint: Pubkey,
+        repost_price: u64,
+        royalty_address: Pubkey,
+    },
+    Reposted {
+        state: Pubkey,
+        post_id: [u8; 32],
+        user: Pubkey,
+        repost_mint: Pubkey,
+        /// lamports paid by the user
+        price: u64,
+        /// how the price was split, see `lamport_split_plan`
+        split: Vec<(Pubkey, u64)>,
+        /// tokens user can redeem with `RedeemRepost`
+        receive_amount: u64,
+        compressed: bool,
+    },
+    RepostRedeemed {
+        state: Pubkey,
+        post_id: [u8; 32],
+        user: Pubkey,
+        amount: u64,
+    },
+    CommissionDeposited {
+        state: Pubkey,
+        /// lamports deposited to the pool
+        lamports: u64,
+        /// LP tokens minted to the owner
+        lp_tokens: u64,
+    },
+    RoundCreated {
+        state: Pubkey,
+        round: Pubkey,
+        number: u64,
+        offer_amount: u64,
+        fanout: Pubkey,
+        fee_version: u32,
+        owner_vesting_bps: u16,
+        owner_release_duration: Option<UnixTimestamp>,
+    },
+    RoundVestingClaimed {
+        state: Pubkey,
+        round: Pubkey,
+        amount: u64,
+        /// owner part is fully released
+        completed: bool,
+    },
+}
+
+impl Event {
+    pub fn emit(&self) -> ProgramResult {
+        let mut data = EVENT_DISCRIMINATOR.to_vec();
+        self.serialize(&mut data)?;
+
+        sol_log_data(&[&data]);
+
+        Ok(())
+    }
+
+    /// Decodes event from `sol_log_data` payload, `None` if it's not an event of this program
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let payload = data.strip_prefix(&EVENT_DISCRIMINATOR)?;
+
+        Self::try_from_slice(payload).ok()
+    }
+}
@@ -0,0 +1,652 @@
+//! Instruction builders. Every builder derives program addresses (state, wallets, authority,
+//! post info, metadata, editions, records) itself, so callers only provide keys that can't be derived
+use borsh::BorshSerialize;
+use mpl_token_metadata::state::EDITION_MARKER_BIT_SIZE;
+use mpl_token_metadata::utils::BUBBLEGUM_SIGNER;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::consts::*;
+use crate::state::RoundPolicy;
+use crate::{
+    authority, collection_mint, contract_state, contract_vault, contract_wallet, master_post_mint,
+    post_info, repost_record, round_fanout_address, round_record_address, spl_ac, spl_noop,
+    swap_program, tree, BuyFromDropInstruction, CreateDropInstruction, CreateRoundInstruction,
+    InitInstruction, InitMultisigInstruction, Instruction as HumanInstruction,
+    RegisterPostInstruction, RepostArgs, SetAdminInstruction, SetRoundFeeInstruction,
+    SetRoundPolicyInstruction, UpdateConfigInstruction,
+};
+
+/// Swap pool the state token is traded in, see `ContractState::swap_state`
+#[derive(Debug, Clone, Copy)]
+pub struct SwapAccounts {
+    pub state: Pubkey,
+    /// state token account of the pool
+    pub token_a: Pubkey,
+    /// wSOL account of the pool
+    pub token_b: Pubkey,
+    pub pool_mint: Pubkey,
+}
+
+/// Admin signing directly, or a `Multisig` followed by its signers
+fn admin_accounts(admin: &Pubkey, multisig_signers: &[Pubkey]) -> Vec<AccountMeta> {
+    if multisig_signers.is_empty() {
+        return vec![AccountMeta::new_readonly(*admin, true)];
+    }
+
+    let mut accounts = vec![AccountMeta::new_readonly(*admin, false)];
+    accounts.extend(
+        multisig_signers
+            .iter()
+            .map(|signer| AccountMeta::new_readonly(*signer, true)),
+    );
+
+    accounts
+}
+
+fn build(program_id: &Pubkey, accounts: Vec<AccountMeta>, data: HumanInstruction) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+pub fn create_init_instruction(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    token: &Pubkey,
+    feepayer: &Pubkey,
+    args: InitInstruction,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(state.to_owned(), false),
+        AccountMeta::new_readonly(token.to_owned(), false),
+        AccountMeta::new(feepayer.to_owned(), true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data: HumanInstruction::Init(args).try_to_vec()?,
+    })
+}
+
+/// `create_init_instruction` for the state derived from `token`
+pub fn init(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    fee_payer: &Pubkey,
+    args: InitInstruction,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new_readonly(*token, false),
+        AccountMeta::new(*fee_payer, true),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::Init(args))
+}
+
+pub fn create_wallets(program_id: &Pubkey, token_mint: &Pubkey, fee_payer: &Pubkey) -> Instruction {
+    let (wallet_addr, _) = contract_wallet!(program_id, token_mint);
+    let (vault_addr, _) = contract_vault!(program_id, token_mint);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(token_mint.to_owned(), false),
+        AccountMeta::new(wallet_addr.to_owned(), false),
+        AccountMeta::new(vault_addr.to_owned(), false),
+        AccountMeta::new_readonly(fee_payer.to_owned(), true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::ID, false),
+        AccountMeta::new_readonly(*program_id, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::CreateWallets)
+}
+
+pub fn create_drop(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    admin: &Pubkey,
+    multisig_signers: &[Pubkey],
+    args: CreateDropInstruction,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (wallet, _) = contract_wallet!(program_id, token);
+
+    let mut accounts = vec![AccountMeta::new(state, false)];
+    accounts.extend(admin_accounts(admin, multisig_signers));
+    accounts.push(AccountMeta::new_readonly(wallet, false));
+
+    build(program_id, accounts, HumanInstruction::CreateDrop(args))
+}
+
+/// `commission_owner` is the owner of `commission` wSOL account, receives token commission
+pub fn buy_from_drop(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    buyer: &Pubkey,
+    owner: &Pubkey,
+    treasury: &Pubkey,
+    commission: &Pubkey,
+    commission_owner: &Pubkey,
+    args: BuyFromDropInstruction,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (wallet, _) = contract_wallet!(program_id, token);
+    let (authority, _) = authority!(program_id);
+
+    let accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new(wallet, false),
+        AccountMeta::new(*buyer, true),
+        AccountMeta::new(get_associated_token_address(buyer, token), false),
+        AccountMeta::new(*owner, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new_readonly(*commission, false),
+        AccountMeta::new(get_associated_token_address(commission_owner, token), false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::BuyFromDrop(args))
+}
+
+pub fn vest(program_id: &Pubkey, token: &Pubkey, owner: &Pubkey) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (vault, _) = contract_vault!(program_id, token);
+    let (authority, _) = authority!(program_id);
+
+    let accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new(get_associated_token_address(owner, token), false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::Vest)
+}
+
+/// Accounts v3 states are migrated with, see `migrate_state`
+pub struct MigrateV3Accounts {
+    pub admin: Pubkey,
+    /// signers of `admin` if it's a multisig
+    pub multisig_signers: Vec<Pubkey>,
+    /// addresses v3 states didn't have
+    pub swap_state: Pubkey,
+    pub commission: Pubkey,
+    pub treasury: Pubkey,
+}
+
+/// `payer` tops up rent of the grown state, `v3` is required for v3 states only
+pub fn migrate_state(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    payer: &Pubkey,
+    v3: Option<&MigrateV3Accounts>,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let mut accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    if let Some(v3) = v3 {
+        accounts.extend(admin_accounts(&v3.admin, &v3.multisig_signers));
+        accounts.extend([
+            AccountMeta::new_readonly(v3.swap_state, false),
+            AccountMeta::new_readonly(v3.commission, false),
+            AccountMeta::new_readonly(v3.treasury, false),
+        ]);
+    }
+
+    build(program_id, accounts, HumanInstruction::MigrateState)
+}
+
+pub fn propose_admin(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    admin: &Pubkey,
+    multisig_signers: &[Pubkey],
+    new_admin: &Pubkey,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let mut accounts = vec![AccountMeta::new(state, false)];
+    accounts.extend(admin_accounts(admin, multisig_signers));
+
+    let data = HumanInstruction::ProposeAdmin(SetAdminInstruction { admin: *new_admin });
+
+    build(program_id, accounts, data)
+}
+
+pub fn accept_admin(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    pending_admin: &Pubkey,
+    multisig_signers: &[Pubkey],
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let mut accounts = vec![AccountMeta::new(state, false)];
+    accounts.extend(admin_accounts(pending_admin, multisig_signers));
+
+    build(program_id, accounts, HumanInstruction::AcceptAdmin)
+}
+
+/// `multisig` has to be allocated with `Multisig::SIZE` and assigned to the program beforehand
+pub fn init_multisig(
+    program_id: &Pubkey,
+    multisig: &Pubkey,
+    args: InitMultisigInstruction,
+) -> Instruction {
+    let accounts = vec![AccountMeta::new(*multisig, true)];
+
+    build(program_id, accounts, HumanInstruction::InitMultisig(args))
+}
+
+pub fn update_config(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    admin: &Pubkey,
+    multisig_signers: &[Pubkey],
+    args: UpdateConfigInstruction,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let mut accounts = vec![AccountMeta::new(state, false)];
+    accounts.extend(admin_accounts(admin, multisig_signers));
+
+    if let Some(commission) = args.commission {
+        accounts.push(AccountMeta::new_readonly(commission, false));
+    }
+
+    if let Some(swap_state) = args.swap_state {
+        accounts.push(AccountMeta::new_readonly(swap_state, false));
+    }
+
+    build(program_id, accounts, HumanInstruction::UpdateConfig(args))
+}
+
+pub fn set_round_fee(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    admin: &Pubkey,
+    multisig_signers: &[Pubkey],
+    members: Vec<fanout::state::Member>,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let mut accounts = vec![AccountMeta::new(state, false)];
+    accounts.extend(admin_accounts(admin, multisig_signers));
+
+    let data = HumanInstruction::SetRoundFee(SetRoundFeeInstruction { members });
+
+    build(program_id, accounts, data)
+}
+
+pub fn set_round_policy(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    admin: &Pubkey,
+    multisig_signers: &[Pubkey],
+    policy: Option<RoundPolicy>,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+
+    let mut accounts = vec![AccountMeta::new(state, false)];
+    accounts.extend(admin_accounts(admin, multisig_signers));
+
+    let data = HumanInstruction::SetRoundPolicy(SetRoundPolicyInstruction { policy });
+
+    build(program_id, accounts, data)
+}
+
+/// `stash` is any new keypair, it holds wSOL during the deposit and is closed afterwards
+pub fn deposit_commission(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    owner: &Pubkey,
+    swap: &SwapAccounts,
+    stash: &Pubkey,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (vault, _) = contract_vault!(program_id, token);
+    let (authority, _) = authority!(program_id);
+    let (swap_authority, _) =
+        Pubkey::find_program_address(&[swap.state.as_ref()], &swap_program::ID);
+
+    let accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new(*stash, true),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(swap.state, false),
+        AccountMeta::new_readonly(swap_authority, false),
+        AccountMeta::new(swap.token_a, false),
+        AccountMeta::new(swap.token_b, false),
+        AccountMeta::new(swap.pool_mint, false),
+        AccountMeta::new(get_associated_token_address(owner, &swap.pool_mint), false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(swap_program::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::DepositCommission)
+}
+
+pub fn register_post(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    admin: &Pubkey,
+    payer: &Pubkey,
+    args: RegisterPostInstruction,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (authority, _) = authority!(program_id);
+
+    let (post_info, _) = post_info!(program_id, &args.post_id);
+    let (master_mint, _) = master_post_mint!(program_id, &args.post_id);
+    let (master_metadata, _) = mpl_token_metadata::pda::find_metadata_account(&master_mint);
+    let (master_edition, _) = mpl_token_metadata::pda::find_master_edition_account(&master_mint);
+
+    let (collection_mint, _) = collection_mint!(program_id, token);
+    let (collection_metadata, _) = mpl_token_metadata::pda::find_metadata_account(&collection_mint);
+    let (collection_edition, _) =
+        mpl_token_metadata::pda::find_master_edition_account(&collection_mint);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(state, false),
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(post_info, false),
+        AccountMeta::new(master_mint, false),
+        AccountMeta::new(
+            get_associated_token_address(&authority, &master_mint),
+            false,
+        ),
+        AccountMeta::new(master_metadata, false),
+        AccountMeta::new(master_edition, false),
+        AccountMeta::new(authority, false),
+        AccountMeta::new(collection_mint, false),
+        AccountMeta::new(
+            get_associated_token_address(&authority, &collection_mint),
+            false,
+        ),
+        AccountMeta::new(collection_metadata, false),
+        AccountMeta::new(collection_edition, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+        AccountMeta::new_readonly(mpl_token_metadata::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::RegisterPost(args))
+}
+
+/// `repost_mint` is a new keypair, `edition` is master edition supply + 1
+pub fn repost(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    post_id: &[u8; 32],
+    owner: &Pubkey,
+    treasury: &Pubkey,
+    swap: &SwapAccounts,
+    user: &Pubkey,
+    repost_mint: &Pubkey,
+    edition: u64,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (authority, _) = authority!(program_id);
+
+    let (post_info, _) = post_info!(program_id, post_id);
+    let (master_mint, _) = master_post_mint!(program_id, post_id);
+    let (master_metadata, _) = mpl_token_metadata::pda::find_metadata_account(&master_mint);
+    let (master_edition, _) = mpl_token_metadata::pda::find_master_edition_account(&master_mint);
+
+    let (repost_metadata, _) = mpl_token_metadata::pda::find_metadata_account(repost_mint);
+    let (repost_edition, _) = mpl_token_metadata::pda::find_master_edition_account(repost_mint);
+    let (edition_mark, _) = mpl_token_metadata::pda::find_edition_account(
+        &master_mint,
+        (edition / EDITION_MARKER_BIT_SIZE).to_string(),
+    );
+    let (repost_record, _) = repost_record!(program_id, repost_mint);
+
+    let accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new_readonly(master_mint, false),
+        AccountMeta::new_readonly(
+            get_associated_token_address(&authority, &master_mint),
+            false,
+        ),
+        AccountMeta::new_readonly(master_metadata, false),
+        AccountMeta::new(master_edition, false),
+        AccountMeta::new_readonly(post_info, false),
+        AccountMeta::new(*repost_mint, true),
+        AccountMeta::new(repost_metadata, false),
+        AccountMeta::new(repost_edition, false),
+        AccountMeta::new(edition_mark, false),
+        AccountMeta::new(*user, true),
+        AccountMeta::new(get_associated_token_address(user, repost_mint), false),
+        AccountMeta::new(get_associated_token_address(user, token), false),
+        AccountMeta::new(*owner, false),
+        AccountMeta::new(*treasury, false),
+        AccountMeta::new(repost_record, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(swap.state, false),
+        AccountMeta::new_readonly(swap.token_a, false),
+        AccountMeta::new_readonly(swap.token_b, false),
+        AccountMeta::new_readonly(*token, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+        AccountMeta::new_readonly(mpl_token_metadata::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(
+        program_id,
+        accounts,
+        HumanInstruction::Repost(RepostArgs {}),
+    )
+}
+
+/// `payer` is the one who paid for the repost record, gets rent back
+pub fn redeem_repost(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    repost_mint: &Pubkey,
+    user: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (vault, _) = contract_vault!(program_id, token);
+    let (authority, _) = authority!(program_id);
+    let (repost_record, _) = repost_record!(program_id, repost_mint);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(state, false),
+        AccountMeta::new(repost_record, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new_readonly(*user, false),
+        AccountMeta::new(get_associated_token_address(user, token), false),
+        AccountMeta::new(*payer, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::RedeemRepost)
+}
+
+/// `round` is a new keypair. `fee_version` is the version of `ContractState::effective_round_fee`
+/// and `round_number` is `ContractState::rounds_created` at the time of the call.
+/// `creator` is the owner, or the admin (`multisig_signers` if it's a multisig)
+pub fn create_round(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    fee_version: u32,
+    round_number: u64,
+    round: &Pubkey,
+    bid_mint: &Pubkey,
+    creator: &Pubkey,
+    multisig_signers: &[Pubkey],
+    payer: &Pubkey,
+    args: CreateRoundInstruction,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (vault, _) = contract_vault!(program_id, token);
+    let (authority, _) = authority!(program_id);
+    let (fanout, _) = round_fanout_address(program_id, &state, fee_version);
+    let (round_record, _) = round_record_address(program_id, &state, round_number);
+
+    let (offer_wallet, _) =
+        Pubkey::find_program_address(&[round::OFFER_SEED, round.as_ref()], &round::ID);
+    let (bid_wallet, _) =
+        Pubkey::find_program_address(&[round::BID_SEED, round.as_ref()], &round::ID);
+    let (round_authority, _) =
+        Pubkey::find_program_address(&[round::AUTHORITY_SEED, round.as_ref()], &round::ID);
+
+    let mut accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new(fanout, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*round, true),
+        AccountMeta::new(offer_wallet, false),
+        AccountMeta::new_readonly(*token, false),
+        AccountMeta::new(bid_wallet, false),
+        AccountMeta::new_readonly(*bid_mint, false),
+        AccountMeta::new_readonly(round_authority, false),
+    ];
+    accounts.extend(admin_accounts(creator, multisig_signers));
+    accounts.extend([
+        AccountMeta::new(round_record, false),
+        AccountMeta::new_readonly(round::ID, false),
+        AccountMeta::new_readonly(fanout::ID, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ]);
+
+    build(program_id, accounts, HumanInstruction::CreateRound(args))
+}
+
+/// `round_number` of the round in the registry, `None` for rounds created before it
+pub fn claim_round_vesting(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    owner: &Pubkey,
+    round: &Pubkey,
+    round_number: Option<u64>,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (vault, _) = contract_vault!(program_id, token);
+    let (authority, _) = authority!(program_id);
+
+    let mut accounts = vec![
+        AccountMeta::new(state, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new_readonly(*round, false),
+        AccountMeta::new(get_associated_token_address(owner, token), false),
+    ];
+
+    if let Some(number) = round_number {
+        let (round_record, _) = round_record_address(program_id, &state, number);
+        accounts.push(AccountMeta::new(round_record, false));
+    }
+
+    accounts.push(AccountMeta::new_readonly(spl_token::ID, false));
+
+    build(program_id, accounts, HumanInstruction::ClaimRoundVesting)
+}
+
+/// tree account has to be allocated beforehand
+pub fn initialize_tree(program_id: &Pubkey, payer: &Pubkey) -> Instruction {
+    let (authority, _) = authority!(program_id);
+    let (tree_authority, _) =
+        Pubkey::find_program_address(&[tree::ID.as_ref()], &mpl_bubblegum::ID);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new(tree::ID, false),
+        AccountMeta::new(tree_authority, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(mpl_bubblegum::ID, false),
+        AccountMeta::new_readonly(spl_noop::ID, false),
+        AccountMeta::new_readonly(spl_ac::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(program_id, accounts, HumanInstruction::InitializeTree)
+}
+
+/// `repost_mint` is any unique key, it only addresses the repost record
+pub fn repost_compressed(
+    program_id: &Pubkey,
+    token: &Pubkey,
+    post_id: &[u8; 32],
+    user: &Pubkey,
+    payer: &Pubkey,
+    repost_mint: &Pubkey,
+) -> Instruction {
+    let (state, _) = contract_state!(program_id, token);
+    let (authority, _) = authority!(program_id);
+    let (post_info, _) = post_info!(program_id, post_id);
+    let (repost_record, _) = repost_record!(program_id, repost_mint);
+    let (tree_authority, _) =
+        Pubkey::find_program_address(&[tree::ID.as_ref()], &mpl_bubblegum::ID);
+
+    let (collection_mint, _) = collection_mint!(program_id, token);
+    let (collection_metadata, _) = mpl_token_metadata::pda::find_metadata_account(&collection_mint);
+    let (collection_edition, _) =
+        mpl_token_metadata::pda::find_master_edition_account(&collection_mint);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(state, false),
+        AccountMeta::new_readonly(post_info, false),
+        AccountMeta::new_readonly(*user, false),
+        AccountMeta::new(get_associated_token_address(user, token), false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*repost_mint, false),
+        AccountMeta::new(repost_record, false),
+        AccountMeta::new_readonly(authority, false),
+        AccountMeta::new(tree_authority, false),
+        AccountMeta::new(tree::ID, false),
+        AccountMeta::new_readonly(collection_mint, false),
+        AccountMeta::new(collection_metadata, false),
+        AccountMeta::new_readonly(collection_edition, false),
+        AccountMeta::new_readonly(mpl_bubblegum::ID, false),
+        AccountMeta::new_readonly(BUBBLEGUM_SIGNER, false),
+        AccountMeta::new_readonly(spl_noop::ID, false),
+        AccountMeta::new_readonly(spl_ac::ID, false),
+        AccountMeta::new_readonly(mpl_token_metadata::ID, false),
+        AccountMeta::new_readonly(*token, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    build(
+        program_id,
+        accounts,
+        HumanInstruction::RepostCompressed(RepostArgs {}),
+    )
+}
@@ -1,23 +1,35 @@
 #![forbid(unsafe_code)]
 #![deny(clippy::all)]
 #![allow(clippy::too_many_arguments)]
-#![cfg(not(feature = "no-entrypoint"))]
 pub mod consts;
+pub mod decoder;
 pub mod error;
+pub mod event;
+pub mod instruction;
 pub mod state;
 pub mod vest;
 
+pub use instruction::{create_init_instruction, create_wallets};
+
 use std::mem;
 
 use crate::consts::*;
 use crate::error::Error;
+use crate::event::{ConfigField, Event};
 use crate::state::{
-    init_state, try_migrate_state, ContractState, PostInfo, RepostRecord, STATE_ACC_SIZE,
+    drop_reserve, init_state, lamport_split_plan, ContractState, ContractStateHeader,
+    ContractStateV3, PostInfo, RepostRecord, RoundPolicy, RoundRecord, RoundRecordStatus,
+    STATE_ACC_SIZE,
 };
+use crate::vest::{process_vest, VestingSchedule};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use human_common::entity::{initialize_entity, next_entity, Entity};
+use checked_math::mul_div_floor;
+use human_common::entity::{
+    entity_from_acc, initialize_entity, next_entity, next_readonly_entity,
+    readonly_entity_from_acc, zero_copy_from_acc, Entity, Migrate,
+};
 use mpl_bubblegum::state::metaplex_adapter::{self, Collection};
 use mpl_token_metadata::state::{CollectionDetails, TokenMetadataAccount};
 use mpl_token_metadata::utils::BUBBLEGUM_SIGNER;
@@ -25,9 +37,8 @@ use solana_program::clock::{Clock, UnixTimestamp};
 use solana_program::program_memory::sol_memset;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
-    instruction::{self, AccountMeta},
+    instruction::AccountMeta,
     msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
@@ -36,20 +47,23 @@ use solana_program::{
     pubkey::Pubkey,
     rent::Rent,
     system_instruction, system_program,
-    sysvar::{self, Sysvar},
+    sysvar::Sysvar,
 };
 use spl_associated_token_account::get_associated_token_address;
 use spl_math::precise_number::PreciseNumber;
 use spl_token::instruction::{close_account, initialize_account2};
 use spl_token_swap::instruction as swap_instruction;
 
+use human_common::multisig::Multisig;
 use human_common::utils::{
-    next_atoken_wallet, next_expected_account, next_expected_token_wallet, next_signer_account,
+    create_pda_account, next_admin_signer, next_atoken_wallet, next_expected_account,
+    next_expected_token_wallet, next_owner_or_admin_signer,
 };
 
 use shank::ShankInstruction;
 
 use anchor_lang::AccountDeserialize;
+use anchor_lang::Discriminator;
 use anchor_lang::InstructionData;
 
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
@@ -65,6 +79,8 @@ pub struct InitInstruction {
     pub treasury: Pubkey,
     /// swap to deposit liquidity to
     pub swap_state: Pubkey,
+    /// vault vesting schedule, `None` keeps the vault locked
+    pub vesting: Option<VestingSchedule>,
 }
 
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
@@ -81,12 +97,57 @@ pub struct CreateDropInstruction {
     pub end_date: UnixTimestamp,
 }
 
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct BuyFromDropInstruction {
+    // how many chatlans to buy
+    pub amount: u64,
+    // max price per chatlan buyer agrees to pay
+    pub expected_price: u64,
+}
+
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 #[repr(C)]
 pub struct SetAdminInstruction {
     pub admin: Pubkey,
 }
 
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct InitMultisigInstruction {
+    /// signatures required
+    pub threshold: u8,
+    /// allowed signers
+    pub signers: Vec<Pubkey>,
+}
+
+#[derive(Debug, Default, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct UpdateConfigInstruction {
+    /// new wSOL commission account
+    pub commission: Option<Pubkey>,
+    /// new owner treasury
+    pub treasury: Option<Pubkey>,
+    /// new swap state, token A must be state token
+    pub swap_state: Option<Pubkey>,
+    /// new owner
+    pub owner: Option<Pubkey>,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct SetRoundFeeInstruction {
+    /// recipients of round bids and their shares in bps
+    pub members: Vec<fanout::state::Member>,
+}
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct SetRoundPolicyInstruction {
+    /// `None` lifts the limits
+    pub policy: Option<RoundPolicy>,
+}
+
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 #[repr(C)]
 pub struct RegisterPostInstruction {
@@ -108,10 +169,15 @@ pub struct RepostArgs {}
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 #[repr(C)]
 pub struct CreateRoundInstruction {
-    bidding_start: UnixTimestamp,
-    bidding_end: UnixTimestamp,
-    offer_amount: u64,
-    target_bid: u64,
+    pub bidding_start: UnixTimestamp,
+    pub bidding_end: UnixTimestamp,
+    pub offer_amount: u64,
+    pub target_bid: u64,
+    /// part of the offer owner receives from the vault once round is accepted, in bps.
+    /// Rounds created by owner are capped by `ContractState::max_owner_vesting_bps`
+    pub owner_vesting_bps: u16,
+    /// release owner part linearly over this many seconds after bidding ends
+    pub owner_release_duration: Option<UnixTimestamp>,
 }
 
 #[derive(Debug, BorshDeserialize, BorshSerialize, ShankInstruction)]
@@ -120,12 +186,13 @@ pub struct CreateRoundInstruction {
 pub enum Instruction {
     CreateWallets,
     Init(InitInstruction),
-    Deprecated1,
-    Deprecated2,
+    CreateDrop(CreateDropInstruction),
+    BuyFromDrop(BuyFromDropInstruction),
     Deprecated3,
     Deprecated4,
     Vest,
     MigrateState,
+    /// rejected, use `ProposeAdmin` and `AcceptAdmin`
     SetAdmin(SetAdminInstruction),
     DepositCommission,
     RegisterPost(RegisterPostInstruction),
@@ -135,16 +202,22 @@ pub enum Instruction {
     ClaimRoundVesting,
     InitializeTree,
     RepostCompressed(RepostArgs),
+    UpdateConfig(UpdateConfigInstruction),
+    /// new admin has to accept with `AcceptAdmin`
+    ProposeAdmin(SetAdminInstruction),
+    AcceptAdmin,
+    InitMultisig(InitMultisigInstruction),
+    SetRoundFee(SetRoundFeeInstruction),
+    SetRoundPolicy(SetRoundPolicyInstruction),
 }
 
-entrypoint!(process_instruction);
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    try_fix_state(accounts);
-
     let instruction = Instruction::try_from_slice(instruction_data).map_err(|e| {
         msg!("error parsing instruction: {}", e);
         ProgramError::InvalidInstructionData
@@ -159,11 +232,13 @@ pub fn process_instruction(
             msg!("initializing account");
             process_init_state(program_id, accounts, ii)?;
         }
-        Instruction::Deprecated1 => {
-            msg!("deprecated");
+        Instruction::CreateDrop(args) => {
+            msg!("creating drop");
+            process_create_drop(program_id, accounts, args)?;
         }
-        Instruction::Deprecated2 => {
-            msg!("deprecated");
+        Instruction::BuyFromDrop(args) => {
+            msg!("buying from drop");
+            process_buy_from_drop(program_id, accounts, args)?;
         }
         Instruction::Deprecated3 => {
             msg!("deprecated");
@@ -172,16 +247,20 @@ pub fn process_instruction(
             msg!("deprecated");
         }
         Instruction::Vest => {
-            msg!("vesting disabled");
-            //process_vest(program_id, accounts)?;
+            msg!("vesting");
+            process_vest(program_id, accounts)?;
         }
         Instruction::MigrateState => {
             msg!("migrating state");
             process_migrate_state(program_id, accounts)?;
         }
-        Instruction::SetAdmin(SetAdminInstruction { admin }) => {
-            msg!("updating admin state");
-            process_set_admin(program_id, accounts, admin)?;
+        Instruction::SetAdmin(_) => {
+            msg!("set admin is replaced by propose and accept admin");
+            return Error::SetAdminDeprecated.into();
+        }
+        Instruction::ProposeAdmin(SetAdminInstruction { admin }) => {
+            msg!("proposing admin");
+            process_propose_admin(program_id, accounts, admin)?;
         }
         Instruction::DepositCommission => {
             msg!("depositing commission");
@@ -215,6 +294,26 @@ pub fn process_instruction(
             msg!("repost compressed");
             process_repost_compressed(program_id, accounts)?;
         }
+        Instruction::UpdateConfig(args) => {
+            msg!("updating config");
+            process_update_config(program_id, accounts, args)?;
+        }
+        Instruction::AcceptAdmin => {
+            msg!("accepting admin");
+            process_accept_admin(program_id, accounts)?;
+        }
+        Instruction::InitMultisig(args) => {
+            msg!("initializing multisig");
+            process_init_multisig(program_id, accounts, args)?;
+        }
+        Instruction::SetRoundFee(args) => {
+            msg!("setting round fee");
+            process_set_round_fee(program_id, accounts, args)?;
+        }
+        Instruction::SetRoundPolicy(args) => {
+            msg!("setting round policy");
+            process_set_round_policy(program_id, accounts, args)?;
+        }
     }
 
     Ok(())
@@ -298,6 +397,27 @@ macro_rules! collection_mint {
     };
 }
 
+/// Registry entry of a round by its number, see `RoundRecord`
+pub fn round_record_address(program_id: &Pubkey, state: &Pubkey, number: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[V1, ROUND_RECORD_SEED, state.as_ref(), &number.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Fanout receiving round bids under given round fee version, created by `CreateRound`
+pub fn round_fanout_address(program_id: &Pubkey, state: &Pubkey, version: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            V1,
+            ROUND_FANOUT_SEED,
+            state.as_ref(),
+            &version.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
 // [] derived state account
 // [] token mint addr
 // [signer] funder
@@ -320,21 +440,21 @@ fn process_init_state(
     let clock = Clock::get()?;
 
     if !spl_token::check_id(mint_acc.owner) {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidMint.into();
     }
 
     let (state_addr, state_seed) = contract_state!(program_id, mint_acc.key);
 
     if *state.key != state_addr {
         msg!("invalid derived address {} != {}", state.key, state_addr);
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidStateAddress.into();
     }
 
     // check token has no mint authority
     let mint = spl_token::state::Mint::unpack(&mint_acc.data.borrow())?;
     if mint.mint_authority != COption::None {
         msg!("token should have fixed supply");
-        return Err(ProgramError::InvalidArgument);
+        return Error::MintNotFixedSupply.into();
     }
 
     msg!("creating state account");
@@ -363,56 +483,111 @@ fn process_init_state(
     Ok(())
 }
 
-pub fn create_init_instruction(
-    program_id: &Pubkey,
-    state: &Pubkey,
-    token: &Pubkey,
-    feepayer: &Pubkey,
-    args: InitInstruction,
-) -> Result<instruction::Instruction, ProgramError> {
-    let accounts = vec![
-        AccountMeta::new(state.to_owned(), false),
-        AccountMeta::new_readonly(token.to_owned(), false),
-        AccountMeta::new_readonly(feepayer.to_owned(), true),
-        AccountMeta::new_readonly(spl_token::id(), false),
-        AccountMeta::new_readonly(system_program::id(), false),
-        AccountMeta::new_readonly(*program_id, false),
-    ];
-
-    Ok(instruction::Instruction {
-        program_id: *program_id,
-        accounts,
-        data: Instruction::Init(args).try_to_vec()?,
-    })
-}
-
 // [write] state
+// [write, signer] payer, tops up rent of the grown state
+// [] sysprog
+// v3 states only:
+// [signer] admin (or multisig followed by its signers)
+// addresses v3 states didn't have:
 // [] swap state
-// [] wsol account
+// [] commission wSOL account
+// [] treasury
 pub fn process_migrate_state(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
 ) -> Result<(), ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
-    let state = next_account_info(account_info_iter)?;
-    let token_mint = next_account_info(account_info_iter)?;
-    let wsol_commission = next_account_info(account_info_iter)?;
-    let treasury = next_account_info(account_info_iter)?;
+    let state_acc = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    next_expected_account(account_info_iter, &system_program::ID)?;
+
+    let is_v3 = state_acc.try_borrow_data()?.first() == Some(&ContractStateV3::MAGIC);
+
+    if is_v3 {
+        let v3 = readonly_entity_from_acc::<ContractStateV3>(state_acc, program_id)?.into_inner();
+
+        // addresses are taken as is, only admin can choose them
+        let _admin = next_admin_signer(account_info_iter, &v3.admin, program_id)?;
 
-    let mut state_data = state.data.borrow_mut();
+        let swap_state_acc = next_account_info(account_info_iter)?;
+        check_swap_state(swap_state_acc, &v3.token)?;
 
-    if state_data.is_empty() {
-        return Err(ProgramError::InvalidAccountData);
+        let commission_acc = next_account_info(account_info_iter)?;
+        check_commission_wallet(commission_acc)?;
+
+        let treasury = next_account_info(account_info_iter)?;
+
+        msg!("migrating v3 state");
+        let state = v3
+            .upgrade_with(
+                *swap_state_acc.key,
+                *commission_acc.key,
+                *treasury.key,
+                Clock::get()?.unix_timestamp,
+            )
+            .upgrade();
+
+        state.serialize_to(&mut state_acc.try_borrow_mut_data()?)?;
     }
 
-    try_migrate_state(
-        &mut state_data,
-        *token_mint.key,
-        *wsol_commission.key,
-        *treasury.key,
-        Clock::get()?.unix_timestamp,
-    )?;
+    // upgraded on load
+    let state = entity_from_acc::<ContractState>(state_acc, program_id)?;
+    state.commit()?;
+
+    // owner is checked on load above
+    if state_acc.data_len() < STATE_ACC_SIZE {
+        state_acc.realloc(STATE_ACC_SIZE, true)?;
+    }
+
+    let rent = Rent::get()?;
+    let required = rent
+        .minimum_balance(state_acc.data_len())
+        .saturating_sub(state_acc.lamports());
+
+    if required > 0 {
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        invoke(
+            &system_instruction::transfer(payer.key, state_acc.key, required),
+            accounts,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// commission is collected into a wSOL token wallet
+fn check_commission_wallet(commission_acc: &AccountInfo) -> Result<(), ProgramError> {
+    if !spl_token::check_id(commission_acc.owner) {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let wallet = spl_token::state::Account::unpack(&commission_acc.try_borrow_data()?)?;
+
+    if wallet.mint != spl_token::native_mint::ID {
+        msg!("commission account mint is {}", wallet.mint);
+        return Error::InvalidCommissionAccount.into();
+    }
+
+    Ok(())
+}
+
+/// swap pool of `token` (token A) to wSOL
+fn check_swap_state(swap_state_acc: &AccountInfo, token: &Pubkey) -> Result<(), ProgramError> {
+    if *swap_state_acc.owner != swap_program::ID {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let swap_state =
+        spl_token_swap::state::SwapVersion::unpack(&swap_state_acc.try_borrow_data()?)?;
+
+    if swap_state.token_a_mint() != token {
+        msg!("swap token A is {}", swap_state.token_a_mint());
+        return Error::InvalidSwapState.into();
+    }
 
     Ok(())
 }
@@ -441,12 +616,12 @@ fn process_create_wallets(
 
     let (wallet_addr, wallet_seed) = contract_wallet!(program_id, token_mint.key);
     if *wallet.key != wallet_addr {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidWallet.into();
     }
 
     let (vault_addr, vault_seed) = contract_vault!(program_id, token_mint.key);
     if *vault.key != vault_addr {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidVault.into();
     }
 
     let (transfer_authority, _) = authority!(program_id);
@@ -491,49 +666,326 @@ fn process_create_wallets(
     Ok(())
 }
 
-pub fn create_wallets(
+// [writable] state
+// [signer] admin (or multisig followed by its signers)
+fn process_propose_admin(
     program_id: &Pubkey,
-    token_mint: &Pubkey,
-    fee_payer: &Pubkey,
-) -> instruction::Instruction {
-    let (wallet_addr, _) = contract_wallet!(program_id, token_mint);
-    let (vault_addr, _) = contract_vault!(program_id, token_mint);
-
-    let accounts = vec![
-        AccountMeta::new_readonly(token_mint.to_owned(), false),
-        AccountMeta::new(wallet_addr.to_owned(), false),
-        AccountMeta::new(vault_addr.to_owned(), false),
-        AccountMeta::new_readonly(fee_payer.to_owned(), true),
-        AccountMeta::new_readonly(spl_token::id(), false),
-        AccountMeta::new_readonly(system_program::id(), false),
-        AccountMeta::new_readonly(sysvar::rent::ID, false),
-        AccountMeta::new_readonly(*program_id, false),
-    ];
+    accounts: &[AccountInfo],
+    new_admin: Pubkey,
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
 
-    instruction::Instruction {
-        program_id: *program_id,
-        accounts,
-        data: Instruction::CreateWallets.try_to_vec().unwrap(),
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+    let _admin = next_admin_signer(account_info_iter, &state.admin, program_id)?;
+
+    state.pending_admin = Some(new_admin);
+
+    Event::AdminProposed {
+        state: *state_acc.key,
+        admin: new_admin,
     }
+    .emit()?;
+
+    state.commit()
 }
 
 // [writable] state
-// [signer] current admin
-fn process_set_admin(
+// [signer] pending admin (or multisig followed by its signers)
+fn process_accept_admin(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+
+    let pending_admin = state.pending_admin.ok_or(Error::NoPendingAdmin)?;
+    let _admin = next_admin_signer(account_info_iter, &pending_admin, program_id)?;
+
+    Event::AdminAccepted {
+        state: *state_acc.key,
+        old: state.admin,
+        new: pending_admin,
+    }
+    .emit()?;
+
+    state.admin = pending_admin;
+    state.pending_admin = None;
+
+    state.commit()
+}
+
+// [writable, signer] multisig account, allocated with `Multisig::SIZE` and owned by this program
+fn process_init_multisig(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    new_admin: Pubkey,
+    args: InitMultisigInstruction,
 ) -> Result<(), ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
-    let (mut state, _) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
-    let _admin = next_signer_account(account_info_iter, &state.admin)?;
+    let multisig_acc = next_account_info(account_info_iter)?;
+
+    // otherwise anyone could initialize an account allocated in a separate transaction
+    if !multisig_acc.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if multisig_acc.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let rent = Rent::get()?;
+    if !rent.is_exempt(multisig_acc.lamports(), multisig_acc.data_len()) {
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+
+    let multisig = Multisig::new(args.threshold, args.signers)?;
 
-    state.admin = new_admin;
+    initialize_entity(multisig, multisig_acc)?;
 
     Ok(())
 }
 
+// [writable] state
+// [signer] admin
+// [] commission wSOL account (only if commission is updated)
+// [] swap state (only if swap state is updated)
+fn process_update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: UpdateConfigInstruction,
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+    let _admin = next_admin_signer(account_info_iter, &state.admin, program_id)?;
+
+    let mut changes = Vec::new();
+
+    if let Some(commission) = args.commission {
+        let commission_acc = next_expected_account(account_info_iter, &commission)?;
+        check_commission_wallet(commission_acc)?;
+
+        changes.push((ConfigField::Commission, state.commission_addr, commission));
+        state.commission_addr = commission;
+    }
+
+    if let Some(treasury) = args.treasury {
+        changes.push((ConfigField::Treasury, state.treasury_addr, treasury));
+        state.treasury_addr = treasury;
+    }
+
+    if let Some(swap_state_addr) = args.swap_state {
+        let swap_state_acc = next_expected_account(account_info_iter, &swap_state_addr)?;
+        check_swap_state(swap_state_acc, &state.token)?;
+
+        changes.push((ConfigField::SwapState, state.swap_state, swap_state_addr));
+        state.swap_state = swap_state_addr;
+    }
+
+    if let Some(owner) = args.owner {
+        changes.push((ConfigField::Owner, state.owner, owner));
+        state.owner = owner;
+    }
+
+    for (field, old, new) in changes {
+        Event::ConfigUpdated {
+            state: *state_acc.key,
+            field,
+            old,
+            new,
+        }
+        .emit()?;
+    }
+
+    state.commit()
+}
+
+// [writable] state, grown by `MigrateState` if it's created before v5 and the split doesn't fit
+// [signer] admin (or multisig followed by its signers)
+fn process_set_round_fee(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: SetRoundFeeInstruction,
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+    let _admin = next_admin_signer(account_info_iter, &state.admin, program_id)?;
+
+    let version = state.set_round_fee(args.members.clone())?;
+
+    Event::RoundFeeUpdated {
+        state: *state_acc.key,
+        version,
+        members: args.members,
+    }
+    .emit()?;
+
+    state.commit()
+}
+
+// [writable] state
+// [signer] admin (or multisig followed by its signers)
+fn process_set_round_policy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: SetRoundPolicyInstruction,
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+    let _admin = next_admin_signer(account_info_iter, &state.admin, program_id)?;
+
+    if let Some(policy) = &args.policy {
+        policy.validate()?;
+    }
+
+    state.round_policy = args.policy;
+
+    Event::RoundPolicyUpdated {
+        state: *state_acc.key,
+        policy: args.policy,
+    }
+    .emit()?;
+
+    state.commit()
+}
+
+// [writable] state
+// [signer] admin
+// [] drop wallet
+fn process_create_drop(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: CreateDropInstruction,
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, _) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+    let _admin = next_admin_signer(account_info_iter, &state.admin, program_id)?;
+
+    let (wallet_addr, _) = contract_wallet!(program_id, state.token);
+    let wallet = next_expected_token_wallet(account_info_iter, &wallet_addr)?;
+
+    // token commission of every sale is paid from the same wallet
+    let reserved = drop_reserve(args.amount).ok_or(Error::Overflow)?;
+
+    if args.amount == 0 || reserved > wallet.amount {
+        msg!(
+            "invalid drop amount: {} with commission {} (wallet balance {})",
+            args.amount,
+            reserved,
+            wallet.amount
+        );
+        return Error::InvalidDropAmount.into();
+    }
+
+    let clock = Clock::get()?;
+
+    state.create_drop(
+        args.price,
+        args.id,
+        args.amount,
+        clock.unix_timestamp,
+        args.start_date,
+        args.end_date,
+    )?;
+
+    state.commit()
+}
+
+// [writable] state
+// [writable] drop wallet
+// [writable, signer] buyer
+// [writable] buyer atoken wallet
+// [writable] owner
+// [writable] treasury
+// [] commission wSOL account
+// [writable] commission owner atoken wallet
+// [] contract transfer authority
+// [] token program
+// [] sysprog
+fn process_buy_from_drop(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: BuyFromDropInstruction,
+) -> Result<(), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
+
+    let (wallet_addr, _) = contract_wallet!(program_id, state.token);
+    next_expected_token_wallet(account_info_iter, &wallet_addr)?; // 2
+
+    let buyer = next_account_info(account_info_iter)?; // 3
+    if !buyer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (buyer_wallet, _) = next_atoken_wallet(account_info_iter, buyer.key, &state.token)?; // 4
+
+    next_expected_account(account_info_iter, &state.owner)?; // 5
+    next_expected_account(account_info_iter, &state.treasury_addr)?; // 6
+
+    let commission = next_expected_token_wallet(account_info_iter, &state.commission_addr)?; // 7
+    let (commission_wallet, _) =
+        next_atoken_wallet(account_info_iter, &commission.owner, &state.token)?; // 8
+
+    let (authority, authority_seeds) = authority!(program_id);
+    next_expected_account(account_info_iter, &authority)?; // 9
+    next_expected_account(account_info_iter, &spl_token::ID)?; // 10
+    next_expected_account(account_info_iter, &system_program::ID)?; // 11
+
+    if args.amount == 0 {
+        return Error::ZeroAmount.into();
+    }
+
+    let clock = Clock::get()?;
+
+    let split = state.calculate_buy_split(
+        state_acc.key,
+        clock.unix_timestamp,
+        args.amount,
+        args.expected_price,
+    )?;
+
+    state.record_drop_sale(args.amount)?;
+
+    // same as with reposts, lamport commission is later deposited to the pool
+    for (recipient, amount) in split.lamports {
+        invoke(
+            &system_instruction::transfer(buyer.key, &recipient, amount),
+            accounts,
+        )?;
+    }
+
+    let transfer = spl_token::instruction::transfer(
+        &spl_token::ID,
+        &wallet_addr,
+        &buyer_wallet,
+        &authority,
+        &[],
+        args.amount,
+    )?;
+    invoke_signed(&transfer, accounts, &[authority_seeds])?;
+
+    if split.token_commission > 0 {
+        let transfer = spl_token::instruction::transfer(
+            &spl_token::ID,
+            &wallet_addr,
+            &commission_wallet,
+            &authority,
+            &[],
+            split.token_commission,
+        )?;
+        invoke_signed(&transfer, accounts, &[authority_seeds])?;
+    }
+
+    if state.drop.is_none() {
+        msg!("drop sold out");
+    }
+
+    state.commit()
+}
+
 pub mod swap_program {
     use solana_program::declare_id;
 
@@ -576,7 +1028,8 @@ fn process_deposit_commission(
     let account_info_iter = &mut accounts.iter();
     let rent = Rent::get()?;
 
-    let (state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
+    let (state, state_acc) =
+        next_readonly_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
 
     let (vault, _) = contract_vault!(program_id, state.token);
     let vault_wallet = next_expected_token_wallet(account_info_iter, &vault)?; // 2
@@ -606,7 +1059,8 @@ fn process_deposit_commission(
 
     let token_acc_size = spl_token::state::Account::LEN;
     let wallet_rent = rent.minimum_balance(token_acc_size);
-    let state_rent = rent.minimum_balance(STATE_ACC_SIZE);
+    // state may not be migrated to the current size yet
+    let state_rent = rent.minimum_balance(state_acc.data_len());
 
     // calculate deposit amount
     let to_deposit_lp = state_acc
@@ -698,6 +1152,13 @@ fn process_deposit_commission(
     let close = close_account(&spl_token::ID, stash.key, state_acc.key, &authority, &[])?;
     invoke_signed(&close, accounts, &[authority_seeds])?;
 
+    Event::CommissionDeposited {
+        state: *state_acc.key,
+        lamports: to_deposit_lp,
+        lp_tokens: pool_tokens,
+    }
+    .emit()?;
+
     Ok(())
 }
 
@@ -706,21 +1167,8 @@ fn calculate_pool_tokens(
     pool_token_supply: u64,
     swap_token_balance: u64,
 ) -> Result<u64, ProgramError> {
-    let wsol_amount = PreciseNumber::new(wsol_amount as u128).unwrap();
-    let pool_token_supply = PreciseNumber::new(pool_token_supply as u128).unwrap();
-    let swap_token_balance = PreciseNumber::new(swap_token_balance as u128).unwrap();
-
-    Ok(wsol_amount
-        .checked_mul(&pool_token_supply)
-        .ok_or(Error::Overflow)?
-        .checked_div(&swap_token_balance)
-        .ok_or(Error::Overflow)?
-        .floor()
-        .ok_or(Error::Overflow)?
-        .to_imprecise()
-        .ok_or(Error::Overflow)?
-        .try_into()
-        .map_err(|_| Error::Overflow)?)
+    mul_div_floor(wsol_amount, pool_token_supply, swap_token_balance)
+        .ok_or_else(|| Error::Overflow.into())
 }
 
 fn process_register_post(
@@ -732,7 +1180,8 @@ fn process_register_post(
 
     let (authority, authority_seeds) = authority!(program_id);
 
-    let (state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
+    let (state, state_acc) =
+        next_readonly_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
 
     // check priviliged account signed this data
     // next_signer_account(account_info_iter, &state.admin)?;
@@ -954,6 +1403,15 @@ fn process_register_post(
     // record creation date
     save_post_info(program_id, payer.key, post_info, post_info_acc, accounts)?;
 
+    Event::PostRegistered {
+        state: *state_acc.key,
+        post_id: args.post_id,
+        master_mint: master_post_mint,
+        repost_price: args.repost_price,
+        royalty_address: args.royalty_addr,
+    }
+    .emit()?;
+
     Ok(())
 }
 
@@ -967,7 +1425,7 @@ fn save_post_info(
     let (derived_info_key, post_info_seeds) = post_info!(program_id, post_info.post_id);
 
     if *post_info_acc.key != derived_info_key {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidPostInfoAddress.into();
     }
 
     let rent = Rent::get()?;
@@ -1042,14 +1500,17 @@ fn process_repost(
     let account_info_iter = &mut accounts.iter();
     let (authority, authority_seeds) = authority!(program_id);
 
-    let (state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
+    // copied out, state account takes part in CPI below
+    let state_acc = next_account_info(account_info_iter)?;
+    let state = *zero_copy_from_acc::<ContractStateHeader>(state_acc, program_id)?;
 
     let master_mint = next_account_info(account_info_iter)?;
     let master_wallet = next_account_info(account_info_iter)?;
     let master_metadata = next_account_info(account_info_iter)?;
     let master_edition = next_account_info(account_info_iter)?;
 
-    let (post_info, _post_info_acc) = next_entity::<_, PostInfo>(account_info_iter, program_id)?;
+    let (post_info, _post_info_acc) =
+        next_readonly_entity::<_, PostInfo>(account_info_iter, program_id)?;
 
     let repost_mint = next_account_info(account_info_iter)?;
     let repost_mint_key = repost_mint.key;
@@ -1084,14 +1545,14 @@ fn process_repost(
     // check this is the same post info
     if post_info.state != *state_acc.key {
         msg!("post belongs to a different state");
-        return Err(ProgramError::InvalidArgument);
+        return Error::PostStateMismatch.into();
     }
 
     let (derived_master_mint, _) = master_post_mint!(program_id, post_info.post_id);
 
     if *master_mint.key != derived_master_mint {
         msg!("post_info id does not match master mint supplied");
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidMasterMint.into();
     }
 
     let clock = Clock::get()?;
@@ -1110,22 +1571,16 @@ fn process_repost(
         repost_price = 0;
     }
 
-    msg!("repost price: {}", repost_price);
-
-    let split = ContractState::calculate_split_by_lamports(repost_price).ok_or(Error::Overflow)?;
+    let split = lamport_split_plan(state_acc.key, &state.owner, &state.treasury_addr)
+        .split(repost_price)
+        .ok_or(Error::Overflow)?;
 
-    invoke(
-        &system_instruction::transfer(user.key, &state.owner, split.owner_split),
-        accounts,
-    )?;
-    invoke(
-        &system_instruction::transfer(user.key, &state.treasury_addr, split.treasury_split),
-        accounts,
-    )?;
-    invoke(
-        &system_instruction::transfer(user.key, state_acc.key, split.commission),
-        accounts,
-    )?;
+    for &(recipient, amount) in &split {
+        invoke(
+            &system_instruction::transfer(user.key, &recipient, amount),
+            accounts,
+        )?;
+    }
 
     // mint fresh repost nft
     mint_nft(
@@ -1147,7 +1602,7 @@ fn process_repost(
         mpl_token_metadata::pda::find_master_edition_account(master_mint.key);
 
     if expected_edition != *master_edition.key {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidMasterEdition.into();
     }
 
     let edition_data: mpl_token_metadata::state::MasterEditionV2 =
@@ -1234,7 +1689,18 @@ fn process_repost(
         invoke(&create_atoken, accounts)?;
     }
 
-    msg!("will-receive {}", amount);
+    Event::Reposted {
+        state: *state_acc.key,
+        post_id: post_info.post_id,
+        user: *user.key,
+        repost_mint: *repost_mint_key,
+        price: repost_price,
+        split,
+        // owner reposts are not recorded
+        receive_amount: if *user.key != state.owner { amount } else { 0 },
+        compressed: false,
+    }
+    .emit()?;
 
     Ok(())
 }
@@ -1296,25 +1762,12 @@ fn process_redeem_repost(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
 ) -> Result<(), ProgramError> {
-    let account_info_iter = &mut accounts.iter().peekable();
-
-    let (state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
-
-    {
-        // migration
-        let record_acc = account_info_iter
-            .peek()
-            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let account_info_iter = &mut accounts.iter();
 
-        let mut data = record_acc.try_borrow_mut_data()?;
-        if data.len() == 145 && data[0] == RepostRecord::MAGIC {
-            // old size
-            record_acc.realloc(RepostRecord::SIZE, false)?;
-
-            data[144..].fill(0);
-        }
-    }
-    let (record, record_acc) = next_entity::<_, RepostRecord>(account_info_iter, program_id)?; // 2
+    let (state, state_acc) =
+        next_readonly_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
+    let (record, record_acc) =
+        next_readonly_entity::<_, RepostRecord>(account_info_iter, program_id)?; // 2
 
     let (vault_addr, _) = contract_vault!(program_id, state.token);
     let _vault_wallet = next_expected_token_wallet(account_info_iter, &vault_addr)?; // 3
@@ -1330,17 +1783,13 @@ fn process_redeem_repost(
 
     if record.state != *state_acc.key {
         msg!("state != record.state");
-        return Err(ProgramError::InvalidArgument);
+        return Error::RepostStateMismatch.into();
     }
 
     let clock = Clock::get()?;
 
-    let can_redeem = record.can_redeem(clock.unix_timestamp);
-    msg!("can redeem: {}", can_redeem);
-
-    // check if eligible
-    if !can_redeem {
-        msg!("cant-redeem-now");
+    // not eligible yet, no `RepostRedeemed` is emitted
+    if !record.can_redeem(clock.unix_timestamp) {
         return Ok(());
     }
 
@@ -1355,13 +1804,19 @@ fn process_redeem_repost(
 
     invoke_signed(&transfer, accounts, &[authority_seeds])?;
 
-    drop(record);
-
     // return lamports to the user
     let mut payer_lamports = return_payer.try_borrow_mut_lamports()?;
 
     erase_repost_record(record_acc, &mut payer_lamports)?;
 
+    Event::RepostRedeemed {
+        state: *state_acc.key,
+        post_id: record.post_id,
+        user: record.user,
+        amount: record.receive_amount,
+    }
+    .emit()?;
+
     Ok(())
 }
 
@@ -1385,16 +1840,15 @@ fn process_create_round(
 ) -> Result<(), ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
-    let (mut state, state_acc) =
-        next_entity::<_, ContractState>(account_info_iter, program_id).unwrap(); // 1
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
 
     let (vault_addr, _) = contract_vault!(program_id, state.token);
-    let _vault_wallet = next_expected_token_wallet(account_info_iter, &vault_addr)?; // 2
+    let vault_wallet = next_expected_token_wallet(account_info_iter, &vault_addr)?; // 2
 
     let (authority, authority_seeds) = authority!(program_id);
     next_expected_account(account_info_iter, &authority)?; // 3
 
-    let fanout_acc = next_account_info(account_info_iter)?; // 4
+    let fanout_acc = next_account_info(account_info_iter)?; // 4, see `round_fanout_address`
     let payer = next_account_info(account_info_iter)?; // 5
 
     // passed as is
@@ -1410,48 +1864,77 @@ fn process_create_round(
 
     //
 
-    let expected_members = vec![
-        fanout::state::Member {
-            address: state.treasury_addr, // treasury addr
-            share: 9650,
-        },
-        fanout::state::Member {
-            address: state.admin, // our fee
-            share: 250,
-        },
-        fanout::state::Member {
-            address: *state_acc.key, // LP
-            share: 100,
-        },
-    ];
+    // owner, or admin (or multisig followed by its signers)
+    let creator =
+        next_owner_or_admin_signer(account_info_iter, &state.owner, &state.admin, program_id)?; // 12
+
+    // owner can't vest more of the vault to themselves than admin allows
+    let max_owner_vesting_bps = state.max_owner_vesting_bps();
+    if *creator.key == state.owner && args.owner_vesting_bps > max_owner_vesting_bps {
+        msg!(
+            "owner vesting {} is above {}",
+            args.owner_vesting_bps,
+            max_owner_vesting_bps
+        );
+        return Error::OwnerVestingAboveCap.into();
+    }
+
+    let round_record_acc = next_account_info(account_info_iter)?; // 13, see `round_record_address`
+
+    let clock = Clock::get()?;
+
+    // amount is chosen by the user within round policy
+    let amount = args.offer_amount;
+    state.record_round(clock.unix_timestamp, amount, vault_wallet.amount)?;
+
+    // bids of rounds created under each fee version go to its own fanout,
+    // so changing the fee doesn't affect rounds in progress
+    let round_fee = state.effective_round_fee(state_acc.key);
+    let expected_members = round_fee.members;
+
+    let version_bytes = round_fee.version.to_le_bytes();
+    let (fanout_addr, fanout_bump) =
+        round_fanout_address(program_id, state_acc.key, round_fee.version);
+
+    if *fanout_acc.key != fanout_addr {
+        msg!("invalid round fanout {} != {}", fanout_acc.key, fanout_addr);
+        return Error::InvalidRoundFanout.into();
+    }
 
     if *fanout_acc.owner == fanout::ID {
         let fanout_data = fanout_acc.try_borrow_data()?;
-        let fanout = fanout::state::Fanout::try_deserialize(&mut fanout_data.as_ref())?;
 
+        if fanout_data.len() < 8 || fanout_data[..8] != fanout::state::Fanout::discriminator() {
+            return Error::InvalidRoundFanout.into();
+        }
+
+        // fanouts of older versions are accepted too, they are migrated separately
+        let (fanout, _) = fanout::state::Fanout::read_versioned(&fanout_data[8..])?;
+
+        // members changed on the fanout directly no longer match the fee, set a new round fee instead
         if fanout.members != expected_members {
             msg!("fanout.members != expected_members");
-            return Err(ProgramError::InvalidArgument);
+            return Error::RoundFanoutMismatch.into();
         }
     } else {
-        let rent = Rent::get()?;
+        let size = fanout::state::Fanout::space(expected_members.len());
 
-        let size = 8 + 8 + 4 + (32 + 2) * expected_members.len();
-
-        // system create
-        let create_ix = system_instruction::create_account(
-            payer.key,
-            fanout_acc.key,
-            rent.minimum_balance(size),
-            size as u64,
-            &fanout::ID,
-        );
-
-        invoke(&create_ix, accounts)?;
+        let fanout_seeds: &[&[u8]] = &[
+            V1,
+            ROUND_FANOUT_SEED,
+            state_acc.key.as_ref(),
+            &version_bytes,
+            &[fanout_bump],
+        ];
+        create_pda_account(payer, fanout_acc, size, &fanout::ID, fanout_seeds, accounts)?;
 
         // initalize fanout
+        // split of a fee version never changes, a new round fee gets a new fanout.
+        // Only the program can close it, signing as its authority
         let data = fanout::instruction::Initialize {
             members: expected_members,
+            close_authority: Some(authority),
+            update_authority: None,
         }
         .data();
 
@@ -1465,10 +1948,6 @@ fn process_create_round(
         invoke(&init_ix, accounts)?;
     }
 
-    // amount is chosen by the user
-    // todo: limits on how much one can offer
-    let amount = args.offer_amount;
-
     // approve this amount to be tranfered from the vault
     let approve_ix = spl_token::instruction::approve(
         &spl_token::ID,
@@ -1480,10 +1959,17 @@ fn process_create_round(
     )?;
     invoke_signed(&approve_ix, accounts, &[authority_seeds])?;
 
+    // SOL bids go to fanout itself, token bids to its vault (created with fanout's `init_vault`)
+    let recipient = if *bid_mint.key == spl_token::native_mint::ID {
+        *fanout_acc.key
+    } else {
+        fanout::vault_address(fanout_acc.key, bid_mint.key)
+    };
+
     let data = round::instruction::CreateRound {
         params: round::CreateRoundParams {
             heir: state.owner,
-            recipient: *fanout_acc.key,
+            recipient,
             target_bid: args.target_bid,
             bidding_start: args.bidding_start,
             bidding_end: args.bidding_end,
@@ -1510,9 +1996,58 @@ fn process_create_round(
     };
     invoke_signed(&ix, accounts, &[authority_seeds])?;
 
-    state.current_round = Some(*round.key);
+    // register round, so it can be claimed independently of other rounds
+    let record = state.register_round(
+        state_acc.key,
+        round.key,
+        clock.unix_timestamp,
+        args.owner_vesting_bps,
+        args.owner_release_duration,
+    )?;
 
-    Ok(())
+    let number_bytes = record.number.to_le_bytes();
+    let (record_addr, record_bump) = round_record_address(program_id, state_acc.key, record.number);
+
+    if *round_record_acc.key != record_addr {
+        msg!(
+            "invalid round record {} != {}",
+            round_record_acc.key,
+            record_addr
+        );
+        return Error::InvalidRoundRecordAddress.into();
+    }
+
+    let record_seeds: &[&[u8]] = &[
+        V1,
+        ROUND_RECORD_SEED,
+        state_acc.key.as_ref(),
+        &number_bytes,
+        &[record_bump],
+    ];
+    create_pda_account(
+        payer,
+        round_record_acc,
+        RoundRecord::SIZE,
+        program_id,
+        record_seeds,
+        accounts,
+    )?;
+
+    Event::RoundCreated {
+        state: *state_acc.key,
+        round: *round.key,
+        number: record.number,
+        offer_amount: amount,
+        fanout: fanout_addr,
+        fee_version: round_fee.version,
+        owner_vesting_bps: args.owner_vesting_bps,
+        owner_release_duration: args.owner_release_duration,
+    }
+    .emit()?;
+
+    initialize_entity(record, round_record_acc)?;
+
+    state.commit()
 }
 
 fn process_claim_round_vesting(
@@ -1521,7 +2056,7 @@ fn process_claim_round_vesting(
 ) -> Result<(), ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
-    let (mut state, _state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
+    let (mut state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?; // 1
 
     let (vault_addr, _) = contract_vault!(program_id, state.token);
     let vault_wallet = next_expected_token_wallet(account_info_iter, &vault_addr)?; // 2
@@ -1533,32 +2068,60 @@ fn process_claim_round_vesting(
 
     let (owner_wallet, _) = next_atoken_wallet(account_info_iter, &state.owner, &state.token)?; // 5
 
+    let clock = Clock::get()?;
+
     // deserialize round
     let round_data = round_acc.try_borrow_data()?;
     let round = round::state::Round::try_deserialize(&mut round_data.as_ref())?;
 
-    // check round is saved in state
-    if state.current_round != Some(*round_acc.key) {
-        msg!("state.current_round != round_acc.key");
-        return Ok(());
-    }
-
     // check round was accepted
     if round.status != round::state::RoundStatus::Accepted {
         msg!("round.status != RoundStatus::Accepted");
-        return Err(ProgramError::InvalidArgument);
+        return Error::RoundNotAccepted.into();
     }
 
-    let amount = round
+    let total_offer = round
         .total_offer
-        .expect("accepted round should always have total_offer set")
-        .checked_div(10)
-        .unwrap();
+        .expect("accepted round should always have total_offer set");
 
-    // check
-    let amount = amount.min(vault_wallet.amount);
+    // rounds created before the registry are claimed through `current_round`
+    let (amount, completed) = if state.current_round == Some(*round_acc.key) {
+        state.current_round = None;
+
+        let amount = LEGACY_OWNER_VESTING
+            .of(total_offer)
+            .ok_or(Error::Overflow)?
+            .min(vault_wallet.amount);
+
+        (amount, true)
+    } else {
+        let (mut record, record_acc) =
+            next_entity::<_, RoundRecord>(account_info_iter, program_id)?; // 6
+
+        let (record_addr, _) = round_record_address(program_id, state_acc.key, record.number);
+
+        if *record_acc.key != record_addr
+            || record.state != *state_acc.key
+            || record.round != *round_acc.key
+        {
+            msg!("round {} is not registered", round_acc.key);
+            return Error::RoundNotRegistered.into();
+        }
+
+        let amount = record.claim(
+            total_offer,
+            round.bidding_end,
+            clock.unix_timestamp,
+            vault_wallet.amount,
+        )?;
+        let completed = record.status != RoundRecordStatus::Open;
+
+        record.commit()?;
+
+        (amount, completed)
+    };
 
-    // tranfer 10% of round offer to owner
+    // tranfer owner part of round offer
     let ix = spl_token::instruction::transfer(
         &spl_token::ID,
         &vault_addr,
@@ -1570,10 +2133,22 @@ fn process_claim_round_vesting(
 
     invoke_signed(&ix, accounts, &[authority_seeds])?;
 
-    state.current_round = None;
-    state.completed_rounds_count = state.completed_rounds_count.checked_add(1).unwrap();
+    if completed {
+        state.completed_rounds_count = state
+            .completed_rounds_count
+            .checked_add(1)
+            .ok_or(Error::Overflow)?;
+    }
 
-    Ok(())
+    Event::RoundVestingClaimed {
+        state: *state_acc.key,
+        round: *round_acc.key,
+        amount,
+        completed,
+    }
+    .emit()?;
+
+    state.commit()
 }
 
 mod tree {
@@ -1642,8 +2217,10 @@ fn process_repost_compressed(
 ) -> Result<(), ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
-    let (state, state_acc) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
-    let (post_info, _post_info_acc) = next_entity::<_, PostInfo>(account_info_iter, program_id)?;
+    let (state, state_acc) =
+        next_readonly_entity::<_, ContractState>(account_info_iter, program_id)?;
+    let (post_info, _post_info_acc) =
+        next_readonly_entity::<_, PostInfo>(account_info_iter, program_id)?;
     let user = next_account_info(account_info_iter)?;
     let user_wallet = next_account_info(account_info_iter)?;
     let payer = next_account_info(account_info_iter)?;
@@ -1668,7 +2245,7 @@ fn process_repost_compressed(
     // check this is the same post info
     if post_info.state != *state_acc.key {
         msg!("post belongs to a different state");
-        return Err(ProgramError::InvalidArgument);
+        return Error::PostStateMismatch.into();
     }
 
     let clock = Clock::get()?;
@@ -1798,39 +2375,23 @@ fn process_repost_compressed(
 
     invoke_signed(&ix, accounts, &[authority_seeds])?;
 
-    Ok(())
-}
-
-// lord forgive me for this
-fn try_fix_state(accounts: &[AccountInfo]) {
-    let mut state_data = accounts[0].try_borrow_mut_data().unwrap();
-
-    if state_data.get(0) != Some(&ContractState::MAGIC) {
-        return;
-    }
-
-    let drop_option_offset = 210;
-    let mut current_round_offset = 211;
-    if state_data[drop_option_offset] == 1 {
-        current_round_offset += 48;
+    Event::Reposted {
+        state: *state_acc.key,
+        post_id: post_info.post_id,
+        user: *user.key,
+        repost_mint: *repost_mint.key,
+        price: 0,
+        split: Vec::new(),
+        receive_amount: if *user.key != state.owner {
+            FREE_REPOST_RECEIVE_AMOUNT
+        } else {
+            0
+        },
+        compressed: true,
     }
-    let completed_rounds_offset = if state_data[current_round_offset] == 1 {
-        current_round_offset + 1 + 32
-    } else {
-        current_round_offset + 1
-    };
-    let completed_rounds_count: u64 = u64::from_le_bytes(
-        state_data[completed_rounds_offset..completed_rounds_offset + 8]
-            .try_into()
-            .unwrap(),
-    );
-
-    let is_broken = state_data[current_round_offset] > 1 || completed_rounds_count > 1000;
+    .emit()?;
 
-    if state_data[0] == ContractState::MAGIC && is_broken {
-        msg!("fixing broken state");
-        state_data[current_round_offset..].fill(0);
-    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -2,16 +2,147 @@ use crate::error::Error;
 use crate::vest::*;
 use crate::{consts::*, InitInstruction};
 use borsh::{BorshDeserialize, BorshSerialize};
-use human_common::entity::Entity;
-use solana_program::program_memory::sol_memcpy;
+use bytemuck::{Pod, Zeroable};
+use checked_math::{mul_div_floor, Bps};
+use fanout::state::Member;
+use human_common::entity::{migrate_from, Entity, Migrate, ZeroCopyEntity};
+use human_common::impl_borsh_for_pod;
+use human_common::split::{Remainder, SplitPlan};
 use solana_program::{clock::UnixTimestamp, msg, program_error::ProgramError, pubkey::Pubkey};
-use spl_math::precise_number::PreciseNumber;
 
-/// Since accounts can't be resized this is constant size
-/// to allow some headroom for future migrations
-pub const STATE_ACC_SIZE: usize = 512 + 1;
+/// Size state accounts are created with,
+/// leaves room for a round fee and future migrations
+pub const STATE_ACC_SIZE: usize = 1024 + 1;
 
-pub type ContractState = ContractStateV4;
+/// Size of state accounts created before v5. They are upgraded in place,
+/// since v5 fits in them until a round fee is set. `MigrateState` grows them to `STATE_ACC_SIZE`
+pub const STATE_ACC_SIZE_V4: usize = 512 + 1;
+
+pub type ContractState = ContractStateV5;
+
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct ContractStateV5 {
+    /// Associated token mint
+    pub token: Pubkey,
+    /// Alice
+    pub owner: Pubkey,
+    /// required for priveleged operations
+    pub admin: Pubkey,
+    /// comission WSOL account
+    pub commission_addr: Pubkey,
+    /// owner treasury
+    pub treasury_addr: Pubkey,
+    /// swap state
+    pub swap_state: Pubkey,
+    /// tokens sold
+    pub sold: u64,
+    /// vesting state
+    pub vest: VestState,
+    /// in progress drop. use helper methods to access this field
+    pub drop: Option<DropV2>,
+    /// round created before `RoundRecord` registry, claimable until vesting is claimed
+    pub current_round: Option<Pubkey>,
+    /// completed rounds count
+    pub completed_rounds_count: u64,
+    /// vesting schedule of the vault, vesting is disabled if not set
+    pub vesting: Option<VestingSchedule>,
+    /// proposed admin, takes over once accepted
+    pub pending_admin: Option<Pubkey>,
+    /// split of round bids, see `effective_round_fee`
+    pub round_fee: Option<RoundFeeConfig>,
+    /// limits on rounds the owner can create, not limited if not set
+    pub round_policy: Option<RoundPolicy>,
+    /// when the last round was created
+    pub last_round_at: Option<UnixTimestamp>,
+    /// start of the period rounds are counted in, see `RoundPolicy::period_length`
+    pub round_period_start: UnixTimestamp,
+    /// rounds created since `round_period_start`
+    pub round_period_count: u16,
+    /// rounds ever registered, number of the next `RoundRecord`
+    pub rounds_created: u64,
+}
+
+impl Entity for ContractStateV5 {
+    // older states keep their accounts, see `STATE_ACC_SIZE_V4`
+    const SIZE: usize = STATE_ACC_SIZE_V4;
+    const MAGIC: u8 = 0x46;
+
+    fn migrate(data: &[u8]) -> Result<Self, ProgramError> {
+        migrate_from::<ContractStateV4>(data)
+    }
+}
+
+/// Split of round bids between members of the round fanout, set by admin with `SetRoundFee`.
+/// Every version gets its own fanout, see `round_fanout_address`
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct RoundFeeConfig {
+    /// bumped on every update, version 0 is the legacy split
+    pub version: u32,
+    pub members: Vec<Member>,
+}
+
+impl RoundFeeConfig {
+    /// Same rules as fanout members: up to `MAX_ROUND_FEE_MEMBERS` non-zero shares
+    /// adding up to 10000, and every recipient listed once
+    pub fn validate(members: &[Member]) -> Result<(), ProgramError> {
+        if members.is_empty() || members.len() > MAX_ROUND_FEE_MEMBERS {
+            return Error::InvalidRoundFee.into();
+        }
+
+        let mut total: u16 = 0;
+        for (i, member) in members.iter().enumerate() {
+            if member.share == 0 {
+                return Error::InvalidRoundFee.into();
+            }
+
+            if members[..i].iter().any(|m| m.address == member.address) {
+                return Error::InvalidRoundFee.into();
+            }
+
+            total = total
+                .checked_add(member.share)
+                .ok_or(Error::InvalidRoundFee)?;
+        }
+
+        if total != Bps::DENOMINATOR {
+            return Error::InvalidRoundFee.into();
+        }
+
+        Ok(())
+    }
+}
+
+/// Limits on rounds created with `CreateRound`, set by admin with `SetRoundPolicy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct RoundPolicy {
+    /// largest part of the vault a single round can offer, in bps
+    pub max_offer_bps: u16,
+    /// seconds since the previous round before a new one can be created
+    pub min_round_gap: UnixTimestamp,
+    /// length of the period rounds are counted in, in seconds
+    pub period_length: UnixTimestamp,
+    /// rounds allowed per period
+    pub max_rounds_per_period: u16,
+    /// largest owner vesting of rounds created by owner, in bps
+    pub max_owner_vesting_bps: u16,
+}
+
+impl RoundPolicy {
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.max_offer_bps == 0
+            || self.max_offer_bps > Bps::DENOMINATOR
+            || self.min_round_gap < 0
+            || self.period_length <= 0
+            || self.max_rounds_per_period == 0
+            || self.max_owner_vesting_bps > Bps::DENOMINATOR
+        {
+            return Error::InvalidRoundPolicy.into();
+        }
+
+        Ok(())
+    }
+}
 
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 #[repr(C)]
@@ -38,11 +169,136 @@ pub struct ContractStateV4 {
     pub current_round: Option<Pubkey>,
     /// completed rounds count
     pub completed_rounds_count: u64,
+    /// vesting schedule of the vault, vesting is disabled if not set
+    pub vesting: Option<VestingSchedule>,
+    /// proposed admin, takes over once accepted
+    pub pending_admin: Option<Pubkey>,
 }
 
 impl Entity for ContractStateV4 {
-    const SIZE: usize = STATE_ACC_SIZE;
+    const SIZE: usize = STATE_ACC_SIZE_V4;
     const MAGIC: u8 = 0x45;
+
+    fn decode(data: &[u8]) -> Result<Self, ProgramError> {
+        let current_round_offset = current_round_offset(data);
+
+        if !has_broken_round_fields(data, current_round_offset) {
+            return Ok(Self::deserialize(&mut &data[1..])?);
+        }
+
+        msg!("fixing broken state");
+
+        // fields before the round ones are intact, everything after them reads as reset
+        let head = &mut &data[1..current_round_offset];
+
+        Ok(Self {
+            token: Pubkey::deserialize(head)?,
+            owner: Pubkey::deserialize(head)?,
+            admin: Pubkey::deserialize(head)?,
+            commission_addr: Pubkey::deserialize(head)?,
+            treasury_addr: Pubkey::deserialize(head)?,
+            swap_state: Pubkey::deserialize(head)?,
+            sold: u64::deserialize(head)?,
+            vest: VestState::deserialize(head)?,
+            drop: Option::deserialize(head)?,
+            current_round: None,
+            completed_rounds_count: 0,
+            vesting: None,
+            pending_admin: None,
+        })
+    }
+
+    fn migrate(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.first() == Some(&ContractStateV3::MAGIC) {
+            msg!("v3 state has to be migrated with MigrateState");
+            return Error::StateMigrationRequired.into();
+        }
+
+        Err(ProgramError::InvalidAccountData)
+    }
+}
+
+/// Round fee is left to the legacy split
+impl Migrate for ContractStateV4 {
+    type Next = ContractStateV5;
+
+    fn upgrade(self) -> ContractStateV5 {
+        ContractStateV5 {
+            token: self.token,
+            owner: self.owner,
+            admin: self.admin,
+            commission_addr: self.commission_addr,
+            treasury_addr: self.treasury_addr,
+            swap_state: self.swap_state,
+            sold: self.sold,
+            vest: self.vest,
+            drop: self.drop,
+            current_round: self.current_round,
+            completed_rounds_count: self.completed_rounds_count,
+            vesting: self.vesting,
+            pending_admin: self.pending_admin,
+            round_fee: None,
+            round_policy: None,
+            last_round_at: None,
+            round_period_start: 0,
+            round_period_count: 0,
+            rounds_created: 0,
+        }
+    }
+}
+
+/// Fixed-size prefix of `ContractStateV4` and `ContractStateV5`, borrowed in place by
+/// hot paths that only need addresses, see `ZeroCopyEntity`
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct ContractStateHeader {
+    pub token: Pubkey,
+    pub owner: Pubkey,
+    pub admin: Pubkey,
+    pub commission_addr: Pubkey,
+    pub treasury_addr: Pubkey,
+    pub swap_state: Pubkey,
+    pub sold: u64,
+    pub deployed_at: UnixTimestamp,
+    pub vested_periods: u8,
+}
+
+impl_borsh_for_pod!(ContractStateHeader);
+
+impl Entity for ContractStateHeader {
+    // states that were not migrated yet are smaller
+    const SIZE: usize = STATE_ACC_SIZE_V4;
+    const MAGIC: u8 = ContractStateV5::MAGIC;
+}
+
+impl ZeroCopyEntity for ContractStateHeader {
+    const COMPATIBLE_MAGIC: &'static [u8] = &[ContractStateV4::MAGIC];
+}
+
+/// Some v4 states were written with garbage after `drop`.
+/// Round fields and everything after them are reset if that's the case
+fn has_broken_round_fields(data: &[u8], current_round_offset: usize) -> bool {
+    let completed_rounds_offset = if data[current_round_offset] == 1 {
+        current_round_offset + 1 + 32
+    } else {
+        current_round_offset + 1
+    };
+    let completed_rounds_count: u64 = u64::from_le_bytes(
+        data[completed_rounds_offset..completed_rounds_offset + 8]
+            .try_into()
+            .unwrap(),
+    );
+
+    data[current_round_offset] > 1 || completed_rounds_count > 1000
+}
+
+fn current_round_offset(data: &[u8]) -> usize {
+    let drop_option_offset = 210;
+    if data[drop_option_offset] == 1 {
+        drop_option_offset + 1 + 48
+    } else {
+        drop_option_offset + 1
+    }
 }
 
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
@@ -66,16 +322,30 @@ pub struct Split {
 }
 
 pub struct BuySplit {
-    pub owner_split: u64,
-    pub commission: u64,
-    pub treasury_split: u64,
+    /// lamport transfers from the buyer, see `lamport_split_plan`
+    pub lamports: Vec<(Pubkey, u64)>,
 
     pub token_commission: u64,
 }
 
+/// Commission is sent to the state account (and later deposited to the pool),
+/// treasury gets its cut and the owner receives the rest
+pub fn lamport_split_plan(state: &Pubkey, owner: &Pubkey, treasury: &Pubkey) -> SplitPlan {
+    SplitPlan::new(Remainder::To(*owner))
+        .with_share(*state, BUY_COMMISSION)
+        .with_share(*treasury, TREASURY_COMMISSION)
+}
+
+/// Tokens a drop of `amount` takes from the drop wallet, including commission of every sale.
+/// Commission of each sale is rounded down, so their sum never exceeds commission of the total
+pub fn drop_reserve(amount: u64) -> Option<u64> {
+    amount.checked_add(BUY_COMMISSION.of(amount)?)
+}
+
 impl ContractState {
     pub fn calculate_buy_split(
         &self,
+        state_addr: &Pubkey,
         now: UnixTimestamp,
         token_amount: u64,
         expected_price: u64,
@@ -86,64 +356,24 @@ impl ContractState {
             return Error::ExpectedPriceMismatch.into();
         }
 
+        let plan = lamport_split_plan(state_addr, &self.owner, &self.treasury_addr);
+
         let split =
-            Self::calculate_split(price_per_chatlan, token_amount).ok_or(Error::Overflow)?;
+            Self::calculate_split(&plan, price_per_chatlan, token_amount).ok_or(Error::Overflow)?;
 
         Ok(split)
     }
 
-    fn calculate_split(price_per_chatlan: u64, token_amount: u64) -> Option<BuySplit> {
+    fn calculate_split(
+        plan: &SplitPlan,
+        price_per_chatlan: u64,
+        token_amount: u64,
+    ) -> Option<BuySplit> {
         let lamports = price_per_chatlan.checked_mul(token_amount)?;
 
-        let tokens_precise = PreciseNumber::new(token_amount as u128)?;
-        let commission_percent = PreciseNumber::new(BUY_COMMISSION as u128)?;
-
-        let bsp = PreciseNumber::new(10_000)?;
-        let token_commission: u64 = tokens_precise
-            .checked_mul(&commission_percent)?
-            .checked_div(&bsp)?
-            .to_imprecise()?
-            .try_into()
-            .ok()?;
-
-        let mut split = Self::calculate_split_by_lamports(lamports)?;
-
-        split.token_commission = token_commission;
-
-        Some(split)
-    }
-
-    pub fn calculate_split_by_lamports(lamports: u64) -> Option<BuySplit> {
-        let lamports_precise = PreciseNumber::new(lamports as u128)?;
-
-        let bsp = PreciseNumber::new(10_000)?;
-        let commission_percent = PreciseNumber::new(BUY_COMMISSION as u128)?;
-
-        let additional_split_percent = PreciseNumber::new(TREASURY_COMMISSION as u128)?;
-
-        let commission: u64 = lamports_precise
-            .checked_mul(&commission_percent)?
-            .checked_div(&bsp)?
-            .to_imprecise()?
-            .try_into()
-            .ok()?;
-
-        let treasury_split: u64 = lamports_precise
-            .checked_mul(&additional_split_percent)?
-            .checked_div(&bsp)?
-            .to_imprecise()?
-            .try_into()
-            .ok()?;
-
-        let owner_split = lamports
-            .checked_sub(commission)?
-            .checked_sub(treasury_split)?;
-
         Some(BuySplit {
-            commission,
-            owner_split,
-            treasury_split,
-            token_commission: 0,
+            lamports: plan.split(lamports)?,
+            token_commission: BUY_COMMISSION.of(token_amount)?,
         })
     }
 
@@ -173,9 +403,11 @@ impl ContractState {
         start_date: UnixTimestamp,
         end_date: UnixTimestamp,
     ) -> Result<(), ProgramError> {
-        if self.drop.is_some() {
-            // drop already in progress
-            return Error::DropInvalidDate.into();
+        if let Some(drop) = &self.drop {
+            // finished drops with unsold leftovers can be replaced
+            if now <= drop.end_date {
+                return Error::DropAlreadyExists.into();
+            }
         }
 
         if price == 0 {
@@ -198,45 +430,161 @@ impl ContractState {
         Ok(())
     }
 
-    pub fn clear_drop(&mut self) {
-        self.drop = None;
+    /// Round fee in effect. States that never set one use the legacy split of version 0:
+    /// treasury, admin fee and the state account itself for LP deposits
+    pub fn effective_round_fee(&self, state_addr: &Pubkey) -> RoundFeeConfig {
+        match &self.round_fee {
+            Some(round_fee) => round_fee.clone(),
+            None => RoundFeeConfig {
+                version: 0,
+                members: vec![
+                    Member {
+                        address: self.treasury_addr,
+                        share: LEGACY_ROUND_TREASURY_SHARE,
+                    },
+                    Member {
+                        address: self.admin,
+                        share: LEGACY_ROUND_ADMIN_SHARE,
+                    },
+                    Member {
+                        address: *state_addr,
+                        share: LEGACY_ROUND_LP_SHARE,
+                    },
+                ],
+            },
+        }
     }
-}
 
-pub fn try_migrate_state(
-    data: &mut [u8],
-    swap_state: Pubkey,
-    new_commission: Pubkey,
-    treasury: Pubkey,
-    now: UnixTimestamp,
-) -> Result<(), ProgramError> {
-    if data.is_empty() {
-        return Err(ProgramError::InvalidAccountData);
+    /// Replaces round fee with the next version, rounds created before keep their fanouts
+    pub fn set_round_fee(&mut self, members: Vec<Member>) -> Result<u32, ProgramError> {
+        RoundFeeConfig::validate(&members)?;
+
+        let version = self
+            .round_fee
+            .as_ref()
+            .map_or(0, |round_fee| round_fee.version)
+            .checked_add(1)
+            .ok_or(Error::Overflow)?;
+
+        self.round_fee = Some(RoundFeeConfig { version, members });
+
+        Ok(version)
     }
 
-    let (magic, _payload) = (&data[0], &data[1..]);
+    /// Checks round offer against `round_policy` and counts the round in.
+    /// Period starts over with the first round after it ended
+    pub fn record_round(
+        &mut self,
+        now: UnixTimestamp,
+        offer_amount: u64,
+        vault_amount: u64,
+    ) -> Result<(), ProgramError> {
+        let period_ended = |length: UnixTimestamp| match self.round_period_start.checked_add(length)
+        {
+            Some(end) => now >= end,
+            None => false,
+        };
+
+        if let Some(policy) = self.round_policy {
+            let max_offer = Bps::try_new(policy.max_offer_bps)
+                .and_then(|bps| bps.of(vault_amount))
+                .ok_or(Error::Overflow)?;
+
+            if offer_amount > max_offer {
+                msg!("round offer {} is above {}", offer_amount, max_offer);
+                return Error::RoundOfferTooLarge.into();
+            }
+
+            if let Some(last_round_at) = self.last_round_at {
+                let next_round_at = last_round_at
+                    .checked_add(policy.min_round_gap)
+                    .ok_or(Error::Overflow)?;
+
+                if now < next_round_at {
+                    msg!("next round can be created at {}", next_round_at);
+                    return Error::RoundTooSoon.into();
+                }
+            }
+
+            if !period_ended(policy.period_length)
+                && self.round_period_count >= policy.max_rounds_per_period
+            {
+                return Error::RoundLimitReached.into();
+            }
+        }
+
+        let new_period = match self.round_policy {
+            Some(policy) => period_ended(policy.period_length),
+            None => true,
+        };
 
-    match *magic {
-        ContractStateV4::MAGIC => {
-            msg!("already up to date on v4");
-            Ok(())
+        if new_period {
+            self.round_period_start = now;
+            self.round_period_count = 0;
         }
-        ContractStateV3::MAGIC => {
-            msg!("migrating from v3 to v4");
-            let v3 = ContractStateV3::deserialize_from(data)?;
-            let v4 = v3
-                .migrate(swap_state, new_commission, treasury, now)
-                .try_to_vec()?;
 
-            data[0] = ContractStateV4::MAGIC;
-            copy_slice(&mut data[1..], &v4);
+        self.round_period_count = self.round_period_count.saturating_add(1);
+        self.last_round_at = Some(now);
+
+        Ok(())
+    }
+
+    /// Owner vesting the owner can set on their own rounds, admin isn't limited.
+    /// Legacy vesting unless admin set a round policy
+    pub fn max_owner_vesting_bps(&self) -> u16 {
+        match self.round_policy {
+            Some(policy) => policy.max_owner_vesting_bps,
+            None => LEGACY_OWNER_VESTING.get(),
+        }
+    }
+
+    /// Registers a new round and returns its record, to be saved at `round_record_address`
+    pub fn register_round(
+        &mut self,
+        state_addr: &Pubkey,
+        round: &Pubkey,
+        now: UnixTimestamp,
+        owner_vesting_bps: u16,
+        release_duration: Option<UnixTimestamp>,
+    ) -> Result<RoundRecord, ProgramError> {
+        RoundRecord::validate_vesting(owner_vesting_bps, release_duration)?;
+
+        let number = self.rounds_created;
+
+        self.rounds_created = number.checked_add(1).ok_or(Error::Overflow)?;
+
+        Ok(RoundRecord {
+            state: *state_addr,
+            number,
+            round: *round,
+            created_at: now,
+            status: RoundRecordStatus::Open,
+            owner_vesting_bps,
+            release_duration,
+            released: 0,
+        })
+    }
+
+    pub fn clear_drop(&mut self) {
+        self.drop = None;
+    }
+
+    /// Subtracts sold amount from the ongoing drop, clearing it once sold out
+    pub fn record_drop_sale(&mut self, amount: u64) -> Result<(), ProgramError> {
+        let drop = self.drop.as_mut().ok_or(Error::NoDrop)?;
 
-            Ok(())
+        if amount > drop.amount {
+            return Error::DropAmountExceeded.into();
         }
-        _ => {
-            msg!("invalid state");
-            Err(ProgramError::InvalidAccountData)
+
+        drop.amount -= amount;
+        self.sold = self.sold.checked_add(amount).ok_or(Error::Overflow)?;
+
+        if drop.amount == 0 {
+            self.clear_drop();
         }
+
+        Ok(())
     }
 }
 
@@ -254,6 +602,10 @@ pub fn init_state(
         return Err(ProgramError::AccountAlreadyInitialized);
     }
 
+    if let Some(schedule) = &args.vesting {
+        schedule.validate()?;
+    }
+
     let state = ContractState {
         token,
         owner: args.owner,
@@ -269,6 +621,14 @@ pub fn init_state(
         treasury_addr: args.treasury,
         current_round: None,
         completed_rounds_count: 0,
+        vesting: args.vesting,
+        pending_admin: None,
+        round_fee: None,
+        round_policy: None,
+        last_round_at: None,
+        round_period_start: 0,
+        round_period_count: 0,
+        rounds_created: 0,
     };
 
     if ContractState::is_initialized(data) {
@@ -280,11 +640,6 @@ pub fn init_state(
     Ok(())
 }
 
-#[inline]
-fn copy_slice(dst: &mut [u8], src: &[u8]) {
-    sol_memcpy(dst, src, src.len())
-}
-
 // old
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 #[repr(C)]
@@ -308,15 +663,16 @@ pub struct ContractStateV3 {
 }
 
 impl Entity for ContractStateV3 {
-    const SIZE: usize = STATE_ACC_SIZE;
+    const SIZE: usize = STATE_ACC_SIZE_V4;
     const MAGIC: u8 = 0x44;
 }
 
+/// V3 states are never upgraded on load: addresses v3 didn't have are passed to `MigrateState`
 impl ContractStateV3 {
-    fn migrate(
+    pub fn upgrade_with(
         self,
         swap_state: Pubkey,
-        new_commission: Pubkey,
+        commission: Pubkey,
         treasury: Pubkey,
         now: UnixTimestamp,
     ) -> ContractStateV4 {
@@ -324,7 +680,7 @@ impl ContractStateV3 {
             token: self.token,
             owner: self.owner,
             admin: self.admin,
-            commission_addr: new_commission,
+            commission_addr: commission,
             sold: self.sold,
             vest: self.vest,
             drop: self.drop.map(|d: DropV1| DropV2 {
@@ -342,6 +698,8 @@ impl ContractStateV3 {
                 .unwrap_or(treasury),
             current_round: None,
             completed_rounds_count: 0,
+            vesting: None,
+            pending_admin: None,
         }
     }
 }
@@ -355,6 +713,90 @@ pub struct DropV1 {
     pub end_date: UnixTimestamp,
 }
 
+/// Registry entry of a round created with `CreateRound`,
+/// PDA of `ROUND_RECORD_SEED`, state and round number
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[repr(C)]
+pub struct RoundRecord {
+    pub state: Pubkey,
+    /// sequential per state, see `ContractState::rounds_created`
+    pub number: u64,
+    pub round: Pubkey,
+    pub created_at: UnixTimestamp,
+    pub status: RoundRecordStatus,
+    /// part of the round offer owner receives from the vault once round is accepted, in bps
+    pub owner_vesting_bps: u16,
+    /// owner part is released linearly over this many seconds after bidding ends,
+    /// all at once if not set
+    pub release_duration: Option<UnixTimestamp>,
+    /// owner part released so far
+    pub released: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum RoundRecordStatus {
+    /// round is in progress or owner part is not fully released yet
+    Open,
+    Claimed {
+        claimed_at: UnixTimestamp,
+    },
+}
+
+impl RoundRecord {
+    pub fn validate_vesting(
+        owner_vesting_bps: u16,
+        release_duration: Option<UnixTimestamp>,
+    ) -> Result<(), ProgramError> {
+        if owner_vesting_bps > Bps::DENOMINATOR || matches!(release_duration, Some(d) if d <= 0) {
+            return Error::InvalidOwnerVesting.into();
+        }
+
+        Ok(())
+    }
+
+    /// Releases owner part of `total_offer` vested by `now` and returns amount to transfer.
+    /// Amount is capped by `vault_amount`, the rest can be claimed once vault has it.
+    /// Round is claimed when everything is released
+    pub fn claim(
+        &mut self,
+        total_offer: u64,
+        release_start: UnixTimestamp,
+        now: UnixTimestamp,
+        vault_amount: u64,
+    ) -> Result<u64, ProgramError> {
+        if self.status != RoundRecordStatus::Open {
+            return Error::RoundAlreadyClaimed.into();
+        }
+
+        let total = Bps::try_new(self.owner_vesting_bps)
+            .and_then(|bps| bps.of(total_offer))
+            .ok_or(Error::Overflow)?;
+
+        let vested = match self.release_duration {
+            Some(duration) => {
+                let elapsed = now.saturating_sub(release_start).clamp(0, duration);
+                mul_div_floor(total, elapsed as u64, duration as u64).ok_or(Error::Overflow)?
+            }
+            None => total,
+        };
+
+        let amount = vested.saturating_sub(self.released).min(vault_amount);
+
+        self.released = self.released.checked_add(amount).ok_or(Error::Overflow)?;
+
+        if self.released >= total {
+            self.status = RoundRecordStatus::Claimed { claimed_at: now };
+        }
+
+        Ok(amount)
+    }
+}
+
+impl Entity for RoundRecord {
+    const SIZE: usize = 160;
+    const MAGIC: u8 = 0x50;
+}
+
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 #[repr(C)]
 pub struct PostInfo {
@@ -396,6 +838,9 @@ pub struct RepostRecord {
 }
 
 impl RepostRecord {
+    /// size of records created before `payer` was tracked
+    pub const LEGACY_SIZE: usize = 145;
+
     pub fn can_redeem(&self, now: UnixTimestamp) -> bool {
         let cooldown_elapsed = self
             .reposted_at
@@ -412,9 +857,49 @@ impl RepostRecord {
     }
 }
 
+/// Records created before `payer` was tracked take `LEGACY_SIZE` bytes.
+/// Some of them were later resized to `SIZE` with zeroed payer, rent of those goes back to the user
 impl Entity for RepostRecord {
     const SIZE: usize = 177;
     const MAGIC: u8 = 0x40;
+
+    fn deserialize_from(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEGACY_SIZE {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+
+        match data[0] {
+            0 => Err(ProgramError::UninitializedAccount),
+            magic if magic == Self::MAGIC => Self::decode(data),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut payload = &data[1..];
+
+        let state = Pubkey::deserialize(&mut payload)?;
+        let token = Pubkey::deserialize(&mut payload)?;
+        let user = Pubkey::deserialize(&mut payload)?;
+        let post_id = <[u8; 32]>::deserialize(&mut payload)?;
+        let reposted_at = UnixTimestamp::deserialize(&mut payload)?;
+        let receive_amount = u64::deserialize(&mut payload)?;
+
+        let payer = match Pubkey::deserialize(&mut payload) {
+            Ok(payer) if payer != Pubkey::default() => payer,
+            _ => user,
+        };
+
+        Ok(Self {
+            state,
+            token,
+            user,
+            post_id,
+            reposted_at,
+            receive_amount,
+            payer,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -422,10 +907,515 @@ mod tests {
     use super::*;
     use proptest::prelude::*;
 
+    fn test_state() -> ContractState {
+        ContractState {
+            token: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            admin: Pubkey::new_unique(),
+            commission_addr: Pubkey::new_unique(),
+            treasury_addr: Pubkey::new_unique(),
+            swap_state: Pubkey::new_unique(),
+            sold: 0,
+            vest: VestState {
+                deployed_at: 0,
+                vested_periods: 0,
+            },
+            drop: None,
+            current_round: None,
+            completed_rounds_count: 0,
+            vesting: None,
+            pending_admin: None,
+            round_fee: None,
+            round_policy: None,
+            last_round_at: None,
+            round_period_start: 0,
+            round_period_count: 0,
+            rounds_created: 0,
+        }
+    }
+
+    #[test]
+    fn test_drop_sale() {
+        let mut state = test_state();
+        state.create_drop(100, 1, 1000, 0, 10, 20).unwrap();
+
+        let state_addr = Pubkey::new_unique();
+        assert!(state.calculate_buy_split(&state_addr, 5, 10, 100).is_err());
+        assert!(state.calculate_buy_split(&state_addr, 15, 10, 99).is_err());
+
+        let split = state.calculate_buy_split(&state_addr, 15, 10, 100).unwrap();
+        assert_eq!(
+            split.lamports,
+            vec![
+                (state_addr, 100),
+                (state.treasury_addr, 800),
+                (state.owner, 100)
+            ]
+        );
+        assert_eq!(split.token_commission, 1);
+
+        state.record_drop_sale(400).unwrap();
+        assert_eq!(state.drop.as_ref().unwrap().amount, 600);
+        assert!(state.record_drop_sale(601).is_err());
+
+        state.record_drop_sale(600).unwrap();
+        assert!(state.drop.is_none());
+        assert_eq!(state.sold, 1000);
+        assert!(state.record_drop_sale(1).is_err());
+
+        // commissions of separate sales fit into the reserve of the whole drop
+        let commissions: u64 = [19, 19, 12]
+            .iter()
+            .map(|amount| BUY_COMMISSION.of(*amount).unwrap())
+            .sum();
+        assert!(50 + commissions <= drop_reserve(50).unwrap());
+        assert_eq!(drop_reserve(1000), Some(1100));
+        assert_eq!(drop_reserve(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_replace_drop() {
+        let mut state = test_state();
+        state.create_drop(100, 1, 1000, 0, 10, 20).unwrap();
+
+        assert!(state.create_drop(100, 2, 1000, 15, 20, 30).is_err());
+        state.create_drop(200, 2, 500, 21, 25, 30).unwrap();
+        assert_eq!(state.drop.as_ref().unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let mut state = test_state();
+        state.sold = 42;
+        state.vest.deployed_at = 7;
+        state.vest.vested_periods = 3;
+        let data = encode(&state);
+
+        let header = ContractStateHeader::from_bytes(&data).unwrap();
+        assert_eq!(header.token, state.token);
+        assert_eq!(header.swap_state, state.swap_state);
+        assert_eq!({ header.sold }, 42);
+        assert_eq!({ header.deployed_at }, 7);
+        assert_eq!(header.vested_periods, 3);
+    }
+
+    fn encode<T: Entity>(ent: &T) -> Vec<u8> {
+        let mut data = vec![0; T::SIZE];
+        ent.serialize_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_migrate_v3() {
+        let owner = Pubkey::new_unique();
+        let v3 = ContractStateV3 {
+            token: Pubkey::new_unique(),
+            owner,
+            admin: Pubkey::new_unique(),
+            commission_addr: Pubkey::new_unique(),
+            sold: 10,
+            vest: VestState {
+                deployed_at: 1,
+                vested_periods: 2,
+            },
+            drop: Some(DropV1 {
+                price: 100,
+                start_date: 5,
+                end_date: 6,
+            }),
+            additional_split: None,
+        };
+
+        // missing addresses are never left empty
+        assert_eq!(
+            ContractState::load(&encode(&v3)).unwrap_err(),
+            Error::StateMigrationRequired.into()
+        );
+
+        let (swap_state, commission, treasury) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let state = v3
+            .upgrade_with(swap_state, commission, treasury, 20)
+            .upgrade();
+        assert_eq!(state.owner, owner);
+        assert_eq!(state.swap_state, swap_state);
+        assert_eq!(state.commission_addr, commission);
+        assert_eq!(state.treasury_addr, treasury);
+        assert_eq!(state.sold, 10);
+        assert_eq!(state.vest.vested_periods, 2);
+        assert_eq!(state.drop.unwrap().created_at, 20);
+        assert!(state.vesting.is_none());
+    }
+
+    fn test_state_v4() -> ContractStateV4 {
+        let state = test_state();
+
+        ContractStateV4 {
+            token: state.token,
+            owner: state.owner,
+            admin: state.admin,
+            commission_addr: state.commission_addr,
+            treasury_addr: state.treasury_addr,
+            swap_state: state.swap_state,
+            sold: state.sold,
+            vest: state.vest,
+            drop: None,
+            current_round: None,
+            completed_rounds_count: 3,
+            vesting: None,
+            pending_admin: Some(Pubkey::new_unique()),
+        }
+    }
+
+    #[test]
+    fn test_repair_broken_state() {
+        let mut data = encode(&test_state_v4());
+
+        let (loaded, migrated) = ContractState::load(&data).unwrap();
+        assert!(migrated);
+        assert_eq!(loaded.completed_rounds_count, 3);
+
+        // garbage in current_round option
+        data[211] = 7;
+        data[300] = 1;
+
+        let (loaded, _) = ContractState::load(&data).unwrap();
+        assert!(loaded.current_round.is_none());
+        assert_eq!(loaded.completed_rounds_count, 0);
+        assert!(loaded.vesting.is_none());
+        assert!(loaded.pending_admin.is_none());
+    }
+
+    #[test]
+    fn test_migrate_v4() {
+        let v4 = test_state_v4();
+        let data = encode(&v4);
+        assert_eq!(data.len(), STATE_ACC_SIZE_V4);
+
+        let (state, migrated) = ContractState::load(&data).unwrap();
+        assert!(migrated);
+        assert_eq!(state.owner, v4.owner);
+        assert_eq!(state.pending_admin, v4.pending_admin);
+        assert!(state.round_fee.is_none());
+
+        // header reads the same before and after migration
+        let header = ContractStateHeader::from_bytes(&data).unwrap();
+        assert_eq!(header.owner, v4.owner);
+
+        let data = encode(&state);
+        let header = ContractStateHeader::from_bytes(&data).unwrap();
+        assert_eq!(header.owner, v4.owner);
+    }
+
+    #[test]
+    fn test_round_fee() {
+        let mut state = test_state();
+        let state_addr = Pubkey::new_unique();
+
+        let legacy = state.effective_round_fee(&state_addr);
+        assert_eq!(legacy.version, 0);
+        assert_eq!(legacy.members[0].address, state.treasury_addr);
+        assert_eq!(legacy.members[2].address, state_addr);
+        assert!(RoundFeeConfig::validate(&legacy.members).is_ok());
+
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let member = |address, share| Member { address, share };
+
+        assert!(state.set_round_fee(vec![]).is_err());
+        assert!(state.set_round_fee(vec![member(a, 9999)]).is_err());
+        assert!(state
+            .set_round_fee(vec![member(a, 10000), member(b, 0)])
+            .is_err());
+        assert!(state
+            .set_round_fee(vec![member(a, 5000), member(a, 5000)])
+            .is_err());
+        assert!(state.round_fee.is_none());
+
+        assert_eq!(
+            state
+                .set_round_fee(vec![member(a, 9000), member(b, 1000)])
+                .unwrap(),
+            1
+        );
+        assert_eq!(state.set_round_fee(vec![member(b, 10000)]).unwrap(), 2);
+
+        let round_fee = state.effective_round_fee(&state_addr);
+        assert_eq!(round_fee.version, 2);
+        assert_eq!(round_fee.members, vec![member(b, 10000)]);
+
+        // worst case without a round fee still fits into accounts of older versions
+        state.vesting = Some(VestingSchedule {
+            cliff: 0,
+            period_length: 1,
+            periods: MAX_VESTING_WEIGHTS as u8,
+            weights: Some(vec![0; MAX_VESTING_WEIGHTS]),
+        });
+        state.drop = Some(DropV2 {
+            id: 0,
+            price: 0,
+            amount: 0,
+            created_at: 0,
+            start_date: 0,
+            end_date: 0,
+        });
+        state.current_round = Some(Pubkey::new_unique());
+        state.pending_admin = Some(Pubkey::new_unique());
+        state.round_fee = None;
+        state.round_policy = Some(RoundPolicy {
+            max_offer_bps: 1,
+            min_round_gap: 1,
+            period_length: 1,
+            max_rounds_per_period: 1,
+            max_owner_vesting_bps: 1,
+        });
+        state.last_round_at = Some(1);
+        encode(&state);
+
+        // and into grown ones with it
+        state.round_fee = Some(RoundFeeConfig {
+            version: 3,
+            members: vec![member(a, 1000); MAX_ROUND_FEE_MEMBERS],
+        });
+        assert!(state.serialize_to(&mut [0; STATE_ACC_SIZE_V4]).is_err());
+        state.serialize_to(&mut [0; STATE_ACC_SIZE]).unwrap();
+    }
+
+    #[test]
+    fn test_round_policy() {
+        let mut state = test_state();
+
+        // not limited without policy
+        state.record_round(100, 1000, 1000).unwrap();
+        state.record_round(100, 1000, 1000).unwrap();
+        assert_eq!(state.max_owner_vesting_bps(), LEGACY_OWNER_VESTING.get());
+
+        let policy = RoundPolicy {
+            max_offer_bps: 1000,
+            min_round_gap: 10,
+            period_length: 100,
+            max_rounds_per_period: 2,
+            max_owner_vesting_bps: 2000,
+        };
+        assert!(policy.validate().is_ok());
+        assert!(RoundPolicy {
+            max_owner_vesting_bps: 10001,
+            ..policy
+        }
+        .validate()
+        .is_err());
+        assert!(RoundPolicy {
+            max_offer_bps: 10001,
+            ..policy
+        }
+        .validate()
+        .is_err());
+        assert!(RoundPolicy {
+            period_length: 0,
+            ..policy
+        }
+        .validate()
+        .is_err());
+
+        state.round_policy = Some(policy);
+
+        assert!(matches!(
+            state.record_round(110, 101, 1000),
+            Err(ProgramError::Custom(c)) if c == Error::RoundOfferTooLarge as u32
+        ));
+        assert!(matches!(
+            state.record_round(109, 100, 1000),
+            Err(ProgramError::Custom(c)) if c == Error::RoundTooSoon as u32
+        ));
+
+        // period started with the round at 100
+        state.record_round(110, 100, 1000).unwrap();
+        assert_eq!(state.round_period_count, 2);
+        assert!(matches!(
+            state.record_round(199, 100, 1000),
+            Err(ProgramError::Custom(c)) if c == Error::RoundLimitReached as u32
+        ));
+
+        state.record_round(200, 100, 1000).unwrap();
+        assert_eq!(state.round_period_start, 200);
+        assert_eq!(state.round_period_count, 1);
+        assert_eq!(state.last_round_at, Some(200));
+    }
+
+    #[test]
+    fn test_round_record() {
+        let mut state = test_state();
+        let state_addr = Pubkey::new_unique();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        assert!(state
+            .register_round(&state_addr, &a, 10, 10001, None)
+            .is_err());
+        assert!(state
+            .register_round(&state_addr, &a, 10, 1000, Some(0))
+            .is_err());
+
+        let mut first = state
+            .register_round(&state_addr, &a, 10, 1000, None)
+            .unwrap();
+        let second = state
+            .register_round(&state_addr, &b, 20, 1000, None)
+            .unwrap();
+        assert_eq!((first.number, second.number), (0, 1));
+        assert_eq!(state.rounds_created, 2);
+
+        // 10% of 12345 rounded down
+        assert_eq!(first.claim(12345, 0, 30, u64::MAX).unwrap(), 1234);
+        assert_eq!(first.status, RoundRecordStatus::Claimed { claimed_at: 30 });
+        assert!(matches!(
+            first.claim(12345, 0, 40, u64::MAX),
+            Err(ProgramError::Custom(c)) if c == Error::RoundAlreadyClaimed as u32
+        ));
+
+        let (loaded, _) = RoundRecord::load(&encode(&first)).unwrap();
+        assert_eq!(loaded.round, a);
+        assert_eq!(loaded.status, first.status);
+        assert_eq!(loaded.released, 1234);
+    }
+
+    #[test]
+    fn test_round_record_vault_cap() {
+        let mut state = test_state();
+        let mut record = state
+            .register_round(&Pubkey::new_unique(), &Pubkey::new_unique(), 0, 2500, None)
+            .unwrap();
+
+        // vault has less than owner part, the rest is claimed later
+        assert_eq!(record.claim(1001, 0, 10, 100).unwrap(), 100);
+        assert_eq!(record.status, RoundRecordStatus::Open);
+        assert_eq!(record.claim(1001, 0, 10, 0).unwrap(), 0);
+        assert_eq!(record.claim(1001, 0, 10, 1000).unwrap(), 150);
+        assert_eq!(record.released, 250);
+        assert_eq!(record.status, RoundRecordStatus::Claimed { claimed_at: 10 });
+    }
+
+    #[test]
+    fn test_round_record_linear_release() {
+        let mut state = test_state();
+        let mut record = state
+            .register_round(
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                0,
+                1000,
+                Some(3),
+            )
+            .unwrap();
+
+        // 1000 released over 3 seconds after bidding ends at 100
+        assert_eq!(record.claim(10000, 100, 50, u64::MAX).unwrap(), 0);
+        assert_eq!(record.claim(10000, 100, 101, u64::MAX).unwrap(), 333);
+        assert_eq!(record.claim(10000, 100, 102, 300).unwrap(), 300);
+        assert_eq!(record.claim(10000, 100, 102, u64::MAX).unwrap(), 33);
+        assert_eq!(record.status, RoundRecordStatus::Open);
+        assert_eq!(record.claim(10000, 100, 1000, u64::MAX).unwrap(), 334);
+        assert_eq!(record.released, 1000);
+        assert_eq!(
+            record.status,
+            RoundRecordStatus::Claimed { claimed_at: 1000 }
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_round_record_release(
+            total_offer in 0u64..u64::MAX,
+            bps in 0u16..=10000,
+            duration in prop::option::of(1i64..1000),
+            steps in prop::collection::vec((0i64..2000, 0u64..u64::MAX), 1..20),
+        ) {
+            let mut state = test_state();
+            let mut record = state
+                .register_round(&Pubkey::new_unique(), &Pubkey::new_unique(), 0, bps, duration)
+                .unwrap();
+            let total = Bps::new(bps).of(total_offer).unwrap();
+
+            let mut now = 0;
+            let mut paid = 0u64;
+            for (dt, vault_amount) in steps {
+                now += dt;
+                if let Ok(amount) = record.claim(total_offer, 0, now, vault_amount) {
+                    assert!(amount <= vault_amount);
+                    paid += amount;
+                }
+            }
+
+            assert!(paid <= total);
+            assert_eq!(paid, record.released);
+        }
+    }
+
+    fn old_repost_record(user: Pubkey, payer: Option<Pubkey>) -> Vec<u8> {
+        let mut data = vec![RepostRecord::MAGIC];
+        data.extend_from_slice(Pubkey::new_unique().as_ref()); // state
+        data.extend_from_slice(Pubkey::new_unique().as_ref()); // token
+        data.extend_from_slice(user.as_ref());
+        data.extend_from_slice(&[1; 32]); // post id
+        data.extend_from_slice(&5i64.to_le_bytes());
+        data.extend_from_slice(&100u64.to_le_bytes());
+        assert_eq!(data.len(), RepostRecord::LEGACY_SIZE);
+
+        if let Some(payer) = payer {
+            data.extend_from_slice(payer.as_ref());
+        }
+        data
+    }
+
+    #[test]
+    fn test_legacy_repost_record() {
+        let user = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+
+        // 145 bytes, same magic
+        let (record, migrated) = RepostRecord::load(&old_repost_record(user, None)).unwrap();
+        assert!(!migrated);
+        assert_eq!(record.user, user);
+        assert_eq!(record.payer, user);
+        assert_eq!(record.receive_amount, 100);
+        assert_eq!(record.reposted_at, 5);
+
+        // resized to 177 with zeroed payer
+        let data = old_repost_record(user, Some(Pubkey::default()));
+        let (record, _) = RepostRecord::load(&data).unwrap();
+        assert_eq!(record.payer, user);
+
+        // 177 bytes with payer
+        let data = old_repost_record(user, Some(payer));
+        let (record, _) = RepostRecord::load(&data).unwrap();
+        assert_eq!(record.payer, payer);
+
+        // new records
+        let data = encode(&record);
+        assert_eq!(data[0], 0x40);
+        let (record, migrated) = RepostRecord::load(&data).unwrap();
+        assert!(!migrated);
+        assert_eq!(record.payer, payer);
+
+        assert_eq!(
+            RepostRecord::load(&data[..100]).unwrap_err(),
+            ProgramError::AccountDataTooSmall
+        );
+    }
+
     proptest! {
         #[test]
         fn proptest_vesting(price in 1u64..100000000000, token_amount in 0u64..10000_0000) {
-            ContractState::calculate_split(price, token_amount).unwrap();
+            let (state, owner, treasury) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+            let plan = lamport_split_plan(&state, &owner, &treasury);
+            let split = ContractState::calculate_split(&plan, price, token_amount).unwrap();
+
+            assert_eq!(
+                split.lamports.iter().map(|(_, amount)| amount).sum::<u64>(),
+                price * token_amount
+            );
+            assert!(split.token_commission <= token_amount);
         }
     }
 }
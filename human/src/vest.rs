@@ -16,75 +16,120 @@ use solana_program::{
     sysvar::Sysvar,
 };
 use spl_associated_token_account::get_associated_token_address;
-use spl_math::precise_number::PreciseNumber;
 
 #[derive(Debug, BorshDeserialize, BorshSerialize)]
 pub struct VestState {
     /// created at
     pub deployed_at: UnixTimestamp,
-    /// periods already vested. Max periods is hardcoded
+    /// periods already vested, up to `VestingSchedule::periods`
     pub vested_periods: u8,
 }
 
+/// Vesting parameters of a token, chosen at `Init`
+#[derive(Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct VestingSchedule {
+    /// seconds after deployment before anything can be vested.
+    /// Periods elapsed during the cliff are released at once when it ends
+    pub cliff: UnixTimestamp,
+    /// length of a single period in seconds
+    pub period_length: UnixTimestamp,
+    /// total number of periods
+    pub periods: u8,
+    /// optional share of each period in bps, must sum up to 10000.
+    /// Equal parts are vested if omitted
+    pub weights: Option<Vec<u16>>,
+}
+
+impl VestingSchedule {
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.cliff < 0 || self.period_length <= 0 || self.periods == 0 {
+            return Error::InvalidVestingSchedule.into();
+        }
+
+        if let Some(weights) = &self.weights {
+            if weights.len() != self.periods as usize || weights.len() > MAX_VESTING_WEIGHTS {
+                return Error::InvalidVestingSchedule.into();
+            }
+
+            // zero weight periods would leave nothing to vest proportionally to
+            let total: u64 = weights.iter().map(|w| *w as u64).sum();
+            if total != 10_000 || weights.contains(&0) {
+                return Error::InvalidVestingSchedule.into();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// weight of periods in range, every period weights 1 without a weight table
+    fn weight_of(&self, from: u8, to: u8) -> u64 {
+        match &self.weights {
+            Some(weights) => weights[from as usize..to as usize]
+                .iter()
+                .map(|w| *w as u64)
+                .sum(),
+            None => to.saturating_sub(from) as u64,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum VestingResult {
     // User part in this period
     Vest(u64),
     /// Already vested in this period
     PeriodVested,
-    /// Vesting completed (vested_periods == VESTING_PARTS)
+    /// Vesting completed (vested_periods == schedule.periods)
     Completed,
 }
 
 impl VestState {
     fn next_period(
         &mut self,
+        schedule: &VestingSchedule,
         vault_supply: u64,
         now: UnixTimestamp,
     ) -> Result<VestingResult, ProgramError> {
-        if self.vested_periods == VESTING_TOTAL_PARTS {
+        if self.vested_periods >= schedule.periods {
             return Ok(VestingResult::Completed);
         }
 
         let elapsed = now.checked_sub(self.deployed_at).ok_or(Error::Overflow)?;
 
-        let elapsed = PreciseNumber::new(elapsed as u128).unwrap();
-        let part_len = PreciseNumber::new(VESTING_PART_LENGTH as u128).unwrap();
+        if elapsed < schedule.cliff {
+            return Ok(VestingResult::PeriodVested);
+        }
 
         let elapsed_parts = elapsed
-            .checked_div(&part_len)
-            .ok_or(Error::Overflow)?
-            .floor()
+            .checked_div(schedule.period_length)
             .ok_or(Error::Overflow)?;
+        let elapsed_parts = elapsed_parts.clamp(0, schedule.periods as i64) as u8;
 
-        let elapsed_parts = elapsed_parts.to_imprecise().ok_or(Error::Overflow)?;
-        let elapsed_parts = elapsed_parts.min(VESTING_TOTAL_PARTS as u128) as u8;
-
-        let missed_parts = elapsed_parts.saturating_sub(self.vested_periods);
-
-        if missed_parts == 0 {
+        if elapsed_parts <= self.vested_periods {
             return Ok(VestingResult::PeriodVested);
         }
 
-        let remaining_periods = VESTING_TOTAL_PARTS
-            .checked_sub(self.vested_periods)
-            .unwrap();
+        // vault holds whatever is left, so vest proportionally to the remaining weight.
+        // Last period always drains the vault
+        let missed = schedule.weight_of(self.vested_periods, elapsed_parts);
+        let remaining = schedule.weight_of(self.vested_periods, schedule.periods);
 
-        // equal parts
-        let part = vault_supply
-            .checked_div(remaining_periods as u64)
-            .ok_or(Error::Overflow)?;
+        if remaining == 0 {
+            // only zero weight periods left, nothing to vest anymore
+            self.vested_periods = schedule.periods;
+            return Ok(VestingResult::Completed);
+        }
 
-        let amount = part
-            .checked_mul(missed_parts as u64)
+        let amount = (vault_supply as u128)
+            .checked_mul(missed as u128)
+            .and_then(|v| v.checked_div(remaining as u128))
             .ok_or(Error::Overflow)?;
 
-        self.vested_periods = self
-            .vested_periods
-            .checked_add(missed_parts)
-            .ok_or(Error::Overflow)?;
+        self.vested_periods = elapsed_parts;
 
-        Ok(VestingResult::Vest(amount))
+        Ok(VestingResult::Vest(
+            u64::try_from(amount).map_err(|_| Error::Overflow)?,
+        ))
     }
 }
 
@@ -98,6 +143,14 @@ pub fn process_vest(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(),
 
     let (mut state, _) = next_entity::<_, ContractState>(account_info_iter, program_id)?;
 
+    let schedule = match &state.vesting {
+        Some(schedule) => schedule.clone(),
+        None => {
+            msg!("vesting is not configured for this token");
+            return Ok(());
+        }
+    };
+
     let (vault_addr, _) = contract_vault!(program_id, state.token);
     let (_, vault_acc) = next_expected_token_wallet(account_info_iter, &vault_addr)?;
 
@@ -108,11 +161,11 @@ pub fn process_vest(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(),
 
     let derived_owner_wallet = get_associated_token_address(&state.owner, &state.token);
     if *owner_token_wallet.key != derived_owner_wallet {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidOwnerWallet.into();
     }
 
     if !spl_token::check_id(token_program.key) {
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidTokenProgram.into();
     }
 
     let (transfer_authority, transfer_seed) = authority!(program_id);
@@ -123,15 +176,15 @@ pub fn process_vest(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(),
 
     let now = Clock::get()?.unix_timestamp;
 
-    let amount_to_transfer = match state.vest.next_period(vault_acc.amount, now)? {
+    let amount_to_transfer = match state.vest.next_period(&schedule, vault_acc.amount, now)? {
         VestingResult::Vest(amount) => amount,
         VestingResult::PeriodVested => {
             msg!("vesting already occured for the period");
-            return Ok(());
+            return state.commit();
         }
         VestingResult::Completed => {
             msg!("vesting completed");
-            return Ok(());
+            return state.commit();
         }
     };
 
@@ -146,7 +199,7 @@ pub fn process_vest(program_id: &Pubkey, accounts: &[AccountInfo]) -> Result<(),
 
     invoke_signed(&inst, accounts, &[transfer_seed])?;
 
-    Ok(())
+    state.commit()
 }
 
 pub fn next_expected_token_wallet<'a, 'b: 'a, I>(
@@ -160,7 +213,7 @@ where
 
     if wallet.key != wallet_addr {
         msg!("invalid expected wallet");
-        return Err(ProgramError::InvalidArgument);
+        return Error::InvalidVault.into();
     }
 
     let account = spl_token::state::Account::unpack(&wallet.data.borrow())?;
@@ -173,9 +226,20 @@ mod tests {
     use rand::Rng;
 
     use super::*;
-    use crate::consts::VESTING_PART_LENGTH;
     use proptest::prelude::*;
 
+    const PART_LENGTH: UnixTimestamp = 2629800; // seconds in a month
+    const TOTAL_PARTS: u8 = 12 * 3;
+
+    fn monthly() -> VestingSchedule {
+        VestingSchedule {
+            cliff: 0,
+            period_length: PART_LENGTH,
+            periods: TOTAL_PARTS,
+            weights: None,
+        }
+    }
+
     fn test_vesting_fuzz(supply: u64) {
         let mut vault_balance = supply;
         let mut now = 0;
@@ -187,14 +251,15 @@ mod tests {
             deployed_at: 0,
             vested_periods: 0,
         };
+        let schedule = monthly();
 
         loop {
             i += 1;
-            let min = VESTING_PART_LENGTH / 2;
-            let max = VESTING_PART_LENGTH * 2 + 1000;
+            let min = PART_LENGTH / 2;
+            let max = PART_LENGTH * 2 + 1000;
             now += rand::thread_rng().gen_range(min..max);
 
-            match vest.next_period(vault_balance, now).unwrap() {
+            match vest.next_period(&schedule, vault_balance, now).unwrap() {
                 VestingResult::Vest(amount) => {
                     println!("{i}: sent {amount}");
                     sent.push(amount);
@@ -211,21 +276,21 @@ mod tests {
         let seconds_in_year = 60 * 60 * 24 * 365;
         dbg!(
             now / seconds_in_year,
-            (VESTING_PART_LENGTH * VESTING_TOTAL_PARTS as i64) / seconds_in_year
+            (PART_LENGTH * TOTAL_PARTS as i64) / seconds_in_year
         );
-        dbg!(now, VESTING_PART_LENGTH * VESTING_TOTAL_PARTS as i64);
+        dbg!(now, PART_LENGTH * TOTAL_PARTS as i64);
 
         // Completion should have occured somewhere between sum of all periods to allow for some errors
-        assert!(now > VESTING_PART_LENGTH * VESTING_TOTAL_PARTS as i64);
+        assert!(now > PART_LENGTH * TOTAL_PARTS as i64);
 
-        let deadline = VESTING_PART_LENGTH * (VESTING_TOTAL_PARTS as i64 + 4);
+        let deadline = PART_LENGTH * (TOTAL_PARTS as i64 + 4);
         assert!(
             now < deadline,
             "difference of {} days",
             (deadline - now).abs() as f64 / 60.0 / 60.0 / 24.0
         );
 
-        assert!(sent.len() as u8 <= VESTING_TOTAL_PARTS);
+        assert!(sent.len() as u8 <= TOTAL_PARTS);
 
         const EPSILON: u64 = 1; // allow for one chatlan of accuracy
         assert!(supply - sent.iter().sum::<u64>() <= EPSILON, "total sent");
@@ -246,7 +311,7 @@ mod tests {
             vested_periods: 0,
         };
 
-        let result = vs.next_period(36000, VESTING_PART_LENGTH + 1).unwrap();
+        let result = vs.next_period(&monthly(), 36000, PART_LENGTH + 1).unwrap();
 
         assert_eq!(result, VestingResult::Vest(1000)); // 36000 / 36 (vesting parts)
         assert_eq!(vs.vested_periods, 1);
@@ -254,15 +319,15 @@ mod tests {
         // transition to complete
         let mut vs = VestState {
             deployed_at: 0,
-            vested_periods: VESTING_TOTAL_PARTS - 1,
+            vested_periods: TOTAL_PARTS - 1,
         };
 
         let result = vs
-            .next_period(1337, VESTING_PART_LENGTH * VESTING_TOTAL_PARTS as i64 + 1)
+            .next_period(&monthly(), 1337, PART_LENGTH * TOTAL_PARTS as i64 + 1)
             .unwrap();
 
         assert_eq!(result, VestingResult::Vest(1337));
-        assert_eq!(vs.vested_periods, VESTING_TOTAL_PARTS);
+        assert_eq!(vs.vested_periods, TOTAL_PARTS);
 
         // multiple periods elapsed
         assert_eq!(
@@ -270,7 +335,7 @@ mod tests {
                 deployed_at: 0,
                 vested_periods: 0,
             }
-            .next_period(36000, VESTING_PART_LENGTH * 2)
+            .next_period(&monthly(), 36000, PART_LENGTH * 2)
             .unwrap(),
             VestingResult::Vest(2000)
         );
@@ -283,7 +348,9 @@ mod tests {
             vested_periods: 1,
         };
 
-        let result = vs.next_period(9999, VESTING_PART_LENGTH + 1000).unwrap();
+        let result = vs
+            .next_period(&monthly(), 9999, PART_LENGTH + 1000)
+            .unwrap();
 
         assert_eq!(result, VestingResult::PeriodVested);
 
@@ -292,7 +359,7 @@ mod tests {
                 deployed_at: 0,
                 vested_periods: 1,
             }
-            .next_period(9999, 1)
+            .next_period(&monthly(), 9999, 1)
             .unwrap(),
             VestingResult::PeriodVested
         );
@@ -302,10 +369,10 @@ mod tests {
     fn test_vesting_completed() {
         let mut vs = VestState {
             deployed_at: 0,
-            vested_periods: VESTING_TOTAL_PARTS,
+            vested_periods: TOTAL_PARTS,
         };
 
-        let result = vs.next_period(9999, 9999).unwrap();
+        let result = vs.next_period(&monthly(), 9999, 9999).unwrap();
         assert_eq!(result, VestingResult::Completed);
     }
 
@@ -317,8 +384,122 @@ mod tests {
             vested_periods: 0,
         };
 
-        let result = vs.next_period(360000, i64::MAX).unwrap();
-        assert_eq!(vs.vested_periods, VESTING_TOTAL_PARTS);
+        let result = vs.next_period(&monthly(), 360000, i64::MAX).unwrap();
+        assert_eq!(vs.vested_periods, TOTAL_PARTS);
         assert_eq!(result, VestingResult::Vest(360000));
     }
+
+    #[test]
+    fn test_vesting_cliff() {
+        let schedule = VestingSchedule {
+            cliff: PART_LENGTH * 6,
+            ..monthly()
+        };
+
+        let mut vs = VestState {
+            deployed_at: 0,
+            vested_periods: 0,
+        };
+
+        let result = vs.next_period(&schedule, 36000, PART_LENGTH * 5).unwrap();
+        assert_eq!(result, VestingResult::PeriodVested);
+        assert_eq!(vs.vested_periods, 0);
+
+        // periods elapsed during the cliff are released at once
+        let result = vs.next_period(&schedule, 36000, PART_LENGTH * 6).unwrap();
+        assert_eq!(result, VestingResult::Vest(6000));
+        assert_eq!(vs.vested_periods, 6);
+    }
+
+    #[test]
+    fn test_vesting_weighted() {
+        let schedule = VestingSchedule {
+            cliff: 0,
+            period_length: PART_LENGTH,
+            periods: 3,
+            weights: Some(vec![5000, 3000, 2000]),
+        };
+        schedule.validate().unwrap();
+
+        let mut vs = VestState {
+            deployed_at: 0,
+            vested_periods: 0,
+        };
+
+        let result = vs.next_period(&schedule, 10000, PART_LENGTH).unwrap();
+        assert_eq!(result, VestingResult::Vest(5000));
+
+        let result = vs.next_period(&schedule, 5000, PART_LENGTH * 2).unwrap();
+        assert_eq!(result, VestingResult::Vest(3000));
+
+        let result = vs.next_period(&schedule, 2001, PART_LENGTH * 10).unwrap();
+        assert_eq!(result, VestingResult::Vest(2001));
+
+        let result = vs.next_period(&schedule, 0, PART_LENGTH * 11).unwrap();
+        assert_eq!(result, VestingResult::Completed);
+    }
+
+    #[test]
+    fn test_vesting_trailing_zero_weight() {
+        // rejected by validation, but must not get stuck either
+        let schedule = VestingSchedule {
+            cliff: 0,
+            period_length: PART_LENGTH,
+            periods: 2,
+            weights: Some(vec![10000, 0]),
+        };
+
+        let mut vs = VestState {
+            deployed_at: 0,
+            vested_periods: 0,
+        };
+
+        let result = vs.next_period(&schedule, 10000, PART_LENGTH).unwrap();
+        assert_eq!(result, VestingResult::Vest(10000));
+
+        let result = vs.next_period(&schedule, 0, PART_LENGTH * 2).unwrap();
+        assert_eq!(result, VestingResult::Completed);
+
+        let result = vs.next_period(&schedule, 0, PART_LENGTH * 3).unwrap();
+        assert_eq!(result, VestingResult::Completed);
+    }
+
+    #[test]
+    fn test_schedule_validation() {
+        monthly().validate().unwrap();
+
+        let invalid = [
+            VestingSchedule {
+                periods: 0,
+                ..monthly()
+            },
+            VestingSchedule {
+                period_length: 0,
+                ..monthly()
+            },
+            VestingSchedule {
+                cliff: -1,
+                ..monthly()
+            },
+            VestingSchedule {
+                periods: 2,
+                weights: Some(vec![5000, 4000]),
+                ..monthly()
+            },
+            VestingSchedule {
+                periods: 3,
+                weights: Some(vec![5000, 5000]),
+                ..monthly()
+            },
+            VestingSchedule {
+                periods: 2,
+                weights: Some(vec![10000, 0]),
+                ..monthly()
+            },
+        ];
+
+        for schedule in invalid {
+            assert!(schedule.validate().is_err(), "{schedule:?}");
+        }
+    }
 }
@@ -0,0 +1,204 @@
+// Compute unit benchmark for process_repost.
+// Needs compiled program: cargo test-bpf --features test-bpf -- --nocapture
+#![cfg(feature = "test-bpf")]
+
+use std::str::FromStr;
+
+use human_program::{
+    authority,
+    consts::*,
+    contract_vault, contract_wallet,
+    instruction::{self, SwapAccounts},
+    InitInstruction, RegisterPostInstruction,
+};
+use solana_program::{
+    clock::Clock, instruction::Instruction, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+    system_instruction, system_program,
+};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+use solana_program_test::*;
+
+#[path = "../../tests/common/mod.rs"]
+#[macro_use]
+mod common;
+
+use common::{mint, swap, token_account};
+
+const POST_ID: [u8; 32] = [7; 32];
+
+struct Bench {
+    program_id: Pubkey,
+    token: Pubkey,
+    owner: Pubkey,
+    admin: Keypair,
+    commission: Pubkey,
+    treasury: Pubkey,
+    swap: SwapAccounts,
+}
+
+impl Bench {
+    fn new() -> Self {
+        Self {
+            program_id: Pubkey::from_str("Human1nfyFpJsPU3BBKqWPwD9FeaZgdPYzDVrBj32Xj").unwrap(),
+            token: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            admin: Keypair::new(),
+            commission: Pubkey::new_unique(),
+            treasury: Pubkey::new_unique(),
+            swap: SwapAccounts {
+                state: Pubkey::new_unique(),
+                token_a: Pubkey::new_unique(),
+                token_b: Pubkey::new_unique(),
+                pool_mint: Pubkey::new_unique(),
+            },
+        }
+    }
+
+    /// program test with wallets and swap pool of the state token
+    fn program_test(&self) -> ProgramTest {
+        let mut pt = ProgramTest::default();
+        pt.prefer_bpf(true);
+        pt.add_program("human_program", self.program_id, None);
+        pt.add_builtin_program(
+            "mpl_token_metadata",
+            mpl_token_metadata::ID,
+            leaked_processor!(mpl_token_metadata::processor::process_instruction).unwrap(),
+        );
+
+        let (authority, _) = authority!(&self.program_id);
+        let (wallet, _) = contract_wallet!(&self.program_id, self.token);
+        let (vault, _) = contract_vault!(&self.program_id, self.token);
+
+        pt.add_account(self.token, mint(1_000_000));
+        pt.add_account(wallet, token_account(&self.token, &authority, 1000));
+        pt.add_account(vault, token_account(&self.token, &authority, 1000));
+        pt.add_account(
+            self.commission,
+            token_account(&spl_token::native_mint::ID, &Pubkey::new_unique(), 0),
+        );
+
+        // split recipients of the repost price
+        for recipient in [self.owner, self.treasury] {
+            pt.add_account(
+                recipient,
+                Account::new(LAMPORTS_PER_SOL, 0, &system_program::ID),
+            );
+        }
+
+        pt.add_account(
+            self.swap.state,
+            swap(
+                &human_program::swap_program::ID,
+                &self.token,
+                &self.swap.token_a,
+                &self.swap.token_b,
+                &self.swap.pool_mint,
+            ),
+        );
+        pt.add_account(
+            self.swap.token_a,
+            token_account(&self.token, &authority, 1000),
+        );
+        pt.add_account(
+            self.swap.token_b,
+            token_account(&spl_token::native_mint::ID, &authority, 1000),
+        );
+
+        pt
+    }
+
+    /// initialized state with a post open for reposts
+    async fn start(&self) -> ProgramTestContext {
+        let mut ctx = self.program_test().start_with_context().await;
+        let payer = ctx.payer.pubkey();
+        let id = &self.program_id;
+
+        let args = InitInstruction {
+            owner: self.owner,
+            admin: self.admin.pubkey(),
+            commission: self.commission,
+            treasury: self.treasury,
+            swap_state: self.swap.state,
+            vesting: None,
+        };
+        let init = instruction::init(id, &self.token, &payer, args);
+        measure(&mut ctx, init, &[]).await;
+
+        let clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+        let args = RegisterPostInstruction {
+            royalty_addr: Pubkey::new_unique(),
+            post_id: POST_ID,
+            created_at: clock.unix_timestamp,
+            post_name: "post".to_string(),
+            post_metadata_uri: "https://example.com".to_string(),
+            collection_name: "collection".to_string(),
+            collection_metadata_uri: "https://example.com".to_string(),
+            symbol: "HMN".to_string(),
+            repost_price: 100,
+        };
+        let ix = instruction::register_post(id, &self.token, &self.admin.pubkey(), &payer, args);
+        measure(&mut ctx, ix, &[&self.admin]).await;
+
+        ctx
+    }
+}
+
+/// runs instruction and returns consumed units
+async fn measure(
+    ctx: &mut ProgramTestContext,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> u64 {
+    let blockhash = ctx.banks_client.get_latest_blockhash().await.unwrap();
+
+    let mut all_signers = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&ctx.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+
+    let result = ctx
+        .banks_client
+        .process_transaction_with_metadata(tx)
+        .await
+        .unwrap();
+
+    result.result.unwrap();
+    result.metadata.unwrap().compute_units_consumed
+}
+
+#[tokio::test]
+async fn bench_repost() {
+    let bench = Bench::new();
+    let mut ctx = bench.start().await;
+    let payer = ctx.payer.pubkey();
+
+    let user = Keypair::new();
+    let transfer = system_instruction::transfer(&payer, &user.pubkey(), LAMPORTS_PER_SOL);
+    measure(&mut ctx, transfer, &[]).await;
+
+    let repost_mint = Keypair::new();
+    let ix = instruction::repost(
+        &bench.program_id,
+        &bench.token,
+        &POST_ID,
+        &bench.owner,
+        &bench.treasury,
+        &bench.swap,
+        &user.pubkey(),
+        &repost_mint.pubkey(),
+        1,
+    );
+    let units = measure(&mut ctx, ix, &[&user, &repost_mint]).await;
+
+    println!("repost: {units} CU");
+}
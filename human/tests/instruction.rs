@@ -0,0 +1,1157 @@
+use std::str::FromStr;
+
+use anchor_lang::{AccountDeserialize, AccountSerialize, InstructionData, ToAccountMetas};
+use borsh::BorshSerialize;
+use fanout::state::{Fanout, Member};
+use human_common::{entity::Entity, multisig::Multisig};
+use human_program::{
+    authority,
+    consts::*,
+    contract_state, contract_vault, contract_wallet,
+    error::Error,
+    instruction::{self, MigrateV3Accounts, SwapAccounts},
+    master_post_mint, post_info, process_instruction, repost_record, round_fanout_address,
+    round_record_address,
+    state::{
+        ContractState, ContractStateV3, ContractStateV4, PostInfo, RepostRecord, RoundPolicy,
+        RoundRecord, STATE_ACC_SIZE, STATE_ACC_SIZE_V4,
+    },
+    vest::VestState,
+    BuyFromDropInstruction, CreateDropInstruction, CreateRoundInstruction, InitInstruction,
+    InitMultisigInstruction, Instruction as HumanInstruction, RegisterPostInstruction,
+    SetAdminInstruction, UpdateConfigInstruction,
+};
+use solana_program::{
+    clock::Clock,
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::{Account as TokenAccount, Mint};
+
+use solana_program_test::*;
+
+#[path = "../../tests/common/mod.rs"]
+#[macro_use]
+mod common;
+
+use common::{account, mint, swap, token_account};
+
+const TOKEN_SUPPLY: u64 = 1_000_000;
+const VAULT_AMOUNT: u64 = 1000;
+
+struct Env {
+    program_id: Pubkey,
+    token: Pubkey,
+    owner: Keypair,
+    admin: Keypair,
+    commission_owner: Pubkey,
+    commission: Pubkey,
+    treasury: Pubkey,
+    swap: SwapAccounts,
+}
+
+impl Env {
+    fn new() -> Self {
+        Self {
+            program_id: Pubkey::from_str("Human1nfyFpJsPU3BBKqWPwD9FeaZgdPYzDVrBj32Xj").unwrap(),
+            token: Pubkey::new_unique(),
+            owner: Keypair::new(),
+            admin: Keypair::new(),
+            commission_owner: Pubkey::new_unique(),
+            commission: Pubkey::new_unique(),
+            treasury: Pubkey::new_unique(),
+            swap: SwapAccounts {
+                state: Pubkey::new_unique(),
+                token_a: Pubkey::new_unique(),
+                token_b: Pubkey::new_unique(),
+                pool_mint: Pubkey::new_unique(),
+            },
+        }
+    }
+
+    fn program_test(&self) -> ProgramTest {
+        let mut pt = ProgramTest::new(
+            "human_program",
+            self.program_id,
+            processor!(process_instruction),
+        );
+        add_programs(&mut pt);
+
+        let (authority, _) = authority!(&self.program_id);
+        let (wallet, _) = contract_wallet!(&self.program_id, self.token);
+        let (vault, _) = contract_vault!(&self.program_id, self.token);
+
+        pt.add_account(self.token, mint(TOKEN_SUPPLY));
+        pt.add_account(wallet, token_account(&self.token, &authority, 1000));
+        pt.add_account(vault, token_account(&self.token, &authority, VAULT_AMOUNT));
+        pt.add_account(
+            self.commission,
+            token_account(&spl_token::native_mint::ID, &self.commission_owner, 0),
+        );
+
+        for user in [&self.owner.pubkey(), &self.commission_owner] {
+            pt.add_account(
+                get_associated_token_address(user, &self.token),
+                token_account(&self.token, user, 0),
+            );
+        }
+
+        pt.add_account(
+            self.swap.state,
+            swap(
+                &human_program::swap_program::ID,
+                &self.token,
+                &self.swap.token_a,
+                &self.swap.token_b,
+                &self.swap.pool_mint,
+            ),
+        );
+
+        pt.add_account(self.swap.pool_mint, mint(1000));
+        pt.add_account(
+            self.swap.token_a,
+            token_account(&self.token, &authority, 1000),
+        );
+        pt.add_account(
+            self.swap.token_b,
+            token_account(&spl_token::native_mint::ID, &authority, 1000),
+        );
+        pt.add_account(
+            get_associated_token_address(&self.owner.pubkey(), &self.swap.pool_mint),
+            token_account(&self.swap.pool_mint, &self.owner.pubkey(), 0),
+        );
+
+        pt
+    }
+
+    fn init_args(&self, admin: Pubkey) -> InitInstruction {
+        InitInstruction {
+            owner: self.owner.pubkey(),
+            admin,
+            commission: self.commission,
+            treasury: self.treasury,
+            swap_state: self.swap.state,
+            vesting: None,
+        }
+    }
+
+    fn state_address(&self) -> Pubkey {
+        let (state, _) = contract_state!(&self.program_id, self.token);
+
+        state
+    }
+}
+
+/// Programs human CPIs into, run natively
+fn add_programs(pt: &mut ProgramTest) {
+    pt.add_program(
+        "spl_token",
+        spl_token::ID,
+        processor!(spl_token::processor::Processor::process),
+    );
+    pt.add_program(
+        "spl_associated_token_account",
+        spl_associated_token_account::ID,
+        processor!(spl_associated_token_account::processor::process_instruction),
+    );
+    pt.add_program(
+        "spl_token_swap",
+        human_program::swap_program::ID,
+        processor!(spl_token_swap::processor::Processor::process),
+    );
+    pt.add_program(
+        "mpl_token_metadata",
+        mpl_token_metadata::ID,
+        leaked_processor!(mpl_token_metadata::processor::process_instruction),
+    );
+    pt.add_program("human_round", round::ID, leaked_processor!(round::entry));
+    pt.add_program("human_fanout", fanout::ID, leaked_processor!(fanout::entry));
+    pt.add_program(
+        "mpl_bubblegum",
+        mpl_bubblegum::ID,
+        leaked_processor!(mpl_bubblegum::entry),
+    );
+    pt.add_program(
+        "spl_account_compression",
+        spl_account_compression::ID,
+        leaked_processor!(spl_account_compression::entry),
+    );
+    pt.add_program("spl_noop", spl_noop::ID, processor!(spl_noop::noop));
+}
+
+fn entity<E: Entity>(program_id: Pubkey, entity: E) -> Account {
+    let mut data = vec![0; E::SIZE];
+    entity.serialize_to(&mut data).unwrap();
+
+    account(program_id, data)
+}
+
+async fn process(
+    ctx: &mut ProgramTestContext,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let blockhash = ctx.banks_client.get_latest_blockhash().await.unwrap();
+
+    let mut all_signers = vec![&ctx.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&ctx.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+
+    ctx.banks_client
+        .process_transaction(tx)
+        .await
+        .map_err(|e| e.unwrap())
+}
+
+fn custom(e: Error) -> Result<(), TransactionError> {
+    Err(TransactionError::InstructionError(
+        0,
+        InstructionError::Custom(e as u32),
+    ))
+}
+
+async fn get_state(ctx: &mut ProgramTestContext, env: &Env) -> ContractState {
+    let account = ctx
+        .banks_client
+        .get_account(env.state_address())
+        .await
+        .unwrap()
+        .expect("state account");
+
+    ContractState::deserialize_from(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn test_config_instructions() {
+    let env = Env::new();
+    let mut ctx = env.program_test().start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let id = &env.program_id;
+    let token = &env.token;
+    let admin = env.admin.pubkey();
+
+    let init = instruction::init(id, token, &payer, env.init_args(admin));
+    process(&mut ctx, init, &[]).await.unwrap();
+
+    let state = get_state(&mut ctx, &env).await;
+    assert_eq!(state.token, *token);
+    assert_eq!(state.admin, admin);
+
+    let members = vec![
+        Member {
+            address: Pubkey::new_unique(),
+            share: 4000,
+        },
+        Member {
+            address: Pubkey::new_unique(),
+            share: 6000,
+        },
+    ];
+    let ix = instruction::set_round_fee(id, token, &admin, &[], members.clone());
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let policy = RoundPolicy {
+        max_offer_bps: 1000,
+        min_round_gap: 60,
+        period_length: 3600,
+        max_rounds_per_period: 2,
+        max_owner_vesting_bps: 1000,
+    };
+    let ix = instruction::set_round_policy(id, token, &admin, &[], Some(policy));
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let new_owner = Pubkey::new_unique();
+    let new_treasury = Pubkey::new_unique();
+    let args = UpdateConfigInstruction {
+        commission: Some(env.commission),
+        treasury: Some(new_treasury),
+        owner: Some(new_owner),
+        ..UpdateConfigInstruction::default()
+    };
+    let ix = instruction::update_config(id, token, &admin, &[], args);
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let new_admin = Keypair::new();
+    // one step handover is gone
+    let mut ix = instruction::propose_admin(id, token, &admin, &[], &new_admin.pubkey());
+    ix.data = HumanInstruction::SetAdmin(SetAdminInstruction {
+        admin: new_admin.pubkey(),
+    })
+    .try_to_vec()
+    .unwrap();
+    assert_eq!(
+        process(&mut ctx, ix, &[&env.admin]).await,
+        custom(Error::SetAdminDeprecated)
+    );
+
+    let ix = instruction::propose_admin(id, token, &admin, &[], &new_admin.pubkey());
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let ix = instruction::accept_admin(id, token, &new_admin.pubkey(), &[]);
+    process(&mut ctx, ix, &[&new_admin]).await.unwrap();
+
+    let args = CreateDropInstruction {
+        id: 1,
+        amount: 100,
+        price: 10,
+        start_date: 0,
+        end_date: i64::MAX,
+    };
+    let ix = instruction::create_drop(id, token, &new_admin.pubkey(), &[], args);
+    process(&mut ctx, ix, &[&new_admin]).await.unwrap();
+
+    let ix = instruction::migrate_state(id, token, &payer, None);
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    // vesting is not configured
+    let ix = instruction::vest(id, token, &new_owner);
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let state = get_state(&mut ctx, &env).await;
+    assert_eq!(state.round_fee.map(|fee| fee.members), Some(members));
+    assert_eq!(state.round_policy, Some(policy));
+    assert_eq!(state.owner, new_owner);
+    assert_eq!(state.treasury_addr, new_treasury);
+    assert_eq!(state.admin, new_admin.pubkey());
+    assert_eq!(state.pending_admin, None);
+    assert_eq!(state.drop.map(|drop| drop.amount), Some(100));
+}
+
+#[tokio::test]
+async fn test_multisig_admin() {
+    let env = Env::new();
+    let multisig_key = Keypair::new();
+    let multisig = multisig_key.pubkey();
+    let signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+
+    let mut pt = env.program_test();
+    pt.add_account(multisig, account(env.program_id, vec![0; Multisig::SIZE]));
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let id = &env.program_id;
+    let token = &env.token;
+
+    let args = InitMultisigInstruction {
+        threshold: 2,
+        signers: signers.iter().map(Signer::pubkey).collect(),
+    };
+
+    // multisig account has to sign its initialization
+    let mut ix = instruction::init_multisig(id, &multisig, args);
+    ix.accounts[0].is_signer = false;
+    assert_eq!(
+        process(&mut ctx, ix.clone(), &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::MissingRequiredSignature
+        ))
+    );
+
+    ix.accounts[0].is_signer = true;
+    process(&mut ctx, ix, &[&multisig_key]).await.unwrap();
+
+    let init = instruction::init(id, token, &payer, env.init_args(multisig));
+    process(&mut ctx, init, &[]).await.unwrap();
+
+    let policy = RoundPolicy {
+        max_offer_bps: 500,
+        min_round_gap: 0,
+        period_length: 60,
+        max_rounds_per_period: 1,
+        max_owner_vesting_bps: 1000,
+    };
+    let multisig_signers = [signers[0].pubkey(), signers[2].pubkey()];
+    let ix = instruction::set_round_policy(id, token, &multisig, &multisig_signers, Some(policy));
+    process(&mut ctx, ix, &[&signers[0], &signers[2]])
+        .await
+        .unwrap();
+
+    let state = get_state(&mut ctx, &env).await;
+    assert_eq!(state.round_policy, Some(policy));
+}
+
+#[tokio::test]
+async fn test_migrate_state_v3() {
+    let env = Env::new();
+    let v3 = ContractStateV3 {
+        token: env.token,
+        owner: env.owner.pubkey(),
+        admin: env.admin.pubkey(),
+        commission_addr: Pubkey::new_unique(),
+        sold: 10,
+        vest: VestState {
+            deployed_at: 0,
+            vested_periods: 0,
+        },
+        drop: None,
+        additional_split: None,
+    };
+
+    let mut pt = env.program_test();
+    pt.add_account(env.state_address(), entity(env.program_id, v3));
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let id = &env.program_id;
+    let token = &env.token;
+
+    // addresses v3 didn't have are never left empty
+    let ix = instruction::vest(id, token, &env.owner.pubkey());
+    assert_eq!(
+        process(&mut ctx, ix, &[]).await,
+        custom(Error::StateMigrationRequired)
+    );
+
+    let v3_accounts = MigrateV3Accounts {
+        admin: env.admin.pubkey(),
+        multisig_signers: Vec::new(),
+        swap_state: env.swap.state,
+        commission: env.commission,
+        treasury: env.treasury,
+    };
+    let mut ix = instruction::migrate_state(id, token, &payer, Some(&v3_accounts));
+
+    // addresses are trusted as is, so only admin can migrate
+    ix.accounts[3].is_signer = false;
+    assert_eq!(
+        process(&mut ctx, ix.clone(), &[]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::MissingRequiredSignature
+        ))
+    );
+
+    ix.accounts[3].is_signer = true;
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let state = get_state(&mut ctx, &env).await;
+    assert_eq!(state.sold, 10);
+    assert_eq!(state.swap_state, env.swap.state);
+    assert_eq!(state.commission_addr, env.commission);
+    assert_eq!(state.treasury_addr, env.treasury);
+
+    let account = ctx
+        .banks_client
+        .get_account(env.state_address())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), STATE_ACC_SIZE);
+    assert!(Rent::default().is_exempt(account.lamports, STATE_ACC_SIZE));
+}
+
+#[tokio::test]
+async fn test_migrate_state_v4() {
+    let env = Env::new();
+    let v4 = ContractStateV4 {
+        token: env.token,
+        owner: env.owner.pubkey(),
+        admin: env.admin.pubkey(),
+        commission_addr: env.commission,
+        treasury_addr: env.treasury,
+        swap_state: env.swap.state,
+        sold: 10,
+        vest: VestState {
+            deployed_at: 0,
+            vested_periods: 0,
+        },
+        drop: None,
+        current_round: None,
+        completed_rounds_count: 0,
+        vesting: None,
+        pending_admin: None,
+    };
+
+    let mut pt = env.program_test();
+    pt.add_account(env.state_address(), entity(env.program_id, v4));
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let id = &env.program_id;
+    let token = &env.token;
+
+    // upgraded in place by any write
+    let new_admin = Pubkey::new_unique();
+    let ix = instruction::propose_admin(id, token, &env.admin.pubkey(), &[], &new_admin);
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let account = ctx
+        .banks_client
+        .get_account(env.state_address())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), STATE_ACC_SIZE_V4);
+    assert_eq!(account.data[0], ContractState::MAGIC);
+
+    let state = get_state(&mut ctx, &env).await;
+    assert_eq!(state.sold, 10);
+    assert_eq!(state.pending_admin, Some(new_admin));
+
+    let round_fee = || {
+        (0..MAX_ROUND_FEE_MEMBERS)
+            .map(|_| Member {
+                address: Pubkey::new_unique(),
+                share: 1000,
+            })
+            .collect()
+    };
+
+    // largest round fee doesn't fit in the old account
+    let ix = instruction::set_round_fee(id, token, &env.admin.pubkey(), &[], round_fee());
+    assert_eq!(
+        process(&mut ctx, ix, &[&env.admin]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::AccountDataTooSmall
+        ))
+    );
+
+    // migration grows it
+    let ix = instruction::migrate_state(id, token, &payer, None);
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let account = ctx
+        .banks_client
+        .get_account(env.state_address())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), STATE_ACC_SIZE);
+    assert!(Rent::default().is_exempt(account.lamports, STATE_ACC_SIZE));
+
+    let ix = instruction::set_round_fee(id, token, &env.admin.pubkey(), &[], round_fee());
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+    assert_eq!(get_state(&mut ctx, &env).await.sold, 10);
+}
+
+/// Instructions ending with CPIs into token, metadata and round programs are run up to
+/// the first check after all accounts were accepted
+#[tokio::test]
+async fn test_accounts_accepted() {
+    let env = Env::new();
+    let id = &env.program_id;
+    let token = &env.token;
+    let state = env.state_address();
+    let user = Keypair::new();
+    let record_payer = Pubkey::new_unique();
+
+    let post_id = [7; 32];
+    let new_post_id = [8; 32];
+    let round = Pubkey::new_unique();
+    let repost_mint = Pubkey::new_unique();
+
+    let mut pt = env.program_test();
+    pt.add_account(
+        user.pubkey(),
+        account(solana_program::system_program::ID, vec![]),
+    );
+    pt.add_account(
+        get_associated_token_address(&user.pubkey(), token),
+        token_account(token, &user.pubkey(), 0),
+    );
+
+    let mut round_data = vec![];
+    round::state::Round::default()
+        .try_serialize(&mut round_data)
+        .unwrap();
+    pt.add_account(round, account(round::ID, round_data));
+
+    // registered somewhere else
+    let other_state = Pubkey::new_unique();
+    let (post_info, _) = post_info!(id, &post_id);
+    pt.add_account(
+        post_info,
+        entity(
+            *id,
+            PostInfo {
+                state: other_state,
+                post_id,
+                created_at: 0,
+                repost_price: None,
+                royalty_address: Pubkey::new_unique(),
+                payer: Pubkey::new_unique(),
+                name: "post".to_string(),
+                symbol: "HMN".to_string(),
+                uri: "https://example.com".to_string(),
+            },
+        ),
+    );
+
+    let (repost_record, _) = repost_record!(id, repost_mint);
+    pt.add_account(
+        repost_record,
+        entity(
+            *id,
+            RepostRecord {
+                state: other_state,
+                token: *token,
+                user: user.pubkey(),
+                post_id,
+                reposted_at: 0,
+                receive_amount: 10,
+                payer: record_payer,
+            },
+        ),
+    );
+
+    let (new_master_mint, _) = master_post_mint!(id, &new_post_id);
+    pt.add_account(new_master_mint, mint(0));
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let owner = env.owner.pubkey();
+    let admin = env.admin.pubkey();
+
+    let init = instruction::init(id, token, &payer, env.init_args(admin));
+    process(&mut ctx, init, &[]).await.unwrap();
+
+    let args = CreateDropInstruction {
+        id: 1,
+        amount: 100,
+        price: 10,
+        start_date: 0,
+        end_date: i64::MAX,
+    };
+    let ix = instruction::create_drop(id, token, &admin, &[], args);
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let args = BuyFromDropInstruction {
+        amount: 0,
+        expected_price: 10,
+    };
+    let ix = instruction::buy_from_drop(
+        id,
+        token,
+        &user.pubkey(),
+        &owner,
+        &env.treasury,
+        &env.commission,
+        &env.commission_owner,
+        args,
+    );
+    assert_eq!(
+        process(&mut ctx, ix, &[&user]).await,
+        custom(Error::ZeroAmount)
+    );
+
+    let policy = RoundPolicy {
+        max_offer_bps: 1000,
+        min_round_gap: 0,
+        period_length: 60,
+        max_rounds_per_period: 1,
+        max_owner_vesting_bps: 1000,
+    };
+    let ix = instruction::set_round_policy(id, token, &admin, &[], Some(policy));
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let new_round = Keypair::new();
+    let args = CreateRoundInstruction {
+        bidding_start: 0,
+        bidding_end: i64::MAX,
+        offer_amount: VAULT_AMOUNT / 10 + 1,
+        target_bid: 1,
+        owner_vesting_bps: 0,
+        owner_release_duration: None,
+    };
+    let ix = instruction::create_round(
+        id,
+        token,
+        0,
+        0,
+        &new_round.pubkey(),
+        &spl_token::native_mint::ID,
+        &owner,
+        &[],
+        &payer,
+        args,
+    );
+    assert_eq!(
+        process(&mut ctx, ix, &[&new_round, &env.owner]).await,
+        custom(Error::RoundOfferTooLarge)
+    );
+
+    // owner can't go above the vesting cap set by admin
+    let args = CreateRoundInstruction {
+        bidding_start: 0,
+        bidding_end: i64::MAX,
+        offer_amount: VAULT_AMOUNT / 10,
+        target_bid: 1,
+        owner_vesting_bps: 1001,
+        owner_release_duration: None,
+    };
+    let ix = instruction::create_round(
+        id,
+        token,
+        0,
+        0,
+        &new_round.pubkey(),
+        &spl_token::native_mint::ID,
+        &owner,
+        &[],
+        &payer,
+        args,
+    );
+    assert_eq!(
+        process(&mut ctx, ix, &[&new_round, &env.owner]).await,
+        custom(Error::OwnerVestingAboveCap)
+    );
+
+    let ix = instruction::claim_round_vesting(id, token, &owner, &round, Some(0));
+    assert_eq!(
+        process(&mut ctx, ix, &[]).await,
+        custom(Error::RoundNotAccepted)
+    );
+
+    let ix = instruction::redeem_repost(id, token, &repost_mint, &user.pubkey(), &record_payer);
+    assert_eq!(
+        process(&mut ctx, ix, &[]).await,
+        custom(Error::RepostStateMismatch)
+    );
+
+    let new_repost_mint = Keypair::new();
+    let ix = instruction::repost(
+        id,
+        token,
+        &post_id,
+        &owner,
+        &env.treasury,
+        &env.swap,
+        &user.pubkey(),
+        &new_repost_mint.pubkey(),
+        1,
+    );
+    assert_eq!(
+        process(&mut ctx, ix, &[&new_repost_mint, &user]).await,
+        custom(Error::PostStateMismatch)
+    );
+
+    let ix = instruction::repost_compressed(
+        id,
+        token,
+        &post_id,
+        &user.pubkey(),
+        &payer,
+        &Pubkey::new_unique(),
+    );
+    assert_eq!(
+        process(&mut ctx, ix, &[]).await,
+        custom(Error::PostStateMismatch)
+    );
+
+    // vault can't match the deposit, nothing is moved
+    let transfer = system_instruction::transfer(&payer, &state, 1_000_000);
+    process(&mut ctx, transfer, &[]).await.unwrap();
+    let lamports = ctx.banks_client.get_balance(state).await.unwrap();
+
+    let stash = Keypair::new();
+    let ix = instruction::deposit_commission(id, token, &owner, &env.swap, &stash.pubkey());
+    process(&mut ctx, ix, &[&stash]).await.unwrap();
+    assert_eq!(ctx.banks_client.get_balance(state).await.unwrap(), lamports);
+
+    // master mint of the post already exists
+    let (new_post_info, _) = post_info!(id, &new_post_id);
+    let args = RegisterPostInstruction {
+        royalty_addr: Pubkey::new_unique(),
+        post_id: new_post_id,
+        created_at: 0,
+        post_name: "post".to_string(),
+        post_metadata_uri: "https://example.com".to_string(),
+        collection_name: "collection".to_string(),
+        collection_metadata_uri: "https://example.com".to_string(),
+        symbol: "HMN".to_string(),
+        repost_price: 0,
+    };
+    let ix = instruction::register_post(id, token, &admin, &payer, args);
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+    assert!(ctx
+        .banks_client
+        .get_account(new_post_info)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_create_wallets() {
+    let env = Env::new();
+    let token = Pubkey::new_unique();
+
+    let mut pt = env.program_test();
+    pt.add_account(token, mint(TOKEN_SUPPLY));
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let id = &env.program_id;
+
+    let ix = instruction::create_wallets(id, &token, &payer);
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let (authority, _) = authority!(id);
+    let (wallet, _) = contract_wallet!(id, token);
+    let (vault, _) = contract_vault!(id, token);
+
+    for address in [wallet, vault] {
+        let account = ctx
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        let wallet = TokenAccount::unpack(&account.data).unwrap();
+        assert_eq!(wallet.mint, token);
+        assert_eq!(wallet.owner, authority);
+    }
+}
+
+#[tokio::test]
+async fn test_round_lifecycle() {
+    let env = Env::new();
+    let id = &env.program_id;
+    let token = &env.token;
+    let state = env.state_address();
+
+    let mut pt = env.program_test();
+    let mut native_mint = mint(0);
+    Mint::pack(
+        Mint {
+            decimals: 9,
+            is_initialized: true,
+            ..Mint::default()
+        },
+        &mut native_mint.data,
+    )
+    .unwrap();
+    pt.add_account(spl_token::native_mint::ID, native_mint);
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let owner = env.owner.pubkey();
+
+    let init = instruction::init(id, token, &payer, env.init_args(env.admin.pubkey()));
+    process(&mut ctx, init, &[]).await.unwrap();
+
+    // anyone can send lamports to the PDAs before the round is created
+    let (fanout, _) = round_fanout_address(id, &state, 0);
+    let (round_record, _) = round_record_address(id, &state, 0);
+    for address in [fanout, round_record] {
+        let lamports = Rent::default().minimum_balance(0);
+        let transfer = system_instruction::transfer(&payer, &address, lamports);
+        process(&mut ctx, transfer, &[]).await.unwrap();
+    }
+
+    let clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+    let bidding_end = clock.unix_timestamp + 60;
+
+    let round = Keypair::new();
+    let args = CreateRoundInstruction {
+        bidding_start: clock.unix_timestamp,
+        bidding_end,
+        offer_amount: VAULT_AMOUNT / 10,
+        target_bid: 1,
+        owner_vesting_bps: 1000,
+        owner_release_duration: None,
+    };
+    let ix = instruction::create_round(
+        id,
+        token,
+        0,
+        0,
+        &round.pubkey(),
+        &spl_token::native_mint::ID,
+        &owner,
+        &[],
+        &payer,
+        args,
+    );
+    process(&mut ctx, ix, &[&round, &env.owner]).await.unwrap();
+
+    let record = ctx
+        .banks_client
+        .get_account(round_record)
+        .await
+        .unwrap()
+        .unwrap();
+    let record = RoundRecord::deserialize_from(&record.data).unwrap();
+    assert_eq!(record.round, round.pubkey());
+    assert_eq!(record.owner_vesting_bps, 1000);
+    assert_eq!(get_state(&mut ctx, &env).await.rounds_created, 1);
+
+    let fanout_account = ctx.banks_client.get_account(fanout).await.unwrap().unwrap();
+    assert_eq!(fanout_account.owner, fanout::ID);
+
+    // fee split of a round can't be changed, and only the program can close its fanout
+    let (fanout_state, _) = Fanout::read_versioned(&fanout_account.data[8..]).unwrap();
+    assert_eq!(fanout_state.update_authority, None);
+    assert_eq!(fanout_state.close_authority, Some(authority!(id).0));
+
+    // heir accepts once bidding has ended
+    ctx.set_sysvar(&Clock {
+        unix_timestamp: bidding_end,
+        ..clock
+    });
+
+    let round_key = round.pubkey();
+    let pda = |seed: &[u8]| Pubkey::find_program_address(&[seed, round_key.as_ref()], &round::ID).0;
+    let accept = Instruction {
+        program_id: round::ID,
+        accounts: round::accounts::Accept {
+            round: round_key,
+            heir: owner,
+            offer_wallet: pda(round::OFFER_SEED),
+            bid_wallet: pda(round::BID_SEED),
+            recipient: fanout,
+            authority: pda(round::AUTHORITY_SEED),
+            payer,
+            token_program: spl_token::ID,
+            system_program: solana_program::system_program::ID,
+        }
+        .to_account_metas(None),
+        data: round::instruction::Accept {
+            reconciliation_authority: None,
+        }
+        .data(),
+    };
+    process(&mut ctx, accept, &[&env.owner]).await.unwrap();
+
+    let ix = instruction::claim_round_vesting(id, token, &owner, &round_key, Some(0));
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let owner_wallet = ctx
+        .banks_client
+        .get_account(get_associated_token_address(&owner, token))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        TokenAccount::unpack(&owner_wallet.data).unwrap().amount,
+        VAULT_AMOUNT / 100
+    );
+    assert_eq!(get_state(&mut ctx, &env).await.completed_rounds_count, 1);
+}
+
+#[tokio::test]
+async fn test_repost_lifecycle() {
+    let env = Env::new();
+    let id = &env.program_id;
+    let token = &env.token;
+    let post_id = [7; 32];
+
+    // split recipients already exist, so a small price does not leave them below rent
+    let mut pt = env.program_test();
+    for recipient in [env.owner.pubkey(), env.treasury] {
+        pt.add_account(
+            recipient,
+            account(solana_program::system_program::ID, vec![]),
+        );
+    }
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let admin = env.admin.pubkey();
+
+    let init = instruction::init(id, token, &payer, env.init_args(admin));
+    process(&mut ctx, init, &[]).await.unwrap();
+
+    let clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+    let args = RegisterPostInstruction {
+        royalty_addr: Pubkey::new_unique(),
+        post_id,
+        created_at: clock.unix_timestamp,
+        post_name: "post".to_string(),
+        post_metadata_uri: "https://example.com".to_string(),
+        collection_name: "collection".to_string(),
+        collection_metadata_uri: "https://example.com".to_string(),
+        symbol: "HMN".to_string(),
+        repost_price: 100,
+    };
+    let ix = instruction::register_post(id, token, &admin, &payer, args);
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let (post_info, _) = post_info!(id, &post_id);
+    let post_info = ctx
+        .banks_client
+        .get_account(post_info)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        PostInfo::deserialize_from(&post_info.data)
+            .unwrap()
+            .repost_price,
+        Some(100)
+    );
+
+    let user = Keypair::new();
+    let transfer = system_instruction::transfer(&payer, &user.pubkey(), 1_000_000_000);
+    process(&mut ctx, transfer, &[]).await.unwrap();
+
+    let repost_mint = Keypair::new();
+    let repost_mint_key = repost_mint.pubkey();
+    let ix = instruction::repost(
+        id,
+        token,
+        &post_id,
+        &env.owner.pubkey(),
+        &env.treasury,
+        &env.swap,
+        &user.pubkey(),
+        &repost_mint_key,
+        1,
+    );
+    process(&mut ctx, ix, &[&user, &repost_mint]).await.unwrap();
+
+    let repost_nft = ctx
+        .banks_client
+        .get_account(get_associated_token_address(
+            &user.pubkey(),
+            &repost_mint_key,
+        ))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(TokenAccount::unpack(&repost_nft.data).unwrap().amount, 1);
+
+    let (repost_record, _) = repost_record!(id, repost_mint_key);
+    let record = ctx
+        .banks_client
+        .get_account(repost_record)
+        .await
+        .unwrap()
+        .unwrap();
+    let record = RepostRecord::deserialize_from(&record.data).unwrap();
+    assert_eq!(record.user, user.pubkey());
+    assert_eq!(record.payer, user.pubkey());
+    assert!(record.receive_amount > 0 && record.receive_amount <= VAULT_AMOUNT);
+
+    // redeemable on the thursday after the cooldown
+    let week_start = clock.unix_timestamp - (clock.unix_timestamp - 345600).rem_euclid(604800);
+    ctx.set_sysvar(&Clock {
+        unix_timestamp: week_start + 604800 + 3 * 86400 + 43200,
+        ..clock
+    });
+
+    let ix =
+        instruction::redeem_repost(id, token, &repost_mint_key, &user.pubkey(), &user.pubkey());
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let user_wallet = ctx
+        .banks_client
+        .get_account(get_associated_token_address(&user.pubkey(), token))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        TokenAccount::unpack(&user_wallet.data).unwrap().amount,
+        record.receive_amount
+    );
+    assert!(ctx
+        .banks_client
+        .get_account(repost_record)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_compressed_repost() {
+    let env = Env::new();
+    let id = &env.program_id;
+    let token = &env.token;
+    let post_id = [8; 32];
+
+    // tree is allocated beforehand, it is too large to be created through CPI
+    let tree = Pubkey::from_str("tree9kmh23Qwa9K8sZ9rQtYshSwKA85CTEvw5bvTrau").unwrap();
+    let mut pt = env.program_test();
+    pt.add_account(tree, account(spl_account_compression::ID, vec![0; 1561592]));
+
+    let mut ctx = pt.start_with_context().await;
+    let payer = ctx.payer.pubkey();
+    let admin = env.admin.pubkey();
+
+    let init = instruction::init(id, token, &payer, env.init_args(admin));
+    process(&mut ctx, init, &[]).await.unwrap();
+
+    let ix = instruction::initialize_tree(id, &payer);
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let (tree_authority, _) = Pubkey::find_program_address(&[tree.as_ref()], &mpl_bubblegum::ID);
+    let tree_config = ctx
+        .banks_client
+        .get_account(tree_authority)
+        .await
+        .unwrap()
+        .unwrap();
+    let tree_config =
+        mpl_bubblegum::state::TreeConfig::try_deserialize(&mut tree_config.data.as_slice())
+            .unwrap();
+    assert_eq!(tree_config.tree_creator, authority!(id).0);
+    assert_eq!(tree_config.num_minted, 0);
+
+    let clock: Clock = ctx.banks_client.get_sysvar().await.unwrap();
+    let args = RegisterPostInstruction {
+        royalty_addr: Pubkey::new_unique(),
+        post_id,
+        created_at: clock.unix_timestamp,
+        post_name: "post".to_string(),
+        post_metadata_uri: "https://example.com".to_string(),
+        collection_name: "collection".to_string(),
+        collection_metadata_uri: "https://example.com".to_string(),
+        symbol: "HMN".to_string(),
+        repost_price: 0,
+    };
+    let ix = instruction::register_post(id, token, &admin, &payer, args);
+    process(&mut ctx, ix, &[&env.admin]).await.unwrap();
+
+    let user = Pubkey::new_unique();
+    let repost_mint = Pubkey::new_unique();
+    let ix = instruction::repost_compressed(id, token, &post_id, &user, &payer, &repost_mint);
+    process(&mut ctx, ix, &[]).await.unwrap();
+
+    let (repost_record, _) = repost_record!(id, repost_mint);
+    let record = ctx
+        .banks_client
+        .get_account(repost_record)
+        .await
+        .unwrap()
+        .unwrap();
+    let record = RepostRecord::deserialize_from(&record.data).unwrap();
+    assert_eq!(record.user, user);
+    assert_eq!(record.payer, payer);
+    assert_eq!(record.receive_amount, FREE_REPOST_RECEIVE_AMOUNT);
+
+    let user_wallet = ctx
+        .banks_client
+        .get_account(get_associated_token_address(&user, token))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(TokenAccount::unpack(&user_wallet.data).unwrap().owner, user);
+
+    let tree_config = ctx
+        .banks_client
+        .get_account(tree_authority)
+        .await
+        .unwrap()
+        .unwrap();
+    let tree_config =
+        mpl_bubblegum::state::TreeConfig::try_deserialize(&mut tree_config.data.as_slice())
+            .unwrap();
+    assert_eq!(tree_config.num_minted, 1);
+}
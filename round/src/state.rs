@@ -1,6 +1,6 @@
 use crate::error::RoundError;
 use anchor_lang::{prelude::*, solana_program::clock::UnixTimestamp};
-use spl_math::precise_number::PreciseNumber;
+use checked_math::mul_div_floor;
 
 #[account]
 #[derive(Default)]
@@ -210,16 +210,8 @@ pub fn calculate_redeem_amount(
 ) -> Option<u64> {
     let bid = target_bid.max(bid_balance);
 
-    let target_bid = PreciseNumber::new(bid as u128)?;
-    let user_bid = PreciseNumber::new(user_bid as u128)?;
-    let total = PreciseNumber::new(total_offer as u128)?;
-
-    user_bid
-        .checked_div(&target_bid)?
-        .checked_mul(&total)?
-        .to_imprecise()?
-        .try_into()
-        .ok()
+    // user_bid / bid * total_offer
+    mul_div_floor(user_bid, total_offer, bid)
 }
 
 #[account]
@@ -299,7 +291,8 @@ mod tests {
                 target_bid: 5_500,
                 user_bid: 899,
                 total_offer: 500,
-                expected: 18,
+                // 17.98, rounded down so redeems never exceed the offer
+                expected: 17,
             },
         ];
 
@@ -463,5 +456,25 @@ mod tests {
 
             assert_eq!(round.assert_can_withdraw(now, sig), Ok(()));
         }
+
+        #[test]
+        fn proptest_redeem_amount(
+            target_bid: u64,
+            total_offer: u64,
+            bids in prop::collection::vec(0u64..u64::MAX / 32, 1..20),
+        ) {
+            let bid_balance: u64 = bids.iter().sum();
+            prop_assume!(target_bid.max(bid_balance) > 0);
+
+            let redeemed: u128 = bids
+                .iter()
+                .map(|bid| {
+                    calculate_redeem_amount(target_bid, bid_balance, *bid, total_offer).unwrap() as u128
+                })
+                .sum();
+
+            // can't redeem more than offered
+            assert!(redeemed <= total_offer as u128);
+        }
     }
 }
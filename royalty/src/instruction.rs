@@ -193,3 +193,26 @@ pub fn sync_weight_record(
         data: RoyaltyInstruction::SyncWeightRecord.try_to_vec().unwrap(),
     }
 }
+
+pub fn distribute(
+    program_id: &Pubkey,
+    state: &Pubkey,
+    owner: &Pubkey,
+    host: &Pubkey,
+    vouchers: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*state, false),
+        AccountMeta::new(*owner, false),
+        AccountMeta::new(*host, false),
+        AccountMeta::new_readonly(sysvar::instructions::ID, false),
+    ];
+
+    accounts.extend(vouchers.iter().map(|v| AccountMeta::new(*v, false)));
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: RoyaltyInstruction::Distribute.try_to_vec().unwrap(),
+    }
+}
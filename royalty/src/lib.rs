@@ -1,5 +1,4 @@
 #![forbid(unsafe_code)]
-#![cfg(not(feature = "no-entrypoint"))]
 #![deny(clippy::all)]
 #![deny(clippy::integer_arithmetic)]
 #![allow(clippy::too_many_arguments)]
@@ -10,19 +9,22 @@ use error::Error;
 
 pub mod state;
 use human_common::{
-    entity::{entity_from_acc, initialize_entity, next_entity, Entity},
+    entity::{
+        entity_from_acc, initialize_entity, next_entity, next_readonly_entity,
+        next_zero_copy_entity_mut, readonly_entity_from_acc, Entity,
+    },
+    error::Error as CommonError,
     utils::{next_expected_account, next_signer_account},
 };
 use spl_associated_token_account::get_associated_token_address;
 use spl_governance::state::token_owner_record;
-use state::{Distribution, State, Voucher};
+use state::{Distribution, State, StateKeys, Voucher};
 
 pub mod instruction;
 use instruction::{InitializeArgs, RoyaltyInstruction};
 
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
     log::sol_log,
     msg,
@@ -49,7 +51,8 @@ use spl_token::state as token_state;
 
 use human_common::utils::{next_atoken_wallet, next_expected_token_wallet};
 
-entrypoint!(process_instruction);
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -163,21 +166,12 @@ fn process_initialize(
         return Err(ProgramError::InvalidArgument);
     }
 
-    let state = State {
-        wallet: derived_wallet,
-        owner: args.owner,
-        host: args.host,
-        settings: args.settings,
-        drop_idx: 0,
-        total_distributed: 0,
-        total_user_distributed: 0,
-        vouchers_count: 0,
-        tokens_held: 0,
-        distribution: None,
+    let keys = StateKeys {
+        token_mint: *mint.key,
         realm_addr: args.realm_addr,
         vault_addr: args.vault_addr,
-        token_mint: *mint.key,
     };
+    let state = State::new(derived_wallet, args.owner, args.host, args.settings, keys);
 
     initialize_entity(state, state_acc)?;
 
@@ -212,14 +206,15 @@ fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -
     let account_info_iter = &mut accounts.iter();
 
     let (mut state, state_acc) = next_entity::<_, State>(account_info_iter, program_id)?;
+    let keys = state.keys();
     let wallet = next_expected_token_wallet(account_info_iter, &state.wallet)?;
     let voucher_acc = next_account_info(account_info_iter)?;
     let user = next_account_info(account_info_iter)?;
     let (user_wallet_addr, _user_wallet) =
         next_atoken_wallet(account_info_iter, user.key, &wallet.mint)?;
 
-    let vault = next_expected_token_wallet(account_info_iter, &state.vault_addr)?;
-    let owner_atoken_addr = get_associated_token_address(&state.owner, &state.token_mint);
+    let vault = next_expected_token_wallet(account_info_iter, &keys.vault_addr)?;
+    let owner_atoken_addr = get_associated_token_address(&state.owner, &keys.token_mint);
     let owner_atoken = next_expected_token_wallet(account_info_iter, &owner_atoken_addr)?;
 
     let max_weight_record = next_account_info(account_info_iter)?;
@@ -262,7 +257,7 @@ fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -
         return Err(ProgramError::InvalidArgument);
     }
 
-    if state.distribution.is_some() {
+    if state.distribution().is_some() {
         return Error::TemporaryUnavailable.into();
     }
 
@@ -328,14 +323,15 @@ fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -
     update_max_voter_weight(
         program_id,
         state_acc.key,
-        &state.realm_addr,
+        &keys.realm_addr,
         &wallet.mint,
         max_vote_weight,
         max_weight_record,
         funder,
     )?;
 
-    Ok(())
+    voucher.commit()?;
+    state.commit()
 }
 
 // [write] state
@@ -355,6 +351,7 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64)
     let account_info_iter = &mut accounts.iter();
 
     let (mut state, state_acc) = next_entity::<_, State>(account_info_iter, program_id)?;
+    let keys = state.keys();
     let wallet = next_expected_token_wallet(account_info_iter, &state.wallet)?;
     let (mut voucher, _voucher_acc) = next_entity::<_, Voucher>(account_info_iter, program_id)?;
 
@@ -365,8 +362,8 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64)
     let voter_record = next_account_info(account_info_iter)?; // TODO
     let vote_weight_record = next_account_info(account_info_iter)?;
 
-    let vault = next_expected_token_wallet(account_info_iter, &state.vault_addr)?;
-    let owner_atoken_addr = get_associated_token_address(&state.owner, &state.token_mint);
+    let vault = next_expected_token_wallet(account_info_iter, &keys.vault_addr)?;
+    let owner_atoken_addr = get_associated_token_address(&state.owner, &keys.token_mint);
     let owner_atoken = next_expected_token_wallet(account_info_iter, &owner_atoken_addr)?;
 
     let max_weight_record = next_account_info(account_info_iter)?;
@@ -377,12 +374,12 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64)
 
     assert_no_unrequilished_votes(
         voter_record,
-        &state.realm_addr,
-        &state.token_mint,
+        &keys.realm_addr,
+        &keys.token_mint,
         &voucher.user,
     )?;
 
-    if state.distribution.is_some() {
+    if state.distribution().is_some() {
         return Error::TemporaryUnavailable.into();
     }
 
@@ -424,7 +421,7 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64)
     update_voter_weight(
         program_id,
         state_acc.key,
-        &state.realm_addr,
+        &keys.realm_addr,
         &wallet.mint,
         &voucher.user,
         voucher.balance,
@@ -435,14 +432,15 @@ fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64)
     update_max_voter_weight(
         program_id,
         state_acc.key,
-        &state.realm_addr,
+        &keys.realm_addr,
         &wallet.mint,
         max_vote_weight,
         max_weight_record,
         funder,
     )?;
 
-    Ok(())
+    voucher.commit()?;
+    state.commit()
 }
 
 pub mod governance_program {
@@ -534,7 +532,8 @@ fn check_no_other_programs(acc: &AccountInfo, allowed_programs: &[&Pubkey]) -> P
 fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter().peekable();
 
-    let (mut state, state_acc) = next_entity::<_, State>(account_info_iter, program_id)?;
+    let (mut state, state_acc) =
+        next_zero_copy_entity_mut::<_, State>(account_info_iter, program_id)?;
     let owner = next_expected_account(account_info_iter, &state.owner)?;
     let host = next_expected_account(account_info_iter, &state.host)?;
     let instructions = next_expected_account(account_info_iter, &instructions::ID)?;
@@ -551,7 +550,7 @@ fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramR
 
     let mut state_lamports = state_acc.try_borrow_mut_lamports()?;
 
-    if state.distribution.is_none() {
+    if state.distribution().is_none() {
         msg!("starting distribution");
         // before starting distribution:
         // 1. check if there is enough balance
@@ -575,33 +574,37 @@ fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramR
         // just to be sure we don't give more than we have after rounding
         let amount_to_distribute = excess_balance.checked_sub(1).unwrap();
 
-        let split = state
-            .settings
-            .calculate_split(amount_to_distribute, state.vouchers_count)
+        let settings = state.settings;
+        let split = settings
+            .split_plan(
+                &state.owner,
+                &state.host,
+                state_acc.key,
+                state.vouchers_count,
+            )
+            .and_then(|plan| plan.split(amount_to_distribute))
             .ok_or(Error::Overflow)?;
 
-        {
-            let mut owner_lamports = owner.try_borrow_mut_lamports()?;
+        let mut distribute_amount: u64 = 0;
 
-            **state_lamports = state_lamports
-                .checked_sub(split.owner_comission)
-                .ok_or(Error::Overflow)?;
+        for (recipient, amount) in split {
+            if recipient == *state_acc.key {
+                // stays on the state account for vouchers, owner or host may be the state too
+                distribute_amount = distribute_amount
+                    .checked_add(amount)
+                    .ok_or(Error::Overflow)?;
+                continue;
+            }
 
-            **owner_lamports = owner_lamports
-                .checked_add(split.owner_comission)
-                .ok_or(Error::Overflow)?;
-        }
-
-        {
-            let mut host_lamports = host.try_borrow_mut_lamports()?;
+            let acc = [owner, host]
+                .into_iter()
+                .find(|acc| *acc.key == recipient)
+                .ok_or(CommonError::UnexpectedAccount)?;
+            let mut acc_lamports = acc.try_borrow_mut_lamports()?;
 
-            **state_lamports = state_lamports
-                .checked_sub(split.host_comission)
-                .ok_or(Error::Overflow)?;
+            **state_lamports = state_lamports.checked_sub(amount).ok_or(Error::Overflow)?;
 
-            **host_lamports = host_lamports
-                .checked_add(split.host_comission)
-                .ok_or(Error::Overflow)?;
+            **acc_lamports = acc_lamports.checked_add(amount).ok_or(Error::Overflow)?;
         }
 
         state.total_distributed = state
@@ -611,19 +614,19 @@ fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramR
 
         state.total_user_distributed = state
             .total_user_distributed
-            .checked_add(split.distribute_amount)
+            .checked_add(distribute_amount)
             .ok_or(Error::Overflow)?;
 
-        state.distribution = Some(Distribution {
-            distribute_amount: split.distribute_amount,
+        state.set_distribution(Some(Distribution {
+            distribute_amount,
             seen_vouchers: 0,
-        })
+        }));
     }
 
     msg!("event-token");
-    state.token_mint.log();
+    state.keys().token_mint.log();
 
-    let mut dist_state = state.distribution.as_ref().cloned().unwrap();
+    let mut dist_state = state.distribution().unwrap();
 
     while account_info_iter.peek().is_some() {
         let (mut voucher, voucher_acc) = next_entity::<_, Voucher>(account_info_iter, program_id)?;
@@ -644,6 +647,8 @@ fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramR
         **voucher_lamports = voucher_lamports
             .checked_add(to_send)
             .ok_or(Error::Overflow)?;
+
+        voucher.commit()?;
     }
 
     // sanity check. could be removed when feature is activated on mainnet
@@ -659,11 +664,11 @@ fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramR
         msg!("{}", dist_state.distribute_amount);
 
         state.drop_idx = state.drop_idx.checked_add(1).ok_or(Error::Overflow)?;
-        state.distribution = None;
+        state.set_distribution(None);
         return Ok(());
     }
 
-    state.distribution = Some(dist_state);
+    state.set_distribution(Some(dist_state));
 
     Ok(())
 }
@@ -674,8 +679,8 @@ fn process_distribute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramR
 fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter().peekable();
 
-    let (state, state_acc) = next_entity::<_, State>(account_info_iter, program_id)?;
-    let (voucher, voucher_acc) = next_entity::<_, Voucher>(account_info_iter, program_id)?;
+    let (state, state_acc) = next_readonly_entity::<_, State>(account_info_iter, program_id)?;
+    let (voucher, voucher_acc) = next_readonly_entity::<_, Voucher>(account_info_iter, program_id)?;
     let user = next_signer_account(account_info_iter, &voucher.user)?;
 
     if voucher.state != *state_acc.key {
@@ -720,14 +725,15 @@ fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
 // [] sysprog
 fn process_sync_weight_record(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
-    let (state, state_acc) = next_entity::<_, State>(account_info_iter, program_id)?;
+    let (state, state_acc) = next_readonly_entity::<_, State>(account_info_iter, program_id)?;
+    let keys = state.keys();
 
     let user = next_account_info(account_info_iter)?;
     let voucher_acc = next_account_info(account_info_iter)?;
     let record_acc = next_account_info(account_info_iter)?;
 
-    let vault = next_expected_token_wallet(account_info_iter, &state.vault_addr)?;
-    let atoken_addr = get_associated_token_address(&state.owner, &state.token_mint);
+    let vault = next_expected_token_wallet(account_info_iter, &keys.vault_addr)?;
+    let atoken_addr = get_associated_token_address(&state.owner, &keys.token_mint);
     let owner_atoken = next_expected_token_wallet(account_info_iter, &atoken_addr)?;
     let max_record_acc = next_account_info(account_info_iter)?;
 
@@ -740,7 +746,7 @@ fn process_sync_weight_record(program_id: &Pubkey, accounts: &[AccountInfo]) ->
             .checked_add(vault.amount)
             .ok_or(Error::Overflow)?
     } else {
-        let voucher = entity_from_acc::<Voucher>(voucher_acc, program_id)?;
+        let voucher = readonly_entity_from_acc::<Voucher>(voucher_acc, program_id)?;
 
         if voucher.user != *user.key {
             msg!("voucher belongs to another user");
@@ -753,8 +759,8 @@ fn process_sync_weight_record(program_id: &Pubkey, accounts: &[AccountInfo]) ->
     update_voter_weight(
         program_id,
         state_acc.key,
-        &state.realm_addr,
-        &state.token_mint,
+        &keys.realm_addr,
+        &keys.token_mint,
         user.key,
         balance,
         record_acc,
@@ -771,8 +777,8 @@ fn process_sync_weight_record(program_id: &Pubkey, accounts: &[AccountInfo]) ->
     update_max_voter_weight(
         program_id,
         state_acc.key,
-        &state.realm_addr,
-        &state.token_mint,
+        &keys.realm_addr,
+        &keys.token_mint,
         max_vote_weight,
         max_record_acc,
         funder,
@@ -884,16 +890,18 @@ fn process_migrate(
 
     let wallet = next_expected_token_wallet(account_info_iter, &state.wallet)?;
 
-    if state.token_mint != Pubkey::default() {
+    if state.keys().token_mint != Pubkey::default() {
         msg!("already migrated");
-        return Ok(());
+        return state.commit();
     }
 
-    state.realm_addr = *realm_addr;
-    state.vault_addr = *vault_addr;
-    state.token_mint = wallet.mint;
+    state.set_keys(StateKeys {
+        token_mint: wallet.mint,
+        realm_addr: *realm_addr,
+        vault_addr: *vault_addr,
+    });
 
-    Ok(())
+    state.commit()
 }
 
 #[macro_export]
@@ -1,16 +1,23 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use checked_math::{mul_div_floor, Bps};
+use human_common::split::{Remainder, SplitPlan};
 use solana_program::{
     clock::{Clock, UnixTimestamp},
     program_error::ProgramError,
     pubkey::Pubkey,
     sysvar::Sysvar,
 };
-use spl_math::precise_number::PreciseNumber;
 
 use crate::error::Error;
-use human_common::entity::Entity;
-
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
+use bytemuck::{Pod, Zeroable};
+use human_common::entity::{Entity, ZeroCopyEntity};
+use human_common::impl_borsh_for_pod;
+use std::mem::size_of;
+
+/// Borsh layout, also accessed in place (see `ZeroCopyEntity`).
+/// Keys after `distribution` move when it is set, so both live in `tail`
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
 #[must_use]
 pub struct State {
     // token wallet associated with this state
@@ -41,9 +48,25 @@ pub struct State {
     /// these tokens will also reduce everyone's share
     pub tokens_held: u64,
 
-    /// current distribution
-    pub distribution: Option<Distribution>,
+    /// borsh encoded `Option<Distribution>` followed by `StateKeys`.
+    /// Use `distribution`, `keys` and their setters to access it
+    tail: [u8; 128],
+}
+
+impl_borsh_for_pod!(State, Settings);
 
+impl Entity for State {
+    // Hardcoded size to allow for future migrations
+    const SIZE: usize = 512;
+    const MAGIC: u8 = 0x77;
+}
+
+impl ZeroCopyEntity for State {}
+
+/// Addresses stored after the distribution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct StateKeys {
     /// Staking token mint
     pub token_mint: Pubkey,
 
@@ -54,20 +77,81 @@ pub struct State {
     pub vault_addr: Pubkey,
 }
 
-impl Entity for State {
-    // Hardcoded size to allow for future migrations
-    const SIZE: usize = 512;
-    const MAGIC: u8 = 0x77;
-}
+const DISTRIBUTION_LEN: usize = 12;
+const KEYS_LEN: usize = size_of::<StateKeys>();
+
+impl State {
+    pub fn new(
+        wallet: Pubkey,
+        owner: Pubkey,
+        host: Pubkey,
+        settings: Settings,
+        keys: StateKeys,
+    ) -> Self {
+        let mut state = Self {
+            wallet,
+            owner,
+            host,
+            settings,
+            ..Self::zeroed()
+        };
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Split {
-    pub distribute_amount: u64,
-    pub owner_comission: u64,
-    pub host_comission: u64,
+        state.write_tail(None, keys);
+        state
+    }
+
+    pub fn distribution(&self) -> Option<Distribution> {
+        if self.tail[0] == 0 {
+            return None;
+        }
+
+        let data = &self.tail[1..1 + DISTRIBUTION_LEN];
+
+        Some(Distribution {
+            distribute_amount: bytemuck::pod_read_unaligned(&data[..8]),
+            seen_vouchers: bytemuck::pod_read_unaligned(&data[8..]),
+        })
+    }
+
+    pub fn set_distribution(&mut self, distribution: Option<Distribution>) {
+        let keys = self.keys();
+        self.write_tail(distribution, keys);
+    }
+
+    pub fn keys(&self) -> StateKeys {
+        let keys = match self.tail[0] {
+            0 => &self.tail[1..][..KEYS_LEN],
+            _ => &self.tail[1 + DISTRIBUTION_LEN..][..KEYS_LEN],
+        };
+
+        bytemuck::pod_read_unaligned(keys)
+    }
+
+    pub fn set_keys(&mut self, keys: StateKeys) {
+        let distribution = self.distribution();
+        self.write_tail(distribution, keys);
+    }
+
+    fn write_tail(&mut self, distribution: Option<Distribution>, keys: StateKeys) {
+        let mut tail = [0; 128];
+
+        let keys_slot = match distribution {
+            Some(d) => {
+                tail[0] = 1;
+                tail[1..9].copy_from_slice(&d.distribute_amount.to_le_bytes());
+                tail[9..13].copy_from_slice(&d.seen_vouchers.to_le_bytes());
+                &mut tail[1 + DISTRIBUTION_LEN..][..KEYS_LEN]
+            }
+            None => &mut tail[1..][..KEYS_LEN],
+        };
+
+        keys_slot.copy_from_slice(bytemuck::bytes_of(&keys));
+        self.tail = tail;
+    }
 }
 
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
 pub struct Settings {
     /// minimum amount of tokens for user
     pub min_token_to_enroll: u64,
@@ -89,47 +173,27 @@ impl Settings {
         true
     }
 
-    pub fn calculate_split(&self, balance: u64, num_vouchers: u32) -> Option<Split> {
-        let bsp: PreciseNumber = PreciseNumber::new(10_000).unwrap();
-
-        let balance_precise = PreciseNumber::new(balance as u128)?;
-        let owner_fee = PreciseNumber::new(self.owner_fee as u128)?;
-        let host_fee = PreciseNumber::new(self.host_fee as u128)?;
+    /// owner and host take their comission, the rest stays in the pool (state account)
+    /// and is distributed to vouchers
+    pub fn split_plan(
+        &self,
+        owner: &Pubkey,
+        host: &Pubkey,
+        pool: &Pubkey,
+        num_vouchers: u32,
+    ) -> Option<SplitPlan> {
+        let host_flat_fee = (self.host_flat_fee as u64).checked_mul(num_vouchers as u64)?;
 
         // balance * 1500/10000 if comission is 15%
-        let owner_split: u64 = balance_precise
-            .checked_mul(&owner_fee)?
-            .checked_div(&bsp)?
-            .to_imprecise()?
-            .try_into()
-            .ok()?;
-
-        dbg!(owner_split);
-
-        let host_split: u64 = balance_precise
-            .checked_mul(&host_fee)?
-            .checked_div(&bsp)?
-            .to_imprecise()?
-            .try_into()
-            .ok()?;
-
-        let host_flat_fee = self.host_flat_fee.checked_mul(num_vouchers)?;
-
-        let host_total_comission = host_split.checked_add(host_flat_fee as u64)?;
-
-        let remaining_balance = balance
-            .checked_sub(owner_split)?
-            .checked_sub(host_total_comission)?;
-
-        Some(Split {
-            distribute_amount: remaining_balance,
-            owner_comission: owner_split,
-            host_comission: host_total_comission,
-        })
+        Some(
+            SplitPlan::new(Remainder::To(*pool))
+                .with_share(*owner, Bps::try_new(self.owner_fee)?)
+                .with_fees(*host, Bps::try_new(self.host_fee)?, host_flat_fee),
+        )
     }
 }
 
-#[derive(Debug, BorshDeserialize, BorshSerialize, Clone)]
+#[derive(Debug, BorshDeserialize, BorshSerialize, Clone, PartialEq, Eq)]
 pub struct Distribution {
     pub distribute_amount: u64,
     pub seen_vouchers: u32,
@@ -150,28 +214,18 @@ impl Distribution {
         // two cases where vouches would not be valid for current drop:
         // 1. Voucher was created during distribution phase, so it has not been enumerated
         // 2. Voucher was already redeeemed (distributed to)
-        let balance = PreciseNumber::new(voucher.balance as u128).unwrap();
-        let total_tokens = PreciseNumber::new(total_tokens as u128).unwrap();
-        let distribute_amount = PreciseNumber::new(self.distribute_amount as u128).unwrap();
-
-        // TODO calculation precision
-        // e.g. 0.25 (25%) of total tokens
-        let owned_percentage = balance.checked_div(&total_tokens).ok_or(Error::Overflow)?;
-        assert!(owned_percentage.less_than_or_equal(&PreciseNumber::new(1).unwrap()));
-
-        // part of total tokens
-        let part = distribute_amount
-            .checked_mul(&owned_percentage)
+
+        // part of total tokens, e.g. 0.25 (25%) of distribution
+        // rounded down so sum of parts never exceeds distribute_amount
+        if voucher.balance > total_tokens {
+            return Error::Overflow.into();
+        }
+
+        let lamports = mul_div_floor(self.distribute_amount, voucher.balance, total_tokens)
             .ok_or(Error::Overflow)?;
 
         self.seen_vouchers = self.seen_vouchers.checked_add(1).ok_or(Error::Overflow)?;
 
-        let lamports = part
-            .to_imprecise()
-            .ok_or(Error::Overflow)?
-            .try_into()
-            .map_err(|_| Error::Overflow)?;
-
         voucher.drop_idx = voucher.drop_idx.checked_add(1).ok_or(Error::Overflow)?;
         voucher.last_distribution = Clock::get()?.unix_timestamp;
 
@@ -209,23 +263,22 @@ mod tests {
             host_flat_fee: 5000,
         };
 
+        let (owner, host, pool) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let plan = s.split_plan(&owner, &host, &pool, 5).unwrap();
+
         assert_eq!(
-            s.calculate_split(1_000_000, 5).unwrap(),
-            Split {
-                owner_comission: 100_000,
-                host_comission: 50000 + 25000,
-                distribute_amount: 825_000,
-            }
+            plan.split(1_000_000).unwrap(),
+            vec![(owner, 100_000), (host, 50000 + 25000), (pool, 825_000)]
         );
 
         // make sure weird values are calculated in a sane way
         assert_eq!(
-            s.calculate_split(3_333_333, 5).unwrap(),
-            Split {
-                owner_comission: 333333,
-                host_comission: 166667 + 25000,
-                distribute_amount: 2808333,
-            }
+            plan.split(3_333_333).unwrap(),
+            vec![(owner, 333333), (host, 166666 + 25000), (pool, 2808334)]
         );
     }
 
@@ -299,6 +352,75 @@ mod tests {
         assert_eq!(ds.seen_vouchers, 3);
     }
 
+    #[test]
+    fn test_state_layout() {
+        let settings = Settings {
+            min_token_to_enroll: 1,
+            owner_fee: 2,
+            host_fee: 3,
+            host_flat_fee: 4,
+        };
+        let keys = StateKeys {
+            token_mint: Pubkey::new_unique(),
+            realm_addr: Pubkey::new_unique(),
+            vault_addr: Pubkey::new_unique(),
+        };
+
+        let mut state = State::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            settings,
+            keys,
+        );
+        state.total_distributed = 100;
+        state.total_user_distributed = 90;
+        state.drop_idx = 5;
+        state.vouchers_count = 6;
+        state.tokens_held = 7;
+
+        let distributions = [
+            None,
+            Some(Distribution {
+                distribute_amount: 8,
+                seen_vouchers: 9,
+            }),
+            None,
+        ];
+
+        for distribution in distributions {
+            state.set_distribution(distribution.clone());
+            assert_eq!(state.distribution(), distribution);
+            assert_eq!(state.keys(), keys);
+
+            // borsh encoding of the state before it was accessed in place
+            let borsh = (
+                (
+                    state.wallet,
+                    state.owner,
+                    state.host,
+                    100u64,
+                    90u64,
+                    settings,
+                ),
+                (5u32, 6u32, 7u64, distribution.clone()),
+                (keys.token_mint, keys.realm_addr, keys.vault_addr),
+            )
+                .try_to_vec()
+                .unwrap();
+
+            let mut data = vec![0; State::SIZE];
+            state.serialize_to(&mut data).unwrap();
+            assert_eq!(data[0], 0x77);
+            assert_eq!(data[1..1 + borsh.len()], borsh);
+
+            let view = State::from_bytes(&data).unwrap();
+            assert_eq!({ view.tokens_held }, 7);
+            assert_eq!(view.distribution(), distribution);
+            assert_eq!(view.keys(), keys);
+        }
+    }
+
     proptest! {
         #[test]
         fn proptest_calculate_split(
@@ -314,9 +436,12 @@ mod tests {
                 host_flat_fee: hff,
             };
 
-            let split = settings.calculate_split(lamports, 10).unwrap();
+            let plan = settings
+                .split_plan(&Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(), 10)
+                .unwrap();
+            let split = plan.split(lamports).unwrap();
 
-            assert_eq!(split.distribute_amount + split.host_comission + split.owner_comission, lamports)
+            assert_eq!(split.iter().map(|(_, amount)| amount).sum::<u64>(), lamports)
         }
 
         #[test]
@@ -0,0 +1,150 @@
+// Compute unit benchmark for process_distribute.
+// Needs compiled program: cargo test-bpf --features test-bpf -- --nocapture
+#![cfg(feature = "test-bpf")]
+
+use std::str::FromStr;
+
+use human_common::entity::Entity;
+use solana_program::{
+    instruction::Instruction, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, rent::Rent,
+};
+
+use solana_sdk::account::Account;
+
+use human_royalty::{
+    instruction::distribute,
+    state::{Settings, State, StateKeys, Voucher},
+};
+
+use {
+    solana_program_test::*,
+    solana_sdk::{signature::Signer, transaction::Transaction},
+};
+
+const VOUCHERS: usize = 10;
+
+struct Bench {
+    program_id: Pubkey,
+    owner: Pubkey,
+    host: Pubkey,
+    state: Pubkey,
+    vouchers: Vec<Pubkey>,
+}
+
+fn entity_account<T: Entity>(ent: &T, program_id: &Pubkey, lamports: u64) -> Account {
+    let mut data = vec![0; T::SIZE];
+    ent.serialize_to(&mut data).unwrap();
+
+    Account {
+        lamports,
+        data,
+        owner: *program_id,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+impl Bench {
+    fn new() -> Self {
+        Self {
+            program_id: Pubkey::from_str("22222222222222222222222222222222222222222222").unwrap(),
+            owner: Pubkey::new_unique(),
+            host: Pubkey::new_unique(),
+            state: Pubkey::new_unique(),
+            vouchers: (0..VOUCHERS).map(|_| Pubkey::new_unique()).collect(),
+        }
+    }
+
+    /// program test with an already current state account
+    fn program_test(&self) -> ProgramTest {
+        let mut pt = ProgramTest::default();
+        pt.prefer_bpf(true);
+        pt.add_program("human_royalty_contract", self.program_id, None);
+
+        let rent = Rent::default();
+
+        for acc in [&self.owner, &self.host] {
+            pt.add_account(
+                *acc,
+                Account::new(LAMPORTS_PER_SOL, 0, &solana_program::system_program::ID),
+            );
+        }
+
+        let settings = Settings {
+            min_token_to_enroll: 0,
+            owner_fee: 0,
+            host_fee: 250,
+            host_flat_fee: 0,
+        };
+        let keys = StateKeys {
+            token_mint: Pubkey::new_unique(),
+            realm_addr: Pubkey::new_unique(),
+            vault_addr: Pubkey::new_unique(),
+        };
+        let mut state = State::new(Pubkey::new_unique(), self.owner, self.host, settings, keys);
+        state.vouchers_count = VOUCHERS as u32;
+        state.tokens_held = VOUCHERS as u64 * 1000;
+
+        pt.add_account(
+            self.state,
+            entity_account(
+                &state,
+                &self.program_id,
+                rent.minimum_balance(State::SIZE) + LAMPORTS_PER_SOL,
+            ),
+        );
+
+        for key in &self.vouchers {
+            let voucher = Voucher {
+                user: Pubkey::new_unique(),
+                state: self.state,
+                drop_idx: 0,
+                balance: 1000,
+                last_distribution: 0,
+            };
+            pt.add_account(
+                *key,
+                entity_account(
+                    &voucher,
+                    &self.program_id,
+                    rent.minimum_balance(Voucher::SIZE),
+                ),
+            );
+        }
+
+        pt
+    }
+
+    /// runs instruction on a fresh bank and returns consumed units
+    async fn measure(&self, instruction: Instruction) -> u64 {
+        let (mut banks_client, payer, recent_blockhash) = self.program_test().start().await;
+
+        let mut tx = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+
+        let result = banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .unwrap();
+
+        result.result.unwrap();
+        result.metadata.unwrap().compute_units_consumed
+    }
+}
+
+#[tokio::test]
+async fn bench_distribute() {
+    let bench = Bench::new();
+
+    let units = bench
+        .measure(distribute(
+            &bench.program_id,
+            &bench.state,
+            &bench.owner,
+            &bench.host,
+            &bench.vouchers,
+        ))
+        .await;
+
+    println!("distribute to {VOUCHERS} vouchers: {units} CU");
+}
@@ -0,0 +1,119 @@
+//! Account fixtures shared by program tests, included with
+//! `#[path = "../../tests/common/mod.rs"] #[macro_use] mod common;`
+#![allow(dead_code, unused_macros)]
+
+use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_sdk::account::Account;
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use spl_token_swap::{
+    curve::{base::SwapCurve, fees::Fees},
+    state::{SwapV1, SwapVersion},
+};
+
+/// Rent exempt account of `owner`
+pub fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner,
+        ..Account::default()
+    }
+}
+
+pub fn mint(supply: u64) -> Account {
+    let mut data = vec![0; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::None,
+            supply,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut data,
+    )
+    .unwrap();
+
+    account(spl_token::ID, data)
+}
+
+pub fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+
+    account(spl_token::ID, data)
+}
+
+/// Swap pool of `token_a_mint` and wSOL, owned by `swap_program`
+pub fn swap(
+    swap_program: &Pubkey,
+    token_a_mint: &Pubkey,
+    token_a: &Pubkey,
+    token_b: &Pubkey,
+    pool_mint: &Pubkey,
+) -> Account {
+    let mut data = vec![0; SwapVersion::LATEST_LEN];
+    SwapVersion::pack(
+        SwapVersion::SwapV1(SwapV1 {
+            is_initialized: true,
+            nonce: 0,
+            token_program_id: spl_token::ID,
+            token_a: *token_a,
+            token_b: *token_b,
+            pool_mint: *pool_mint,
+            token_a_mint: *token_a_mint,
+            token_b_mint: spl_token::native_mint::ID,
+            pool_fee_account: Pubkey::new_unique(),
+            fees: Fees::default(),
+            swap_curve: SwapCurve::default(),
+        }),
+        &mut data,
+    )
+    .unwrap();
+
+    account(*swap_program, data)
+}
+
+/// Anchor and metadata processors take accounts living as long as their infos.
+/// Metadata also serializes through `&mut *data`, advancing the account data slice. Onchain
+/// the runtime reads lengths back from the input buffer, so the slices are restored from it here
+macro_rules! leaked_processor {
+    ($process:path) => {{
+        fn process(
+            program_id: &solana_program::pubkey::Pubkey,
+            accounts: &[solana_program::account_info::AccountInfo],
+            data: &[u8],
+        ) -> solana_program::entrypoint::ProgramResult {
+            let program_id = Box::leak(Box::new(*program_id));
+            let accounts = Box::leak(accounts.to_vec().into_boxed_slice());
+            let starts: Vec<*mut u8> = accounts
+                .iter()
+                .map(|account| account.data.borrow_mut().as_mut_ptr())
+                .collect();
+
+            let result = $process(program_id, accounts, data);
+
+            for (account, start) in accounts.iter().zip(starts) {
+                // the input buffer keeps data length right before the data, realloc updates it
+                unsafe {
+                    let len = *(start.offset(-8) as *const u64) as usize;
+                    *account.data.borrow_mut() = std::slice::from_raw_parts_mut(start, len);
+                }
+            }
+
+            result
+        }
+
+        solana_program_test::processor!(process)
+    }};
+}